@@ -0,0 +1,76 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use vim_editor::window::Window;
+
+/// 確保中のヒープの大きさを数えるアロケータ（undo 履歴のメモリ使用量の確認用）
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const LINES: usize = 50_000;
+/// 上限まで積んだ undo 履歴に許すメモリ量
+const HISTORY_LIMIT_BYTES: usize = 256 * 1024;
+
+fn large_window() -> Window {
+    let mut window = Window::new(None);
+    *window.buffer_mut() = (0..LINES).map(|i| format!("    let value_{} = compute({});", i, i)).collect();
+    window
+}
+
+/// 離れた行を1文字ずつ `edits` 回書き換える
+fn edit_lines(window: &mut Window, edits: usize) {
+    for i in 0..edits {
+        *window.cursor_y_mut() = (i * 7919) % LINES;
+        *window.cursor_x_mut() = 4;
+        window.replace_char('x');
+    }
+}
+
+fn benchmark_undo_memory(c: &mut Criterion) {
+    // 履歴が上限 (100) に達した後は、編集を続けてもメモリが増えないことを確認する
+    let mut window = large_window();
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+    edit_lines(&mut window, 1_000);
+    let capped = ALLOCATED.load(Ordering::Relaxed);
+    edit_lines(&mut window, 5_000);
+    let after = ALLOCATED.load(Ordering::Relaxed);
+    // 履歴は変更のあった行だけを持つので、バッファ全体の複製よりずっと小さい
+    assert!(capped - baseline < HISTORY_LIMIT_BYTES, "undo history uses {} bytes", capped - baseline);
+    assert!(after <= capped + HISTORY_LIMIT_BYTES / 16, "undo history grew from {} to {} bytes", capped - baseline, after - baseline);
+
+    c.bench_function("edit_50k_lines_with_undo", |b| {
+        let mut window = large_window();
+        b.iter(|| edit_lines(black_box(&mut window), 10))
+    });
+
+    c.bench_function("undo_redo_50k_lines", |b| {
+        let mut window = large_window();
+        edit_lines(&mut window, 100);
+        b.iter(|| {
+            while window.undo() {}
+            while window.redo() {}
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_undo_memory);
+criterion_main!(benches);
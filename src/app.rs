@@ -1,18 +1,23 @@
-use crate::{app_config::{AppConfigManager, ConfigManager}, config::Config, pane::PaneManager, utils, window::Window,};
+use crate::{app_config::{AppConfigManager, ConfigManager}, config::{Config, LineNumberMode}, constants::editor::MAX_COMPLETIONS, dictionary::Dictionary, spell, pane::PaneManager, positions, registers::{self, Registers}, substitute::Substitute, utils, window::{CharSearch, IndentStyle, Motion, Window, YankKind},};
 use arboard::Clipboard;
-use std::{env, path::PathBuf};
+use regex::Regex;
+use std::{env, path::PathBuf, time::{Duration, Instant}};
 use tokio::sync::mpsc::{Receiver, Sender};
 use unicode_segmentation::UnicodeSegmentation;
 
 pub use crate::window::Mode;
 
+/// 引数のパスを Tab で補完するコマンド
+const PATH_COMMANDS: [&str; 5] = ["e ", "w ", "saveas ", "vs ", "sp "];
+
 pub struct App {
     pub windows: Vec<Window>,
     pub pane_manager: PaneManager,
     pub mode: Mode,
     pub command_buffer: String,
     pub status_message: String,
-    clipboard: Clipboard,
+    /// システムのクリップボード（使えない環境では None で、無名レジスタだけを使う）
+    clipboard: Option<Clipboard>,
     pub current_path: PathBuf,
     pub directory_files: Vec<String>,
     pub selected_directory_index: usize,
@@ -21,7 +26,12 @@ pub struct App {
     pub config: Config,
     pub show_completion: bool,
     pub completions: Vec<String>,
-    pub selected_completion: usize,
+    /// 選択中の補完候補（入力中に自動で表示した候補は、選ぶまで何も選択しない）
+    pub selected_completion: Option<usize>,
+    /// コマンドラインでパスの候補を Tab で切り替えている間の、パスより前の部分（`e ` など）
+    pub command_completion_prefix: Option<String>,
+    /// 最後に補完候補を作ったときのカーソル直前の単語 (行, 開始位置, 単語)
+    pub completion_word: Option<(usize, usize, String)>,
     pub show_right_panel: bool,
     pub right_panel_input: String,
     pub right_panel_items: Vec<String>,
@@ -32,8 +42,94 @@ pub struct App {
     pub ai_response_receiver: Option<Receiver<String>>,
     pub ai_status: String,
     pub right_panel_input_cursor: usize,
+    pub pending_operator: Option<PendingOperator>,
+    pub pending_count: Option<usize>,
+    /// `g` などの2打鍵コマンドの1打目
+    pub pending_key: Option<char>,
+    /// エスケープシーケンスの続きを待っている Esc を押した時刻
+    pub pending_escape: Option<Instant>,
+    /// `"{a-z}` で指定した、次のヤンク・削除・貼り付けで使うレジスタ
+    pub selected_register: Option<char>,
+    pub registers: Registers,
+    /// 最後に検索した正規表現と、その一致位置 (x, y)
+    pub search_pattern: Option<Regex>,
+    pub search_matches: Vec<(usize, usize)>,
+    /// 最後の検索方向（`/` なら true、`?` なら false）
+    pub search_forward: bool,
+    /// ビジュアルモードから `:` で渡された行範囲（'<,'>）
+    pub visual_range: Option<(usize, usize)>,
+    pub pending_prompt: Option<Prompt>,
+    /// 文字ジャンプのラベルと移動先 (ラベル, x, y)。空でなければ次のキーでラベルを選ぶ
+    pub jump_labels: Vec<(char, usize, usize)>,
+    /// `.` で繰り返す直前の変更
+    pub last_change: Option<LastChange>,
+    /// `;` / `,` で繰り返す直前の f / F / t / T
+    pub last_char_search: Option<CharSearch>,
+    /// 現在の挿入モードへの入り方と、そこで入力したテキスト
+    pub insert_entry: Option<InsertEntry>,
+    pub inserted_text: String,
+    last_swap_write: Instant,
+    /// 表示位置がウィンドウに反映されているペイン（アクティブペインが変わったら入れ替える）
+    view_pane: usize,
+    /// 補完に使う単語リスト（起動時と設定の再読み込み時に読み込む）
+    dictionary: Dictionary,
+}
+
+/// ユーザーに y/n で確認を求めている内容
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Prompt {
+    RecoverSwap(usize),
+}
+
+/// ノーマルモードで次のキー入力を待っているオペレータ
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PendingOperator {
+    Delete,
+    Yank,
+    Change,
+}
+
+/// 挿入モードへの入り方（i / a / o / O / c）
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InsertEntry {
+    Insert,
+    Append,
+    /// o / O（`above` なら現在行の上）
+    OpenLine { above: bool },
+    /// cw / ce / c$（モーションと回数）
+    ChangeToMotion(Motion, usize),
+    /// cc（行数）
+    ChangeLines(usize),
 }
 
+/// `.` で繰り返すバッファの変更
+#[derive(Clone, Debug)]
+pub enum LastChange {
+    /// x / ビジュアルモードでの1行内の削除（削除した文字数）
+    DeleteChars(usize),
+    /// dd / V での削除（削除した行数）
+    DeleteLines(usize),
+    /// r（置き換えた文字）
+    ReplaceChar(char),
+    /// dw / de / d$
+    DeleteToMotion { motion: Motion, count: usize },
+    /// p / P
+    Paste { text: String, kind: YankKind, before: bool },
+    /// 挿入モードの開始から終了までに入力したテキスト（Backspace は `BACKSPACE` として記録する）
+    Insert { entry: InsertEntry, text: String },
+    /// :s / :%s
+    Substitute(Substitute),
+    /// `>>` / `<<` / ビジュアルモードの `>` `<`（行数と、深くするか）
+    ShiftLines { count: usize, right: bool },
+    /// J / gJ（結合した行数と、空白でつなぐか）
+    JoinLines { count: usize, with_space: bool },
+    /// gcc / ビジュアルモードの gc（切り替えた行数）
+    ToggleComment(usize),
+}
+
+/// `.` で繰り返す挿入テキスト中の Backspace。挿入前からあった文字や改行も同じように消せるよう、打鍵として再生する
+pub const BACKSPACE: char = '\u{8}';
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum FocusedPanel {
     Editor,
@@ -43,8 +139,12 @@ pub enum FocusedPanel {
 
 impl App {
     pub fn new(filename: Option<String>) -> Self {
-        let config = AppConfigManager::load_config();
-        let initial_window = Window::new(filename.clone());
+        Self::with_config(filename, AppConfigManager::load_config())
+    }
+
+    /// 設定ファイルを読まずに、与えた設定で起動する
+    pub fn with_config(filename: Option<String>, config: Config) -> Self {
+        let initial_window = Window::open(filename.clone(), positions::cache_path());
         let path = if let Some(f) = &filename {
             PathBuf::from(f)
                 .parent()
@@ -61,7 +161,7 @@ impl App {
             mode: Mode::Normal,
             command_buffer: String::new(),
             status_message: String::new(),
-            clipboard: Clipboard::new().unwrap(),
+            clipboard: Clipboard::new().ok(),
             current_path: path,
             directory_files: vec![],
             selected_directory_index: 0,
@@ -70,7 +170,9 @@ impl App {
             config,
             show_completion: false,
             completions: Vec::new(),
-            selected_completion: 0,
+            selected_completion: None,
+            command_completion_prefix: None,
+            completion_word: None,
             show_right_panel: false,
             right_panel_input: String::new(),
             right_panel_items: Vec::new(),
@@ -81,14 +183,38 @@ impl App {
             ai_response_receiver: Some(rx),
             ai_status: "LLM接続失敗".to_string(),
             right_panel_input_cursor: 0,
+            pending_operator: None,
+            pending_count: None,
+            pending_key: None,
+            pending_escape: None,
+            selected_register: None,
+            registers: Registers::new(),
+            search_pattern: None,
+            search_matches: Vec::new(),
+            search_forward: true,
+            visual_range: None,
+            pending_prompt: None,
+            jump_labels: Vec::new(),
+            last_change: None,
+            last_char_search: None,
+            insert_entry: None,
+            inserted_text: String::new(),
+            last_swap_write: Instant::now(),
+            view_pane: 0,
+            dictionary: Dictionary::default(),
         };
         app.update_directory_files();
+        if let Err(message) = app.load_dictionary() {
+            app.status_message = message;
+        }
+        app.load_undo_file(0);
+        app.check_swap_recovery(0);
         app
     }
 
     pub fn reload_config(&mut self) -> Result<(), String> {
         self.config = AppConfigManager::load_config();
-        Ok(())
+        self.load_dictionary()
     }
 
     pub fn show_current_config(&mut self) {
@@ -116,6 +242,47 @@ impl App {
                     format!("Set tab_size to {}", s)
                 })
                 .map_err(|_| "Invalid value for tab_size".to_string()),
+            // :set autoindent=detect でファイルからのインデント推定も有効にする
+            "autoindent" | "auto_indent" if value == "detect" => {
+                self.config.editor.auto_indent = true;
+                self.config.editor.detect_indent = true;
+                Ok("Set autoindent with indent detection".to_string())
+            }
+            "autoindent" | "auto_indent" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.auto_indent = b;
+                    format!("Set autoindent to {}", b)
+                })
+                .map_err(|_| "Invalid value for autoindent (use true/false/detect)".to_string()),
+            "expandtab" | "expand_tab" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.expand_tab = b;
+                    format!("Set expandtab to {}", b)
+                })
+                .map_err(|_| "Invalid value for expandtab (use true/false)".to_string()),
+            "detectindent" | "detect_indent" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.detect_indent = b;
+                    format!("Set detectindent to {}", b)
+                })
+                .map_err(|_| "Invalid value for detectindent (use true/false)".to_string()),
+            "line_number_mode" | "linenumbermode" => LineNumberMode::from_name(value)
+                .map(|mode| {
+                    self.config.editor.line_number_mode = mode;
+                    format!("Set line_number_mode to {}", mode.name())
+                })
+                .ok_or_else(|| "Invalid value for line_number_mode (use absolute/relative/hybrid)".to_string()),
+            // Vim の :set rnu / :set nornu（カーソル行は絶対、それ以外は相対で表示する）
+            "relativenumber" | "rnu" => value
+                .parse::<bool>()
+                .map(|relative| {
+                    self.config.editor.line_number_mode = if relative { LineNumberMode::Hybrid } else { LineNumberMode::Absolute };
+                    format!("Set line_number_mode to {}", self.config.editor.line_number_mode.name())
+                })
+                .map_err(|_| "Invalid value for relativenumber (use true/false)".to_string()),
             "show_line_numbers" => value
                 .parse::<bool>()
                 .map(|b| {
@@ -123,6 +290,143 @@ impl App {
                     format!("Set show_line_numbers to {}", b)
                 })
                 .map_err(|_| "Invalid value for show_line_numbers (use true/false)".to_string()),
+            "wrap" | "word_wrap" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.word_wrap = b;
+                    format!("Set wrap to {}", b)
+                })
+                .map_err(|_| "Invalid value for wrap (use true/false)".to_string()),
+            "undolevels" | "undo_levels" => value
+                .parse::<usize>()
+                .map(|levels| {
+                    self.config.editor.undo_levels = levels;
+                    format!("Set undolevels to {}", levels)
+                })
+                .map_err(|_| "Invalid value for undolevels".to_string()),
+            "undofile" | "undo_file" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.undo_file = b;
+                    format!("Set undofile to {}", b)
+                })
+                .map_err(|_| "Invalid value for undofile (use true/false)".to_string()),
+            "list" | "show_trailing_whitespace" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.show_trailing_whitespace = b;
+                    format!("Set list to {}", b)
+                })
+                .map_err(|_| "Invalid value for list (use true/false)".to_string()),
+            "cursorline" | "cul" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.cursorline = b;
+                    format!("Set cursorline to {}", b)
+                })
+                .map_err(|_| "Invalid value for cursorline (use true/false)".to_string()),
+            "wordcount" | "word_count" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.word_count = b;
+                    format!("Set wordcount to {}", b)
+                })
+                .map_err(|_| "Invalid value for wordcount (use true/false)".to_string()),
+            "showcmd" | "show_cmd" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.show_cmd = b;
+                    format!("Set showcmd to {}", b)
+                })
+                .map_err(|_| "Invalid value for showcmd (use true/false)".to_string()),
+            "overflowmarkers" | "show_overflow_markers" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.show_overflow_markers = b;
+                    format!("Set show_overflow_markers to {}", b)
+                })
+                .map_err(|_| "Invalid value for show_overflow_markers (use true/false)".to_string()),
+            "virtualedit" | "virtual_edit" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.virtual_edit = b;
+                    format!("Set virtualedit to {}", b)
+                })
+                .map_err(|_| "Invalid value for virtualedit (use true/false)".to_string()),
+            "dictionary" | "dict" => {
+                self.config.editor.dictionary = Some(value.to_string()).filter(|path| !path.is_empty());
+                self.load_dictionary().map(|_| format!("Set dictionary to {}", value))
+            }
+            "spell" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.spell = b;
+                    format!("Set spell to {}", b)
+                })
+                .map_err(|_| "Invalid value for spell (use true/false)".to_string()),
+            "autocomplete" | "auto_complete" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.auto_complete = b;
+                    format!("Set autocomplete to {}", b)
+                })
+                .map_err(|_| "Invalid value for autocomplete (use true/false)".to_string()),
+            "completion_min_chars" | "completionminchars" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|chars| (1..=10).contains(chars))
+                .map(|chars| {
+                    self.config.editor.completion_min_chars = chars;
+                    format!("Set completion_min_chars to {}", chars)
+                })
+                .ok_or_else(|| "Invalid value for completion_min_chars (use 1-10)".to_string()),
+            "autopairs" | "auto_pairs" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.auto_pairs = b;
+                    format!("Set autopairs to {}", b)
+                })
+                .map_err(|_| "Invalid value for autopairs (use true/false)".to_string()),
+            "stickyvisual" | "sticky_visual" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.sticky_visual = b;
+                    format!("Set stickyvisual to {}", b)
+                })
+                .map_err(|_| "Invalid value for stickyvisual (use true/false)".to_string()),
+            "esctimeout" | "esc_timeout_ms" => value
+                .parse::<u64>()
+                .map(|ms| {
+                    self.config.editor.esc_timeout_ms = ms;
+                    format!("Set esctimeout to {}", ms)
+                })
+                .map_err(|_| "Invalid value for esctimeout (milliseconds)".to_string()),
+            "backup" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.backup = b;
+                    format!("Set backup to {}", b)
+                })
+                .map_err(|_| "Invalid value for backup (use true/false)".to_string()),
+            "swap_file" => value
+                .parse::<bool>()
+                .map(|b| {
+                    self.config.editor.swap_file = b;
+                    format!("Set swap_file to {}", b)
+                })
+                .map_err(|_| "Invalid value for swap_file (use true/false)".to_string()),
+            "backup_suffix" => {
+                if value.is_empty() {
+                    Err("backup_suffix must not be empty".to_string())
+                } else {
+                    self.config.editor.backup_suffix = value.to_string();
+                    Ok(format!("Set backup_suffix to {}", value))
+                }
+            }
+            "backup_dir" => {
+                self.config.editor.backup_dir = if value.is_empty() { None } else { Some(value.to_string()) };
+                Ok(format!("Set backup_dir to {}", value))
+            }
             _ => Err(format!("Unknown config key: {}", key)),
         };
 
@@ -135,6 +439,46 @@ impl App {
         self.directory_scroll_offset = 0;
     }
 
+    /// 現在のファイルを同じバッファのまま分割し、新しいペインで別のカーソル位置を持てるようにする
+    pub fn split_current_window(&mut self, vertical: bool) {
+        let active_pane_id = self.pane_manager.get_active_pane_id();
+        let window_index = self.get_active_window_index();
+        let new_pane_id = if vertical {
+            self.pane_manager.vsplit(active_pane_id, window_index)
+        } else {
+            self.pane_manager.hsplit(active_pane_id, window_index)
+        };
+        if let Some(new_pane_id) = new_pane_id {
+            self.pane_manager.set_active_pane(new_pane_id);
+        }
+    }
+
+    /// アクティブペインが変わっていれば、前のペインの表示位置を保存して新しいペインの表示位置を復元する
+    pub fn sync_pane_views(&mut self) {
+        let active_pane_id = self.pane_manager.get_active_pane_id();
+        if active_pane_id == self.view_pane {
+            return;
+        }
+
+        // 分割された直後のペインは、元の内容を引き継いだ子ペインに保存する
+        let previous_pane_id = match self.pane_manager.get_pane(self.view_pane) {
+            Some(pane) if !pane.is_leaf() => pane.children.first().copied(),
+            Some(pane) => Some(pane.id),
+            None => None,
+        };
+        if let Some(pane) = previous_pane_id.and_then(|id| self.pane_manager.get_pane_mut(id)) {
+            pane.view = self.windows.get(pane.window_index).map(Window::view);
+        }
+
+        if let Some(pane) = self.pane_manager.get_active_pane() {
+            if let (Some(view), Some(window)) = (pane.view, self.windows.get_mut(pane.window_index)) {
+                window.clear_extra_cursors();
+                window.set_view(view);
+            }
+        }
+        self.view_pane = active_pane_id;
+    }
+
     pub fn open_selected_item(&mut self) {
         if let Some(selected_item) = self.directory_files.get(self.selected_directory_index).cloned() {
             self.handle_directory_item(selected_item, None);
@@ -174,22 +518,25 @@ impl App {
             let window_index = self.get_or_create_window(file_path_str);
 
             match split_type {
-                Some(SplitType::Vertical) => {
+                Some(split_type) => {
                     let active_pane_id = self.pane_manager.get_active_pane_id();
-                    if let Some(new_pane_id) = self.pane_manager.vsplit(active_pane_id, window_index) {
-                        self.pane_manager.set_active_pane(new_pane_id);
-                    }
-                }
-                Some(SplitType::Horizontal) => {
-                    let active_pane_id = self.pane_manager.get_active_pane_id();
-                    if let Some(new_pane_id) = self.pane_manager.hsplit(active_pane_id, window_index) {
-                        self.pane_manager.set_active_pane(new_pane_id);
+                    let new_pane_id = match split_type {
+                        SplitType::Vertical => self.pane_manager.vsplit(active_pane_id, window_index),
+                        SplitType::Horizontal => self.pane_manager.hsplit(active_pane_id, window_index),
+                    };
+                    // 元の内容は分割したペインの最初の子に引き継がれる
+                    let focused_pane_id = if self.config.ui.split_keeps_focus {
+                        self.pane_manager.get_pane(active_pane_id).and_then(|pane| pane.children.first().copied())
+                    } else {
+                        new_pane_id
+                    };
+                    if let Some(pane_id) = focused_pane_id {
+                        self.pane_manager.set_active_pane(pane_id);
                     }
                 }
                 None => {
-                    let active_pane_id = self.pane_manager.get_active_pane_id();
-                    if let Some(pane) = self.pane_manager.get_pane_mut(active_pane_id) {
-                        pane.window_index = window_index;
+                    if let Some(warning) = self.show_in_active_pane(window_index) {
+                        self.status_message = warning;
                     }
                 }
             }
@@ -203,9 +550,88 @@ impl App {
         if let Some(index) = self.windows.iter().position(|w| w.filename() == Some(&file_path_str)) {
             index
         } else {
-            let new_window = Window::new(Some(file_path_str));
+            let new_window = Window::open(Some(file_path_str), positions::cache_path());
             self.windows.push(new_window);
-            self.windows.len() - 1
+            let index = self.windows.len() - 1;
+            self.load_undo_file(index);
+            self.check_swap_recovery(index);
+            index
+        }
+    }
+
+    /// `:set undofile` なら前回の undo 履歴を読み込む
+    fn load_undo_file(&mut self, window_index: usize) {
+        let window = &mut self.windows[window_index];
+        if let (true, Some(path)) = (self.config.editor.undo_file, window.undo_file_path()) {
+            window.read_undo_file(&path);
+        }
+    }
+
+    /// 終了時、保存した内容のままのバッファの undo 履歴を書き出す（変更が残っていれば保存時に書いた履歴を残す）
+    pub fn write_undo_files(&mut self) {
+        if !self.config.editor.undo_file {
+            return;
+        }
+        for window in self.windows.iter_mut().filter(|window| !window.is_modified()) {
+            if let Some(path) = window.undo_file_path() {
+                let _ = window.write_undo_file(&path);
+            }
+        }
+    }
+
+    /// `:set swap_file` のときだけ、前回のスワップファイルが残っていれば復元するか確認する
+    fn check_swap_recovery(&mut self, window_index: usize) {
+        if !self.config.editor.swap_file {
+            return;
+        }
+        let window = &mut self.windows[window_index];
+        if window.detect_swap() {
+            self.status_message = format!(
+                "Swap file found for \"{}\". Recover? (y/n)",
+                window.filename().unwrap_or("Untitled")
+            );
+            self.pending_prompt = Some(Prompt::RecoverSwap(window_index));
+        }
+    }
+
+    /// 確認プロンプトへの回答を処理する
+    pub fn answer_prompt(&mut self, accepted: bool) {
+        match self.pending_prompt.take() {
+            Some(Prompt::RecoverSwap(window_index)) => {
+                let window = &mut self.windows[window_index];
+                self.status_message = if !accepted {
+                    window.discard_swap();
+                    "Swap file discarded".to_string()
+                } else {
+                    match window.recover_from_swap() {
+                        Ok(()) => "Recovered from swap file. Write the buffer to keep the changes".to_string(),
+                        Err(e) => format!("Failed to recover: {}", e),
+                    }
+                };
+            }
+            None => {}
+        }
+    }
+
+    /// 書き出し間隔が経過していれば全ウィンドウのスワップファイルを更新する
+    pub fn write_swap_files(&mut self) {
+        let interval = Duration::from_millis(self.config.editor.swap_interval_ms);
+        if !self.config.editor.swap_file || self.last_swap_write.elapsed() < interval {
+            return;
+        }
+        for window in &mut self.windows {
+            if let Err(e) = window.write_swap_file() {
+                self.status_message = format!("Failed to write swap file: {}", e);
+            }
+        }
+        self.last_swap_write = Instant::now();
+    }
+
+    pub fn remove_swap_files(&mut self) {
+        for window in &mut self.windows {
+            if !window.has_recoverable_swap() {
+                window.remove_swap_file();
+            }
         }
     }
 
@@ -219,18 +645,138 @@ impl App {
         &self.windows[index]
     }
 
-    pub fn set_yanked_text(&mut self, text: String) {
-        self.current_window_mut().yanked_text = text.clone();
-        if let Err(e) = self.clipboard.set_text(text) {
+    /// showcmd 用に、入力途中の回数・オペレータ・プレフィックスキーを文字列にする
+    pub fn pending_command_text(&self) -> String {
+        let mut text = self.selected_register.map(|name| format!("\"{}", name)).unwrap_or_default();
+        if let Some(count) = self.pending_count {
+            text.push_str(&count.to_string());
+        }
+        match self.pending_operator {
+            Some(PendingOperator::Delete) => text.push('d'),
+            Some(PendingOperator::Yank) => text.push('y'),
+            Some(PendingOperator::Change) => text.push('c'),
+            None => {}
+        }
+        if let Some(key) = self.pending_key {
+            text.push(key);
+        }
+        text
+    }
+
+    /// パターンを検索方向に検索し、最初の一致へ移動する
+    pub fn search(&mut self, pattern: &str) {
+        match Regex::new(pattern) {
+            Ok(regex) => {
+                self.search_pattern = Some(regex);
+                self.jump_to_search_match(self.search_forward, self.search_forward);
+            }
+            Err(e) => {
+                self.status_message = format!("Invalid pattern: {}", e);
+            }
+        }
+    }
+
+    /// `n` / `N`: 最後の検索と同じ方向（`reverse` なら逆方向）の一致へ移動する
+    pub fn repeat_search(&mut self, reverse: bool) {
+        self.jump_to_search_match(self.search_forward != reverse, false);
+    }
+
+    /// 次（前）の検索一致へ移動する
+    pub fn jump_to_search_match(&mut self, forward: bool, inclusive: bool) {
+        let Some(pattern) = self.search_pattern.clone() else {
+            self.status_message = "No previous search pattern".to_string();
+            return;
+        };
+        // 編集で位置がずれている可能性があるので毎回検索し直す
+        self.search_matches = self.current_window().find_matches(&pattern);
+        let pattern = pattern.as_str();
+        let next = self.current_window().next_match(&self.search_matches, forward, inclusive);
+        match next {
+            Some(((x, y), wrapped)) => {
+                let current_window = self.current_window_mut();
+                *current_window.cursor_x_mut() = x;
+                *current_window.cursor_y_mut() = y;
+                self.status_message = match (wrapped, forward) {
+                    (true, true) => "search hit BOTTOM, continuing at TOP".to_string(),
+                    (true, false) => "search hit TOP, continuing at BOTTOM".to_string(),
+                    (false, _) => format!("{}{}", if self.search_forward { '/' } else { '?' }, pattern),
+                };
+            }
+            None => {
+                self.status_message = format!("Pattern not found: {}", pattern);
+            }
+        }
+    }
+
+    /// ヤンクした内容を指定されたレジスタ（無ければ無名レジスタ）に入れ、クリップボードにも反映する
+    pub fn set_yanked_text(&mut self, text: String, kind: YankKind) {
+        let name = self.selected_register.take();
+        registers::store(&mut self.registers, name, text.clone(), kind);
+        let current_window = self.current_window_mut();
+        current_window.yanked_text = text.clone();
+        current_window.yank_kind = kind;
+        if let Some(Err(e)) = self.clipboard.as_mut().map(|clipboard| clipboard.set_text(text)) {
             self.status_message = format!("Failed to set clipboard: {}", e);
         }
     }
 
     pub fn get_clipboard_text(&mut self) -> Result<String, arboard::Error> {
-        self.clipboard.get_text()
+        self.clipboard.as_mut().ok_or(arboard::Error::ClipboardNotSupported)?.get_text()
+    }
+
+    /// `p` / `P`: クリップボード（`"a` 指定時はそのレジスタ）の内容をヤンクの種類に応じて貼り付ける
+    pub fn paste(&mut self, before: bool) {
+        if let Some(name) = self.selected_register.take().filter(|&name| name != registers::UNNAMED) {
+            let Some((text, kind)) = registers::get(&self.registers, Some(name)).cloned() else {
+                self.status_message = format!("Nothing in register {}", name);
+                return;
+            };
+            self.current_window_mut().paste(&text, kind, before);
+            self.last_change = Some(LastChange::Paste { text, kind, before });
+            return;
+        }
+        // クリップボードが使えなければ無名レジスタから貼り付ける
+        let Some(text) = self
+            .get_clipboard_text()
+            .ok()
+            .or_else(|| registers::get(&self.registers, None).map(|(text, _)| text.clone()))
+        else {
+            return;
+        };
+        let current_window = self.current_window_mut();
+        // 自分でヤンクしたテキストならその種類を、外部からのテキストは末尾の改行で判断する
+        let kind = if text == current_window.yanked_text {
+            current_window.yank_kind
+        } else if text.ends_with('\n') {
+            YankKind::LineWise
+        } else {
+            YankKind::CharWise
+        };
+        current_window.paste(&text, kind, before);
+        self.last_change = Some(LastChange::Paste { text, kind, before });
     }
 
-    fn get_active_window_index(&self) -> usize {
+    /// インデント1段分の文字列。推定が有効ならファイルから推定した設定を優先する
+    pub fn indent_unit(&self) -> String {
+        let detected = self.current_window().indent_style().filter(|_| self.config.editor.detect_indent);
+        match detected {
+            Some(IndentStyle::Tabs) => "\t".to_string(),
+            Some(IndentStyle::Spaces(width)) => " ".repeat(width),
+            None if self.config.editor.expand_tab => " ".repeat(self.config.editor.indent_width),
+            None => "\t".to_string(),
+        }
+    }
+
+    /// 挿入モードを終了し、入力したテキストを `.` 用に記録する
+    pub fn end_insert_mode(&mut self) {
+        self.current_window_mut().end_insert_mode();
+        if let Some(entry) = self.insert_entry.take() {
+            let text = std::mem::take(&mut self.inserted_text);
+            self.last_change = Some(LastChange::Insert { entry, text });
+        }
+    }
+
+    pub fn get_active_window_index(&self) -> usize {
         if let Some(active_pane) = self.pane_manager.get_active_pane() {
             active_pane.window_index
         } else {
@@ -265,6 +811,10 @@ impl App {
                 if let Some(left_pane_id) = self.pane_manager.get_next_left_pane_id() {
                     self.pane_manager.focus_pane(left_pane_id);
                 } else if self.show_directory {
+                    // 挿入モードから移る場合も、入力したテキストを `.` と undo の1単位として区切る
+                    if self.mode == Mode::Insert {
+                        self.end_insert_mode();
+                    }
                     self.focused_panel = FocusedPanel::Directory;
                     self.mode = Mode::Normal;
                 }
@@ -306,6 +856,7 @@ impl App {
                 if let Some(up_pane_id) = self.pane_manager.get_next_up_pane_id() {
                     self.pane_manager.focus_pane(up_pane_id);
                 } else {
+                    let virtual_edit = self.config.editor.virtual_edit;
                     let current_window = self.current_window_mut();
                     let cy = *current_window.cursor_y_mut();
                     if cy > 0 {
@@ -313,7 +864,9 @@ impl App {
                         let cy2 = *current_window.cursor_y_mut();
                         let current_line_len_graphemes = current_window.buffer()[cy2].graphemes(true).count();
                         let cx = *current_window.cursor_x_mut();
-                        *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                        if !virtual_edit {
+                            *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                        }
                     }
                 }
             }
@@ -334,6 +887,7 @@ impl App {
                 if let Some(down_pane_id) = self.pane_manager.get_next_down_pane_id() {
                     self.pane_manager.focus_pane(down_pane_id);
                 } else {
+                    let virtual_edit = self.config.editor.virtual_edit;
                     let current_window = self.current_window_mut();
                     let len = current_window.buffer().len();
                     let cy = *current_window.cursor_y_mut();
@@ -342,7 +896,9 @@ impl App {
                         let cy2 = *current_window.cursor_y_mut();
                         let current_line_len_graphemes = current_window.buffer()[cy2].graphemes(true).count();
                         let cx = *current_window.cursor_x_mut();
-                        *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                        if !virtual_edit {
+                            *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                        }
                     }
                 }
             }
@@ -353,55 +909,196 @@ impl App {
         }
     }
 
-    pub fn open_file(&mut self, filename: &str) {
-        let file_path = if filename.starts_with('/') {
-            PathBuf::from(filename)
-        } else {
-            self.current_path.join(filename)
-        };
+    /// 相対パスをディレクトリパネルの現在のディレクトリから解決する
+    pub fn resolve_path(&self, filename: &str) -> String {
+        self.current_path.join(filename).to_string_lossy().to_string()
+    }
 
-        let file_path_str = file_path.to_string_lossy().to_string();
+    pub fn open_file(&mut self, filename: &str) {
+        let file_path_str = self.resolve_path(filename);
+        let file_path = PathBuf::from(&file_path_str);
         let window_index = self.get_or_create_window(file_path_str.clone());
+        let warning = self.show_in_active_pane(window_index);
+
+        self.status_message = warning.unwrap_or_else(|| if file_path.exists() {
+            format!("\"{}\" opened", filename)
+        } else {
+            format!("\"{}\" [New File]", filename)
+        });
+    }
 
+    /// アクティブペインに別のウィンドウを表示する。未保存の変更があるバッファが見えなくなる場合は警告を返す
+    fn show_in_active_pane(&mut self, window_index: usize) -> Option<String> {
+        let previous = self.get_active_window_index();
         let active_pane_id = self.pane_manager.get_active_pane_id();
         if let Some(pane) = self.pane_manager.get_pane_mut(active_pane_id) {
             pane.window_index = window_index;
         }
+        let still_shown = self.pane_manager.get_leaf_panes().iter().any(|pane| pane.window_index == previous);
+        let window = &self.windows[previous];
+        (!still_shown && window.is_modified()).then(|| {
+            format!(
+                "No write since last change in \"{}\" (kept open, :bd! to discard)",
+                window.filename().unwrap_or("Untitled")
+            )
+        })
+    }
 
-        self.status_message = if file_path.exists() {
-            format!("\"{}\" opened", filename)
-        } else {
-            format!("\"{}\" [New File]", filename)
+    /// :bd: 現在のバッファを閉じ、それを表示していたペインには別のバッファ（無ければ空のバッファ）を表示する
+    pub fn delete_current_buffer(&mut self, force: bool) {
+        let index = self.get_active_window_index();
+        if !force && self.windows[index].is_modified() {
+            self.status_message = "No write since last change (add ! to override)".to_string();
+            return;
+        }
+        if self.windows.len() == 1 {
+            self.windows.push(Window::new(None));
+        }
+        let replacement = if index > 0 { index - 1 } else { index + 1 };
+
+        let mut window = self.windows.remove(index);
+        window.remove_swap_file();
+        self.pane_manager.remove_window(index, replacement);
+        self.pending_prompt = match self.pending_prompt.take() {
+            Some(Prompt::RecoverSwap(i)) if i == index => None,
+            Some(Prompt::RecoverSwap(i)) if i > index => Some(Prompt::RecoverSwap(i - 1)),
+            prompt => prompt,
         };
+        self.status_message = format!("\"{}\" deleted", window.filename().unwrap_or("Untitled"));
     }
 
-    pub fn apply_completion(&mut self) {
-        if self.show_completion && !self.completions.is_empty() {
-            let completion = self.completions[self.selected_completion].clone();
-            let (start, end) = self.get_current_word_bounds();
-            let window = self.current_window_mut();
-            let cursor_y = window.cursor_y();
-            let line = &mut window.buffer_mut()[cursor_y];
-            line.replace_range(start..end, &completion);
-            *window.cursor_x_mut() = start + completion.len();
+    /// コマンドラインの Tab: `:e` などのパスを補完し、候補が複数あれば続けて押すたびに次の候補に切り替える
+    pub fn complete_command_path(&mut self) {
+        if let Some(prefix) = &self.command_completion_prefix {
+            let selected = self.selected_completion.map_or(0, |i| (i + 1) % self.completions.len());
+            self.selected_completion = Some(selected);
+            self.command_buffer = format!("{}{}", prefix, self.completions[selected]);
+            return;
+        }
+        let Some(command) = PATH_COMMANDS.iter().find(|command| self.command_buffer.starts_with(*command)) else {
+            return;
+        };
+        let partial = self.command_buffer[command.len()..].trim_start();
+        let prefix = self.command_buffer[..self.command_buffer.len() - partial.len()].to_string();
+        let completions = utils::complete_path(&self.current_path, partial);
+        let Some(first) = completions.first() else {
+            return;
+        };
+        self.command_buffer = format!("{}{}", prefix, first);
+        // 候補が1つならそのまま確定し、次の Tab ではディレクトリの中を補完する
+        if completions.len() > 1 {
+            self.command_completion_prefix = Some(prefix);
+            self.show_completion = true;
+            self.completions = completions;
+            self.selected_completion = Some(0);
+        }
+    }
+
+    pub fn reset_command_completion(&mut self) {
+        if self.command_completion_prefix.take().is_some() {
             self.show_completion = false;
         }
     }
 
-    fn get_current_word_bounds(&self) -> (usize, usize) {
+    /// :vs file / :sp file: アクティブペインを分割してファイルを開く
+    pub fn split_open_file(&mut self, filename: &str, vertical: bool) {
+        self.split_current_window(vertical);
+        self.open_file(filename);
+    }
+
+    /// カーソル直前の単語を接頭辞として、バッファ内の単語と辞書の単語から補完候補を作る
+    pub fn update_completions(&mut self) {
         let window = self.current_window();
-        let line = &window.buffer()[window.cursor_y()];
-        let cursor_x = window.cursor_x();
+        let (_, _, prefix) = window.completion_prefix();
+        let mut completions = Vec::new();
+        if !prefix.is_empty() {
+            completions = window.buffer_words_with_prefix(&prefix);
+            completions.truncate(MAX_COMPLETIONS);
+            for word in self.dictionary.words_with_prefix(&prefix) {
+                if completions.len() >= MAX_COMPLETIONS {
+                    break;
+                }
+                if !completions.iter().any(|c| c == word) {
+                    completions.push(word.to_string());
+                }
+            }
+        }
+        self.show_completion = !completions.is_empty();
+        self.completions = completions;
+        self.selected_completion = None;
+    }
+
+    /// 挿入モードで入力するたびに補完候補を更新する。カーソル直前の単語が変わったときだけ作り直し、
+    /// 表示していなければ、単語が `completion_min_chars` 文字以上で候補が複数あるときだけ自動で表示する
+    pub fn refresh_completions(&mut self) {
+        if !self.show_completion && !self.config.editor.auto_complete {
+            return;
+        }
+        let word = self.current_window().completion_prefix();
+        if self.completion_word.as_ref() == Some(&word) {
+            return;
+        }
+        let was_shown = self.show_completion;
+        if !was_shown && word.2.graphemes(true).count() < self.config.editor.completion_min_chars {
+            return;
+        }
+        self.completion_word = Some(word);
+        self.update_completions();
+        if !was_shown && self.completions.len() < 2 {
+            self.show_completion = false;
+        }
+    }
+
+    /// 選択中の補完候補を確定して補完を閉じる（何も選択していなければ閉じるだけ）
+    pub fn apply_completion(&mut self) {
+        if let Some(completion) = self.selected_completion.filter(|_| self.show_completion).and_then(|i| self.completions.get(i)) {
+            let completion = completion.clone();
+            self.current_window_mut().replace_word_with_completion(&completion);
+        }
+        self.show_completion = false;
+    }
 
-        let start = line[..cursor_x]
-            .rfind(|c: char| !c.is_alphanumeric() && c != '_')
-            .map_or(0, |i| i + 1);
+    /// `:set spell` が有効なら、ウィンドウ内の辞書にない単語の位置 (行, 開始バイト, 終了バイト) を返す
+    pub fn misspellings(&self, window_index: usize) -> Vec<spell::Misspelling> {
+        match self.windows.get(window_index) {
+            Some(window) if self.config.editor.spell => window.misspellings(&self.dictionary),
+            _ => Vec::new(),
+        }
+    }
 
-        let end = line[cursor_x..]
-            .find(|c: char| !c.is_alphanumeric() && c != '_')
-            .map_or(line.len(), |i| cursor_x + i);
+    /// ]s / [s: 次（前）の綴りの誤りへ移動する（端に達したら反対側から探す）
+    pub fn jump_to_misspelling(&mut self, forward: bool) {
+        if !self.config.editor.spell {
+            self.status_message = "Spell checking is off (:set spell)".to_string();
+            return;
+        }
+        let misspellings = self.misspellings(self.get_active_window_index());
+        let window = self.current_window();
+        let line = &window.buffer()[window.cursor_y()];
+        let cursor_byte = line.grapheme_indices(true).nth(window.cursor_x()).map_or(line.len(), |(i, _)| i);
+        let cursor = (window.cursor_y(), cursor_byte);
+        let target = if forward {
+            misspellings.iter().find(|&&(y, start, _)| (y, start) > cursor).or(misspellings.first())
+        } else {
+            misspellings.iter().rev().find(|&&(y, start, _)| (y, start) < cursor).or(misspellings.last())
+        };
+        let Some(&(y, start, _)) = target else {
+            self.status_message = "No misspelled words".to_string();
+            return;
+        };
+        let window = self.current_window_mut();
+        *window.cursor_y_mut() = y;
+        *window.cursor_x_mut() = window.buffer()[y][..start].graphemes(true).count();
+    }
 
-        (start, end)
+    /// 設定された辞書ファイルを読み込む（未設定や読み込みに失敗した場合は空にする）
+    fn load_dictionary(&mut self) -> Result<(), String> {
+        self.windows.iter().for_each(Window::clear_misspellings_cache);
+        self.dictionary = Dictionary::default();
+        if let Some(path) = self.config.editor.dictionary.as_deref() {
+            self.dictionary = Dictionary::load(path).map_err(|e| format!("Failed to load dictionary {}: {}", path, e))?;
+        }
+        Ok(())
     }
 
     pub fn move_directory_selection_up(&mut self, visible_height: usize) {
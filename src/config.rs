@@ -3,8 +3,10 @@ use serde::{Serialize, Deserialize};
 use std::fs;
 
 use std::collections::HashMap;
-use ratatui::style::Color;
+use ratatui::style::{Color, Modifier, Style};
 use std::path::Path;
+use crate::window::Mode;
+use crate::constants::{editor, ui};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
@@ -58,6 +60,20 @@ pub struct SyntaxTheme {
     pub bracket_colors: Vec<SerializableColor>,
     pub unmatched_bracket_fg: SerializableColor,
     pub unmatched_bracket_bg: SerializableColor,
+    /// `///` / `//!` のドキュメントコメントの色
+    #[serde(default = "default_doc_comment_color")]
+    pub doc_comment: SerializableColor,
+    /// `#[...]` の属性の色
+    #[serde(default = "default_attribute_color")]
+    pub attribute: SerializableColor,
+}
+
+fn default_doc_comment_color() -> SerializableColor {
+    SerializableColor::Name("Green".to_string())
+}
+
+fn default_attribute_color() -> SerializableColor {
+    SerializableColor::Name("LightMagenta".to_string())
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -71,6 +87,40 @@ pub struct UiTheme {
     pub completion_background: SerializableColor,
     pub completion_foreground: SerializableColor,
     pub completion_selection_background: SerializableColor,
+    /// モードごとのモード表示の背景色（未指定なら status_bar_background）
+    #[serde(default)]
+    pub status_bar_normal_background: Option<SerializableColor>,
+    #[serde(default)]
+    pub status_bar_insert_background: Option<SerializableColor>,
+    #[serde(default)]
+    pub status_bar_visual_background: Option<SerializableColor>,
+    #[serde(default)]
+    pub status_bar_command_background: Option<SerializableColor>,
+    /// タブ文字の先頭に表示する → の色（未指定なら空白だけで表示する）
+    #[serde(default)]
+    pub tab_indicator: Option<SerializableColor>,
+    /// 文字ジャンプのラベルの背景色（未指定なら selection_background）
+    #[serde(default)]
+    pub jump_label_background: Option<SerializableColor>,
+    /// カーソル下の括弧と対応する括弧の文字色・背景色（どちらも未指定なら下線で示す）
+    #[serde(default)]
+    pub matching_bracket_fg: Option<SerializableColor>,
+    #[serde(default)]
+    pub matching_bracket_bg: Option<SerializableColor>,
+    /// cursorline が有効なときのカーソル行の背景色
+    #[serde(default = "default_cursor_line_background")]
+    pub cursor_line_background: SerializableColor,
+    /// show_trailing_whitespace が有効なときの行末の空白の背景色
+    #[serde(default = "default_trailing_whitespace_background")]
+    pub trailing_whitespace_background: SerializableColor,
+}
+
+fn default_cursor_line_background() -> SerializableColor {
+    SerializableColor::Rgb([40, 40, 40])
+}
+
+fn default_trailing_whitespace_background() -> SerializableColor {
+    SerializableColor::Name("Red".to_string())
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -80,6 +130,35 @@ pub struct Theme {
     pub ui: UiTheme,
 }
 
+impl UiTheme {
+    /// モードに応じたモード表示の背景色
+    pub fn status_bar_background_for(&self, mode: Mode) -> Color {
+        let color = match mode {
+            Mode::Normal => &self.status_bar_normal_background,
+            Mode::Insert => &self.status_bar_insert_background,
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => &self.status_bar_visual_background,
+            Mode::Command | Mode::Search => &self.status_bar_command_background,
+            Mode::RightPanelInput => &None,
+        };
+        color.clone().unwrap_or_else(|| self.status_bar_background.clone()).into()
+    }
+
+    /// カーソル下の括弧とそれに対応する括弧のスタイル（色の指定が無ければ下線を引く）
+    pub fn matching_bracket_style(&self) -> Style {
+        let mut style = Style::default();
+        if let Some(fg) = self.matching_bracket_fg.clone() {
+            style = style.fg(fg.into());
+        }
+        if let Some(bg) = self.matching_bracket_bg.clone() {
+            style = style.bg(bg.into());
+        }
+        if self.matching_bracket_fg.is_none() && self.matching_bracket_bg.is_none() {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        style
+    }
+}
+
 impl Theme {
     pub fn load(name: &str) -> Self {
         let path_str = format!("themes/{}.json", name);
@@ -114,12 +193,52 @@ impl Default for KeyBindings {
         normal.insert("l".to_string(), "move_right".to_string());
         normal.insert("i".to_string(), "mode_insert".to_string());
         normal.insert("v".to_string(), "mode_visual".to_string());
+        normal.insert("V".to_string(), "mode_visual_line".to_string());
         normal.insert(":".to_string(), "mode_command".to_string());
         normal.insert("p".to_string(), "paste".to_string());
+        normal.insert("P".to_string(), "paste_before".to_string());
         normal.insert("x".to_string(), "delete_char".to_string());
         normal.insert("a".to_string(), "append".to_string());
         normal.insert("u".to_string(), "undo".to_string());
+        normal.insert(".".to_string(), "repeat_last_change".to_string());
+        normal.insert("c".to_string(), "operator_change".to_string());
+        normal.insert("C".to_string(), "change_to_line_end".to_string());
+        normal.insert("r".to_string(), "replace_char".to_string());
         normal.insert("o".to_string(), "open_new_line".to_string());
+        normal.insert("O".to_string(), "open_new_line_above".to_string());
+        normal.insert("d".to_string(), "operator_delete".to_string());
+        normal.insert("D".to_string(), "delete_to_line_end".to_string());
+        normal.insert("y".to_string(), "operator_yank".to_string());
+        normal.insert("Y".to_string(), "yank_line".to_string());
+        normal.insert("w".to_string(), "word_forward".to_string());
+        normal.insert("b".to_string(), "word_backward".to_string());
+        normal.insert("e".to_string(), "word_end".to_string());
+        normal.insert("%".to_string(), "jump_percent".to_string());
+        normal.insert("0".to_string(), "line_start".to_string());
+        normal.insert("$".to_string(), "line_end".to_string());
+        normal.insert("^".to_string(), "first_non_blank".to_string());
+        normal.insert("g".to_string(), "g_prefix".to_string());
+        normal.insert("z".to_string(), "z_prefix".to_string());
+        normal.insert("s".to_string(), "jump_to_char".to_string());
+        normal.insert("f".to_string(), "find_char".to_string());
+        normal.insert("F".to_string(), "find_char_backward".to_string());
+        normal.insert("t".to_string(), "till_char".to_string());
+        normal.insert("T".to_string(), "till_char_backward".to_string());
+        normal.insert(";".to_string(), "repeat_char_search".to_string());
+        normal.insert(",".to_string(), "repeat_char_search_reverse".to_string());
+        normal.insert("m".to_string(), "set_mark".to_string());
+        normal.insert("\"".to_string(), "register_prefix".to_string());
+        normal.insert("`".to_string(), "jump_to_mark".to_string());
+        normal.insert("]".to_string(), "next_prefix".to_string());
+        normal.insert("[".to_string(), "prev_prefix".to_string());
+        normal.insert(">".to_string(), "indent_prefix".to_string());
+        normal.insert("<".to_string(), "dedent_prefix".to_string());
+        normal.insert("G".to_string(), "goto_line".to_string());
+        normal.insert("J".to_string(), "join_lines".to_string());
+        normal.insert("/".to_string(), "mode_search".to_string());
+        normal.insert("?".to_string(), "mode_search_backward".to_string());
+        normal.insert("n".to_string(), "search_next".to_string());
+        normal.insert("N".to_string(), "search_prev".to_string());
         
         let mut ctrl = HashMap::new();
         ctrl.insert("f".to_string(), "toggle_directory".to_string());
@@ -130,15 +249,104 @@ impl Default for KeyBindings {
     }
 }
 
+/// 行番号の表示方法
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumberMode {
+    #[default]
+    Absolute,
+    /// カーソル行からの距離（カーソル行は 0）
+    Relative,
+    /// カーソル行は絶対、それ以外は相対
+    Hybrid,
+}
+
+impl LineNumberMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "absolute" => Some(LineNumberMode::Absolute),
+            "relative" => Some(LineNumberMode::Relative),
+            "hybrid" => Some(LineNumberMode::Hybrid),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumberMode::Absolute => "absolute",
+            LineNumberMode::Relative => "relative",
+            LineNumberMode::Hybrid => "hybrid",
+        }
+    }
+
+    /// 行 `y` に表示する番号
+    pub fn label(self, y: usize, cursor_y: usize) -> usize {
+        match self {
+            LineNumberMode::Absolute => y + 1,
+            LineNumberMode::Hybrid if y == cursor_y => y + 1,
+            LineNumberMode::Relative | LineNumberMode::Hybrid => y.abs_diff(cursor_y),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct EditorConfig {
     pub indent_width: usize,
     pub show_line_numbers: bool,
     pub line_number_width: usize,
+    /// 行番号を絶対・カーソル行からの相対・その両方（カーソル行だけ絶対）のどれで表示するか
+    pub line_number_mode: LineNumberMode,
     pub tab_size: usize,
     pub auto_indent: bool,
+    /// インデントに空白を使うか（false ならタブ）
+    pub expand_tab: bool,
+    /// 開いたファイルのインデント（タブか空白か、その幅）を推定して使うか
+    pub detect_indent: bool,
     pub word_wrap: bool,
     pub cursor_style: String,
+    /// 入力途中のコマンド（回数やオペレータ）をステータスバーに表示するか
+    pub show_cmd: bool,
+    /// 表示範囲からはみ出した行の端にマーカーを表示するか
+    pub show_overflow_markers: bool,
+    /// カーソルを行末より先（仮想空間）へ移動できるようにするか
+    pub virtual_edit: bool,
+    /// 保存前に既存ファイルのバックアップを作成するか
+    pub backup: bool,
+    /// バックアップファイル名に付加するサフィックス
+    pub backup_suffix: String,
+    /// バックアップの保存先ディレクトリ（未指定なら元ファイルと同じ場所）
+    pub backup_dir: Option<String>,
+    /// 未保存の変更をスワップファイルへ定期的に書き出すか
+    pub swap_file: bool,
+    /// スワップファイルを書き出す間隔（ミリ秒）
+    pub swap_interval_ms: u64,
+    /// Esc を押した後、エスケープシーケンス（Alt+キー）の続きを待つ時間（ミリ秒）
+    /// この間に文字が届けば Esc と合わせて Alt+文字 として扱い、届かなければ Esc として処理する（0 なら待たない）
+    pub esc_timeout_ms: u64,
+    /// 挿入モードで開き括弧・引用符を入力したときに閉じ文字を自動で挿入するか
+    pub auto_pairs: bool,
+    /// 挿入モードで入力中の単語に合うバッファ内の単語が複数あれば、補完候補を自動で表示するか
+    /// （false なら Ctrl+Space / Ctrl+N を押したときだけ表示する）
+    pub auto_complete: bool,
+    /// 補完候補を自動で表示し始める単語の最小文字数（1〜10）
+    pub completion_min_chars: usize,
+    /// 補完候補に加える単語リストファイル（1行1単語）。綴りの確認にも使う
+    pub dictionary: Option<String>,
+    /// 辞書にない単語に下線を引くか（コードではコメントと文字列だけ）
+    pub spell: bool,
+    /// ビジュアルモードでヤンクした後も選択を残すか（d は常に選択を終了する）
+    pub sticky_visual: bool,
+    /// 保存する undo 履歴の最大数
+    pub undo_levels: usize,
+    /// undo 履歴を保存時にファイルへ書き出し、次に開いたときに読み込むか
+    pub undo_file: bool,
+    /// 文章ファイル（.txt / .md など）でステータスバーに単語数と読了時間を表示するか
+    pub word_count: bool,
+    /// アクティブなペインでカーソル行の背景に色を付けるか
+    pub cursorline: bool,
+    /// 行末の空白・タブに背景色を付けるか（挿入中の行は除く）
+    pub show_trailing_whitespace: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -155,6 +363,16 @@ pub struct UiConfig {
     pub show_directory_pane: bool,
     pub directory_pane_floating: bool,
     pub editor_margins: EditorMargins,
+    /// ディレクトリパネルから分割して開いたとき、フォーカスを元のペインに残すか（false なら新しいペインへ移す）
+    #[serde(default)]
+    pub split_keeps_focus: bool,
+    /// 補完ポップアップの最大幅（これより長い候補は省略記号で切り詰める）
+    #[serde(default = "default_completion_max_width")]
+    pub completion_max_width: u16,
+}
+
+fn default_completion_max_width() -> u16 {
+    ui::COMPLETION_MAX_WIDTH
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -172,11 +390,34 @@ impl Default for EditorConfig {
         Self {
             indent_width: 4,
             show_line_numbers: true,
-            line_number_width: 4,
+            line_number_width: editor::DEFAULT_LINE_NUMBER_WIDTH,
+            line_number_mode: LineNumberMode::default(),
             tab_size: 4,
             auto_indent: true,
+            expand_tab: true,
+            detect_indent: true,
             word_wrap: false,
             cursor_style: "block".to_string(),
+            show_cmd: true,
+            show_overflow_markers: true,
+            virtual_edit: false,
+            backup: false,
+            backup_suffix: "~".to_string(),
+            backup_dir: None,
+            swap_file: false,
+            swap_interval_ms: 4000,
+            esc_timeout_ms: 50,
+            auto_pairs: false,
+            auto_complete: true,
+            completion_min_chars: 2,
+            dictionary: None,
+            spell: false,
+            sticky_visual: false,
+            undo_levels: 100,
+            undo_file: false,
+            word_count: false,
+            cursorline: false,
+            show_trailing_whitespace: false,
         }
     }
 }
@@ -199,6 +440,8 @@ impl Default for UiConfig {
             show_directory_pane: false,
             directory_pane_floating: false,
             editor_margins: EditorMargins::default(),
+            split_keeps_focus: false,
+            completion_max_width: ui::COMPLETION_MAX_WIDTH,
         }
     }
 }
@@ -228,6 +471,8 @@ impl Default for SyntaxTheme {
             ],
             unmatched_bracket_fg: SerializableColor::Name("Red".to_string()),
             unmatched_bracket_bg: SerializableColor::Rgb([80, 0, 0]),
+            doc_comment: default_doc_comment_color(),
+            attribute: default_attribute_color(),
         }
     }
 }
@@ -253,6 +498,16 @@ impl Default for UiTheme {
             completion_background: SerializableColor::Name("DarkGray".to_string()),
             completion_foreground: SerializableColor::Name("White".to_string()),
             completion_selection_background: SerializableColor::Name("Blue".to_string()),
+            status_bar_normal_background: Some(SerializableColor::Name("Blue".to_string())),
+            status_bar_insert_background: Some(SerializableColor::Name("Green".to_string())),
+            status_bar_visual_background: Some(SerializableColor::Name("Magenta".to_string())),
+            status_bar_command_background: Some(SerializableColor::Name("Yellow".to_string())),
+            tab_indicator: None,
+            jump_label_background: Some(SerializableColor::Name("Yellow".to_string())),
+            matching_bracket_fg: Some(SerializableColor::Name("Yellow".to_string())),
+            matching_bracket_bg: Some(SerializableColor::Name("DarkGray".to_string())),
+            cursor_line_background: default_cursor_line_background(),
+            trailing_whitespace_background: default_trailing_whitespace_background(),
         }
     }
 }
@@ -5,6 +5,24 @@ pub mod editor {
     
     /// 行番号とテキストの間のセパレータ幅
     pub const LINE_NUMBER_SEPARATOR_WIDTH: usize = 1;
+
+    /// イベント待ちのポーリング間隔（ミリ秒）
+    pub const EVENT_POLL_INTERVAL_MS: u64 = 250;
+
+    /// インデントの推定に使う、インデントされた行の最大数
+    pub const INDENT_DETECTION_LINES: usize = 100;
+
+    /// カーソル位置のキャッシュに残すファイルの最大数（古いものから消す）
+    pub const POSITION_CACHE_ENTRIES: usize = 500;
+
+    /// 補完候補の最大数（バッファの単語と辞書の単語の合計）
+    pub const MAX_COMPLETIONS: usize = 50;
+
+    /// 読了時間の見積もりに使う1分あたりの単語数
+    pub const READING_WORDS_PER_MINUTE: usize = 200;
+
+    /// 文字ジャンプのラベルに使う文字（カーソルに近い位置から順に割り当てる）
+    pub const JUMP_LABELS: &str = "asdfghjklqwertyuiopzxcvbnm";
     
     
 }
@@ -13,6 +31,18 @@ pub mod editor {
 pub mod ui {
     /// 非表示行マーカーの文字
     pub const EMPTY_LINE_MARKER: &str = "~";
+
+    /// 行が表示範囲の右側にはみ出していることを示すマーカー
+    pub const OVERFLOW_RIGHT_MARKER: &str = ">";
+
+    /// 行が表示範囲の左側にはみ出していることを示すマーカー
+    pub const OVERFLOW_LEFT_MARKER: &str = "<";
+
+    /// showcmd 表示領域の幅
+    pub const SHOWCMD_WIDTH: u16 = 12;
+
+    /// 補完ポップアップの既定の最大幅
+    pub const COMPLETION_MAX_WIDTH: u16 = 40;
 }
 
 
@@ -0,0 +1,58 @@
+use std::{fs, io};
+
+/// 補完に使う単語リスト（1行1単語のファイルから読み込む）
+#[derive(Clone, Debug, Default)]
+pub struct Dictionary {
+    /// 前方一致で探せるよう整列・重複除去した単語
+    words: Vec<String>,
+}
+
+impl Dictionary {
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(Self::from_words(fs::read_to_string(path)?.lines()))
+    }
+
+    pub fn from_words<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(String::from)
+            .collect();
+        words.sort();
+        words.dedup();
+        Self { words }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.binary_search_by(|w| w.as_str().cmp(word)).is_ok()
+    }
+
+    /// `prefix` で始まる単語を辞書順に返す（`prefix` と同じ単語は除く）
+    pub fn words_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        let start = self.words.partition_point(|word| word.as_str() < prefix);
+        self.words[start..]
+            .iter()
+            .take_while(move |word| word.starts_with(prefix))
+            .filter(move |word| word.as_str() != prefix)
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_with_prefix() {
+        let dictionary = Dictionary::from_words(["beta", "alpha", "  alphabet ", "", "alpha", "alps"]);
+        assert_eq!(dictionary.words_with_prefix("").count(), 4);
+        assert_eq!(dictionary.words_with_prefix("alp").collect::<Vec<_>>(), vec!["alpha", "alphabet", "alps"]);
+        assert_eq!(dictionary.words_with_prefix("alpha").collect::<Vec<_>>(), vec!["alphabet"]);
+        assert_eq!(dictionary.words_with_prefix("gamma").count(), 0);
+    }
+}
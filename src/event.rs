@@ -3,16 +3,20 @@ mod insert;
 mod normal;
 mod visual;
 mod right_panel_input;
+mod search;
 
 use crate::app::{App, Mode};
+use crate::constants::editor::EVENT_POLL_INTERVAL_MS;
 use crossterm::{
     cursor::SetCursorStyle,
-    event::{self, Event, KeyEventKind, KeyCode, KeyModifiers},
+    event::{self, Event, KeyEvent, KeyEventKind, KeyCode, KeyModifiers},
     execute,
 };
 use ratatui::backend::Backend;
+use ratatui::layout::Rect;
 use ratatui::Terminal;
-use std::io;
+use std::{io, time::{Duration, Instant}};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub async fn run_app<B: Backend + std::io::Write>(
     terminal: &mut Terminal<B>,
@@ -45,51 +49,193 @@ pub async fn run_app<B: Backend + std::io::Write>(
                 execute!(terminal.backend_mut(), SetCursorStyle::SteadyBlock)?;
             }
         }
+        app.write_swap_files();
+        app.sync_pane_views();
+        let (tab_size, word_wrap, undo_levels) = (app.config.editor.tab_size, app.config.editor.word_wrap, app.config.editor.undo_levels);
+        app.windows.iter_mut().for_each(|window| {
+            window.set_tab_size(tab_size);
+            window.set_word_wrap(word_wrap);
+            window.set_undo_levels(undo_levels);
+        });
         terminal.draw(|f| crate::ui::ui(f, &mut app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press || key.kind == KeyEventKind::Repeat {
-                // パネル切り替えの統一処理
-                if handle_panel_toggle(&mut app, key.code, key.modifiers) {
-                    continue;
-                }
-                
-                // フォーカス切り替えの統一処理
-                if handle_focus_cycling(&mut app, key.code) {
-                    continue;
-                }
+        // 定期処理（スワップファイルの書き出しなど）のため、入力はタイムアウト付きで待つ
+        // Esc が続きを待っている間は、そのタイムアウトが過ぎたらすぐ処理できるように待つ時間を縮める
+        let poll_timeout = app.pending_escape.map_or(Duration::from_millis(EVENT_POLL_INTERVAL_MS), |since| {
+            Duration::from_millis(app.config.editor.esc_timeout_ms).saturating_sub(since.elapsed())
+        });
+        if !event::poll(poll_timeout)? {
+            flush_expired_escape(&mut app);
+            continue;
+        }
 
-                if key.code == KeyCode::Esc {
-                    // どのモードでもEscでノーマルモードに戻る
-                    // ただし、特殊な状態（ビジュアルモードなど）のクリーンアップが必要な場合がある
-                    if app.mode == Mode::Visual {
-                        *app.current_window_mut().visual_start_mut() = None;
-                    }
-                    if app.mode == Mode::Insert {
-                        app.current_window_mut().end_insert_mode();
-                    }
-                    app.mode = Mode::Normal;
-                    continue;
-                }
+        let event = event::read()?;
+        if let Event::Resize(width, height) = event {
+            // 端末サイズが変わったら画面を作り直し、次のループで即座に再描画する
+            // （ペインのレイアウトやスクロール位置は描画時に新しいサイズで再計算される）
+            terminal.resize(Rect::new(0, 0, width, height))?;
+            continue;
+        }
 
-                match app.mode {
-                    Mode::Normal => normal::handle_normal_mode_event(&mut app, key.code, key.modifiers),
-                    Mode::Insert => insert::handle_insert_mode_event(&mut app, key.code),
-                    Mode::Visual => visual::handle_visual_mode_event(&mut app, key.code),
-                    // 非同期AIリクエストはbg関数で処理
-                    Mode::RightPanelInput => right_panel_input::handle_right_panel_input_mode_event(&mut app, key),
-                    Mode::Command => {
-                        if (command::handle_command_mode_event(&mut app, key.code)?).is_some() {
-                            return Ok(());
-                        }
-                    }
-                }
-                app.current_window_mut().find_matching_bracket();
+        if let Event::Paste(text) = event {
+            if app.pending_escape.take().is_some() {
+                escape_to_normal_mode(&mut app);
+            }
+            handle_paste(&mut app, &text);
+            continue;
+        }
+
+        if let Event::Key(key) = event {
+            if (key.kind == KeyEventKind::Press || key.kind == KeyEventKind::Repeat) && handle_key_event(&mut app, key)? {
+                app.write_undo_files();
+                app.remove_swap_files();
+                return Ok(());
             }
         }
     }
 }
 
+/// キー入力を1つ処理する。エディタを終了するときは true を返す
+fn handle_key_event(app: &mut App, key: KeyEvent) -> io::Result<bool> {
+    // 確認プロンプトへの回答
+    if app.pending_prompt.is_some() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => app.answer_prompt(true),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => app.answer_prompt(false),
+            _ => {}
+        }
+        return Ok(false);
+    }
+
+    // 続きを待っている Esc の直後に文字が届けば、Esc と合わせて Alt+文字 のエスケープシーケンスとして扱う。
+    // それ以外のキーやタイムアウト後のキーなら、先に Esc を処理してからキーを扱う
+    let esc_timeout = Duration::from_millis(app.config.editor.esc_timeout_ms);
+    let key = match app.pending_escape.take() {
+        Some(since)
+            if since.elapsed() < esc_timeout
+                && matches!(key.code, KeyCode::Char(_))
+                && (key.modifiers - KeyModifiers::SHIFT).is_empty() =>
+        {
+            KeyEvent { modifiers: key.modifiers | KeyModifiers::ALT, ..key }
+        }
+        Some(_) => {
+            escape_to_normal_mode(app);
+            key
+        }
+        None => key,
+    };
+
+    // パネル切り替えの統一処理
+    if handle_panel_toggle(app, key.code, key.modifiers) {
+        return Ok(false);
+    }
+    
+    // フォーカス切り替えの統一処理
+    // コマンドラインでは Tab をパス補完に、挿入モードでは字下げに使う
+    if !matches!(app.mode, Mode::Command | Mode::Insert) && handle_focus_cycling(app, key.code) {
+        return Ok(false);
+    }
+
+    if key.code == KeyCode::Esc {
+        if esc_timeout.is_zero() {
+            escape_to_normal_mode(app);
+        } else {
+            app.pending_escape = Some(Instant::now());
+        }
+        return Ok(false);
+    }
+
+    match app.mode {
+        Mode::Normal => {
+            let register = app.selected_register;
+            normal::handle_normal_mode_event(app, key.code, key.modifiers);
+            // "a の指定は続く1コマンドにだけ効かせる
+            if register.is_some() && app.pending_operator.is_none() && app.pending_key.is_none() && app.pending_count.is_none() {
+                app.selected_register = None;
+            }
+        }
+        // Ctrl+Space は Ctrl+N と同じく補完を開く（表示中なら次の候補へ）
+        Mode::Insert if key.modifiers == KeyModifiers::CONTROL && matches!(key.code, KeyCode::Char('n') | KeyCode::Char('p') | KeyCode::Char(' ')) => {
+            insert::handle_completion_key(app, key.code != KeyCode::Char('p'));
+        }
+        Mode::Insert if app.show_completion && key.modifiers == KeyModifiers::CONTROL && matches!(key.code, KeyCode::Char('y') | KeyCode::Char('e')) => {
+            insert::handle_completion_confirm_key(app, key.code == KeyCode::Char('y'));
+        }
+        Mode::Insert => {
+            insert::handle_insert_mode_event(app, key.code);
+            // 入力に合わせて補完候補を作り直す（表示中なら絞り込む）
+            if matches!(key.code, KeyCode::Char(_) | KeyCode::Backspace) {
+                app.refresh_completions();
+            }
+        }
+        Mode::Visual | Mode::VisualLine | Mode::VisualBlock => visual::handle_visual_mode_event(app, key.code),
+        // 非同期AIリクエストはbg関数で処理
+        Mode::RightPanelInput => right_panel_input::handle_right_panel_input_mode_event(app, key),
+        Mode::Search => search::handle_search_mode_event(app, key.code),
+        Mode::Command => {
+            if (command::handle_command_mode_event(app, key.code)?).is_some() {
+                return Ok(true);
+            }
+        }
+    }
+    app.current_window_mut().find_matching_bracket();
+    Ok(false)
+}
+
+/// どのモードからでもノーマルモードに戻り、入力途中の状態を破棄する
+fn escape_to_normal_mode(app: &mut App) {
+    // ビジュアルモードなどの特殊な状態はここで後始末する
+    if matches!(app.mode, Mode::Visual | Mode::VisualLine | Mode::VisualBlock) {
+        *app.current_window_mut().visual_start_mut() = None;
+    }
+    if app.mode == Mode::Insert {
+        app.end_insert_mode();
+    } else if app.mode == Mode::Normal {
+        // ノーマルモードでの Esc は追加カーソルを解除する
+        app.current_window_mut().clear_extra_cursors();
+    }
+    app.pending_operator = None;
+    app.pending_count = None;
+    app.pending_key = None;
+    app.selected_register = None;
+    app.jump_labels.clear();
+    app.reset_command_completion();
+    app.show_completion = false;
+    app.completion_word = None;
+    app.visual_range = None;
+    app.mode = Mode::Normal;
+}
+
+/// ブラケットペーストで受け取ったテキストを一度に挿入する（1打鍵ずつ処理しないので自動インデントが重ならない）
+fn handle_paste(app: &mut App, text: &str) {
+    match app.mode {
+        Mode::Insert => {
+            // 挿入モード中の貼り付けはその挿入セッションと一緒に元に戻す
+            app.inserted_text.push_str(text);
+            app.current_window_mut().insert_text(text);
+        }
+        Mode::Normal => {
+            let current_window = app.current_window_mut();
+            current_window.save_state();
+            current_window.insert_text(text);
+        }
+        Mode::Command | Mode::Search => {
+            app.command_buffer.push_str(text.lines().next().unwrap_or(""));
+        }
+        Mode::RightPanelInput => {
+            let text = text.replace(['\r', '\n'], " ");
+            let byte_index = app.right_panel_input
+                .grapheme_indices(true)
+                .nth(app.right_panel_input_cursor)
+                .map(|(i, _)| i)
+                .unwrap_or(app.right_panel_input.len());
+            app.right_panel_input.insert_str(byte_index, &text);
+            app.right_panel_input_cursor += text.graphemes(true).count();
+        }
+        Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {}
+    }
+}
+
 /// パネルの表示/非表示を切り替える統一処理
 fn handle_panel_toggle(app: &mut App, key_code: KeyCode, key_modifiers: KeyModifiers) -> bool {
     
@@ -138,6 +284,15 @@ fn handle_panel_toggle(app: &mut App, key_code: KeyCode, key_modifiers: KeyModif
     }
 }
 
+/// タイムアウトまでに続きが届かなかった Esc を処理する
+fn flush_expired_escape(app: &mut App) {
+    let esc_timeout = Duration::from_millis(app.config.editor.esc_timeout_ms);
+    if app.pending_escape.is_some_and(|since| since.elapsed() >= esc_timeout) {
+        app.pending_escape = None;
+        escape_to_normal_mode(app);
+    }
+}
+
 /// パネルフォーカス処理
 fn handle_panel_focus(app: &mut App, action: &str) {
     
@@ -160,7 +315,8 @@ fn handle_panel_focus(app: &mut App, action: &str) {
 
 /// フォーカスの循環切り替えを処理
 fn handle_focus_cycling(app: &mut App, key_code: KeyCode) -> bool {
-    if key_code != KeyCode::Tab {
+    // サイドパネルがどちらも閉じていれば Tab はそのまま各モードに渡す
+    if key_code != KeyCode::Tab || !(app.show_directory || app.show_right_panel) {
         return false;
     }
     
@@ -177,4 +333,231 @@ fn handle_focus_cycling(app: &mut App, key_code: KeyCode) -> bool {
     };
     
     true
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::FocusedPanel;
+    use crate::config::Config;
+
+    fn press(app: &mut App, code: KeyCode) {
+        assert!(!handle_key_event(app, KeyEvent::new(code, KeyModifiers::NONE)).unwrap());
+    }
+
+    /// Esc を押し、続きが届かないままタイムアウトしたことにする
+    fn press_escape(app: &mut App) {
+        press(app, KeyCode::Esc);
+        expire_escape(app);
+        flush_expired_escape(app);
+    }
+
+    fn expire_escape(app: &mut App) {
+        let timeout = Duration::from_millis(app.config.editor.esc_timeout_ms);
+        app.pending_escape = app.pending_escape.map(|since| since.checked_sub(timeout).unwrap_or(since));
+    }
+
+    #[test]
+    fn test_tab_in_insert_mode_edits_buffer() {
+        let mut app = App::with_config(None, Config::default());
+        assert!(app.show_directory);
+        app.focused_panel = FocusedPanel::Editor;
+
+        // ディレクトリパネルが開いていても、挿入モードの Tab は字下げになる
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('a'));
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.current_window().buffer()[0], "a   ");
+        assert_eq!(app.focused_panel, FocusedPanel::Editor);
+
+        // ノーマルモードの Tab は従来どおりパネルのフォーカスを切り替える
+        press_escape(&mut app);
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.focused_panel, FocusedPanel::Directory);
+        assert_eq!(app.current_window().buffer()[0], "a   ");
+    }
+
+    #[test]
+    fn test_repeat_insert_replays_backspace_past_insert_start() {
+        let mut app = App::with_config(None, Config::default());
+        app.focused_panel = FocusedPanel::Editor;
+        *app.current_window_mut().buffer_mut() = vec!["ab".to_string(), "cd".to_string(), "ef".to_string()];
+        *app.current_window_mut().cursor_y_mut() = 1;
+
+        // 挿入開始位置より前を Backspace で消して行を結合してから入力する
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Char('X'));
+        press_escape(&mut app);
+        assert_eq!(app.current_window().buffer(), &vec!["abXcd".to_string(), "ef".to_string()]);
+
+        // `.` も同じように行を結合する
+        *app.current_window_mut().cursor_x_mut() = 0;
+        *app.current_window_mut().cursor_y_mut() = 1;
+        press(&mut app, KeyCode::Char('.'));
+        assert_eq!(app.current_window().buffer(), &vec!["abXcdXef".to_string()]);
+    }
+
+    #[test]
+    fn test_auto_completion_keeps_enter_as_newline() {
+        let mut app = App::with_config(None, Config::default());
+        app.focused_panel = FocusedPanel::Editor;
+        *app.current_window_mut().buffer_mut() = vec!["into inside".to_string(), String::new()];
+        *app.current_window_mut().cursor_y_mut() = 1;
+
+        // 入力中に自動で開いた候補は何も選択しないので、Enter はそのまま改行になる
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.show_completion);
+        assert_eq!(app.selected_completion, None);
+        press(&mut app, KeyCode::Enter);
+        assert!(!app.show_completion);
+        assert_eq!(app.current_window().buffer()[1..], ["in".to_string(), String::new()]);
+    }
+
+    #[test]
+    fn test_non_word_char_accepts_only_chosen_completion() {
+        let mut app = App::with_config(None, Config::default());
+        app.focused_panel = FocusedPanel::Editor;
+        *app.current_window_mut().buffer_mut() = vec!["into inside".to_string(), String::new()];
+        *app.current_window_mut().cursor_y_mut() = 1;
+
+        // 候補を選んでいなければ、空白を入力しても単語は書き換えない
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.show_completion);
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.current_window().buffer()[1], "in ");
+
+        // Ctrl+N で選んだ候補は、続けて入力した空白で確定する
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(!handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL)).unwrap());
+        let chosen = app.completions[app.selected_completion.unwrap()].clone();
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.current_window().buffer()[1], format!("in {} ", chosen));
+    }
+
+    #[test]
+    fn test_swap_recovery_prompt_only_with_swap_file_enabled() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("notes.txt");
+        std::fs::write(&path, "saved\n").unwrap();
+        std::fs::write(temp.path().join(".notes.txt.swp"), "unsaved").unwrap();
+        let filename = path.to_str().unwrap().to_string();
+
+        // スワップファイルが無効なら、残っていても復元を確認しない
+        let app = App::with_config(Some(filename.clone()), Config::default());
+        assert!(app.pending_prompt.is_none());
+        assert!(!app.current_window().has_recoverable_swap());
+
+        let mut config = Config::default();
+        config.editor.swap_file = true;
+        let app = App::with_config(Some(filename), config);
+        assert!(app.pending_prompt.is_some());
+        assert!(app.current_window().has_recoverable_swap());
+    }
+
+    #[test]
+    fn test_focusing_directory_ends_insert_mode() {
+        let mut app = App::with_config(None, Config::default());
+        app.focused_panel = FocusedPanel::Editor;
+
+        // 挿入モードのまま Ctrl+H でディレクトリパネルへ移っても、入力は1回の変更として残る
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('a'));
+        press(&mut app, KeyCode::Char('b'));
+        assert!(!handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('h'), KeyModifiers::CONTROL)).unwrap());
+        assert_eq!(app.focused_panel, FocusedPanel::Directory);
+        assert!(app.mode == Mode::Normal);
+        assert!(matches!(&app.last_change, Some(crate::app::LastChange::Insert { text, .. }) if text == "ab"));
+        assert!(app.current_window_mut().undo());
+        assert_eq!(app.current_window().buffer()[0], "");
+    }
+
+    #[test]
+    fn test_esc_waits_for_escape_sequence_timeout() {
+        let mut app = App::with_config(None, Config::default());
+        app.focused_panel = FocusedPanel::Editor;
+        assert!(app.config.editor.esc_timeout_ms > 0);
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Char('a'));
+
+        // 続きを待っている間は挿入モードのまま。タイムアウトしたらノーマルモードに戻る
+        press(&mut app, KeyCode::Esc);
+        assert!(app.mode == Mode::Insert);
+        flush_expired_escape(&mut app);
+        assert!(app.mode == Mode::Insert);
+        expire_escape(&mut app);
+        flush_expired_escape(&mut app);
+        assert!(app.mode == Mode::Normal);
+
+        // タイムアウト後に届いたキーは、Esc を処理してからノーマルモードのキーとして扱う
+        *app.current_window_mut().cursor_x_mut() = 0;
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Esc);
+        expire_escape(&mut app);
+        press(&mut app, KeyCode::Char('x'));
+        assert!(app.mode == Mode::Normal);
+        assert_eq!(app.current_window().buffer()[0], "");
+
+        // 文字以外のキーならタイムアウト前でもすぐ Esc を処理する
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Left);
+        assert!(app.mode == Mode::Normal);
+    }
+
+    #[test]
+    fn test_char_right_after_esc_is_an_alt_sequence() {
+        let mut config = Config::default();
+        config.editor.esc_timeout_ms = 60_000;
+        let mut app = App::with_config(None, config);
+        app.focused_panel = FocusedPanel::Editor;
+
+        // Esc の直後に届いた文字は Alt+文字 として1つのキーになり、挿入モードは終わらない
+        press(&mut app, KeyCode::Char('i'));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('b'));
+        assert!(app.mode == Mode::Insert);
+        assert!(app.pending_escape.is_none());
+        assert_eq!(app.current_window().buffer()[0], "b");
+
+        // 0 なら待たずに Esc を処理する
+        app.config.editor.esc_timeout_ms = 0;
+        press(&mut app, KeyCode::Esc);
+        assert!(app.mode == Mode::Normal);
+        assert!(app.pending_escape.is_none());
+    }
+
+    #[test]
+    fn test_split_from_directory_focus_follows_split_keeps_focus() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("notes.txt"), "notes\n").unwrap();
+
+        for keeps_focus in [false, true] {
+            for vertical in [true, false] {
+                let mut config = Config::default();
+                config.ui.split_keeps_focus = keeps_focus;
+                let mut app = App::with_config(None, config);
+                app.current_path = temp.path().to_path_buf();
+                app.directory_files = vec!["notes.txt".to_string()];
+                app.selected_directory_index = 0;
+                if vertical {
+                    app.vsplit_selected_item();
+                } else {
+                    app.hsplit_selected_item();
+                }
+
+                // false なら開いたファイルのペイン、true なら元のペインがアクティブになる
+                let active = app.pane_manager.get_active_pane().unwrap();
+                assert!(active.is_leaf());
+                let expected = if keeps_focus { "" } else { "notes" };
+                assert_eq!(app.windows[active.window_index].buffer()[0], expected);
+                assert_eq!(app.focused_panel, FocusedPanel::Editor);
+            }
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use crate::app::App;
+use crate::app::{App, LastChange};
 use crate::app::Mode;
+use crate::pane::SplitDirection;
+use crate::substitute::Substitute;
+use crate::window::LineEnding;
 use crossterm::event::KeyCode;
 use std::io;
 
 pub fn handle_command_mode_event(app: &mut App, key_code: KeyCode) -> io::Result<Option<()>> {
+    if key_code != KeyCode::Tab {
+        app.reset_command_completion();
+    }
     match key_code {
         KeyCode::Char(c) => {
             app.command_buffer.push(c);
@@ -11,44 +17,63 @@ pub fn handle_command_mode_event(app: &mut App, key_code: KeyCode) -> io::Result
         KeyCode::Backspace => {
             app.command_buffer.pop();
         }
+        KeyCode::Tab => {
+            app.complete_command_path();
+        }
         KeyCode::Enter => {
             let command = app.command_buffer.trim().to_string();
             match command.as_str() {
-                "w" => {
-                    let current_window = app.current_window_mut();
-                    current_window.save_file()?;
-                    app.status_message = format!("\"{}\" written", current_window.filename().as_deref().unwrap_or("Untitled"));
+                "w" | "w!" => {
+                    write_current_window(app, None, false, command == "w!");
                 }
-                "q" => {
-                    let active_pane_id = app.pane_manager.get_active_pane_id();
-                    if !app.pane_manager.close_pane(active_pane_id) {
-                        // ルートペインを閉じようとした場合、アプリを終了
+                "q" | "q!" => {
+                    if close_active_pane(app, command == "q!") {
                         return Ok(Some(()));
                     }
                 }
-                "wq" => {
-                    let current_window = app.current_window_mut();
-                    current_window.save_file()?;
-                    app.status_message = format!("\"{}\" written", current_window.filename().as_deref().unwrap_or("Untitled"));
-                    return Ok(Some(()));
+                "bd" | "bd!" | "bdelete" | "bdelete!" => {
+                    app.delete_current_buffer(command.ends_with('!'));
                 }
-                "r" | "reload" => {
-                    let current_window = app.current_window_mut();
-                    match current_window.reload_file() {
-                        Ok(()) => {
-                            app.status_message = format!("\"{}\" reloaded", current_window.filename().as_deref().unwrap_or("Untitled"));
-                        }
-                        Err(e) => {
-                            app.status_message = format!("Failed to reload file: {}", e);
-                        }
+                "wq" | "wq!" => {
+                    if write_current_window(app, None, false, command == "wq!") {
+                        return Ok(Some(()));
                     }
                 }
-                "e" | "edit" => {
-                    // 引数なしの場合は現在のファイルを再読み込み
+                // :42 / :$ で指定した行へ移動
+                address if app.current_window_mut().goto_line_address(address) => {}
+                "sp" | "split" => {
+                    // 同じファイルを別のカーソル位置で表示する
+                    app.split_current_window(false);
+                }
+                "vs" | "vsplit" => {
+                    app.split_current_window(true);
+                }
+                cmd if cmd.starts_with("close ") => {
+                    // 指定方向のペインをすべて閉じる: :close right
+                    let direction = match cmd["close ".len()..].trim() {
+                        "left" => Some((SplitDirection::Horizontal, false)),
+                        "right" => Some((SplitDirection::Horizontal, true)),
+                        "up" | "above" => Some((SplitDirection::Vertical, false)),
+                        "down" | "below" => Some((SplitDirection::Vertical, true)),
+                        _ => None,
+                    };
+                    app.status_message = match direction {
+                        Some((direction, forward)) => {
+                            let closed = app.pane_manager.close_panes_in_direction(direction, forward);
+                            format!("Closed {} pane{}", closed, if closed == 1 { "" } else { "s" })
+                        }
+                        None => "Usage: :close {left|right|up|down}".to_string(),
+                    };
+                }
+                "e" | "edit" if app.current_window().is_modified() => {
+                    app.status_message = "No write since last change (add ! to override)".to_string();
+                }
+                "r" | "reload" | "e" | "edit" | "e!" | "edit!" => {
+                    // 引数なしの :e は現在のファイルを再読み込み（:e! は変更を捨てて再読み込み）
                     let current_window = app.current_window_mut();
                     match current_window.reload_file() {
                         Ok(()) => {
-                            app.status_message = format!("\"{}\" reloaded", current_window.filename().as_deref().unwrap_or("Untitled"));
+                            app.status_message = format!("\"{}\" reloaded", current_window.filename().unwrap_or("Untitled"));
                         }
                         Err(e) => {
                             app.status_message = format!("Failed to reload file: {}", e);
@@ -89,33 +114,152 @@ pub fn handle_command_mode_event(app: &mut App, key_code: KeyCode) -> io::Result
                     // 設定をデフォルトにリセット
                     app.reset_config_to_default();
                 }
+                cmd if cmd.trim_start_matches("'<,'>").starts_with("align") => {
+                    // 選択行を区切り文字で揃える: :'<,'>align =
+                    let delimiter = cmd.trim_start_matches("'<,'>")["align".len()..].trim().to_string();
+                    match app.visual_range {
+                        _ if delimiter.is_empty() => {
+                            app.status_message = "Usage: :'<,'>align {delimiter}".to_string();
+                        }
+                        Some((start, end)) => {
+                            let aligned = app.current_window_mut().align_lines(start, end, &delimiter);
+                            app.status_message = format!("{} lines aligned on \"{}\"", aligned, delimiter);
+                        }
+                        None => {
+                            app.status_message = "No visual selection to align".to_string();
+                        }
+                    }
+                }
+                "wc" | "'<,'>wc" => {
+                    // バッファ全体（ビジュアル選択があればその行）の行数・単語数・文字数
+                    let current_window = app.current_window();
+                    let (start, end) = app.visual_range.unwrap_or((0, current_window.buffer().len().saturating_sub(1)));
+                    let (lines, words, chars) = current_window.count_stats(start, end);
+                    app.status_message = format!("{} lines, {} words, {} chars", lines, words, chars);
+                }
+                cmd if cmd == "rename" || cmd.starts_with("rename ") => {
+                    // カーソル下の識別子をバッファ全体で置換: :rename newname
+                    let new_name = cmd["rename".len()..].trim().to_string();
+                    if new_name.is_empty() {
+                        app.status_message = "Usage: :rename {newname}".to_string();
+                    } else {
+                        let count = app.current_window_mut().rename_word_at_cursor(&new_name);
+                        app.status_message = if count == 0 {
+                            "No identifier under cursor".to_string()
+                        } else {
+                            format!("Renamed {} occurrence{} to {}", count, if count == 1 { "" } else { "s" }, new_name)
+                        };
+                    }
+                }
+                cmd if cmd.strip_prefix("set ").is_some_and(|s| s.starts_with("fileformat") || s.starts_with("ff")) => {
+                    // 改行コードの確認と変換: :set ff? / :set fileformat=unix
+                    app.status_message = match cmd.split_once('=').map(|(_, value)| value.trim()) {
+                        None => format!("fileformat={}", app.current_window().line_ending().name()),
+                        Some(value) => match LineEnding::from_name(value) {
+                            Some(line_ending) if app.current_window_mut().set_line_ending(line_ending) => {
+                                format!("Converted to fileformat={}", line_ending.name())
+                            }
+                            Some(line_ending) => format!("fileformat is already {}", line_ending.name()),
+                            None => format!("Invalid fileformat: {} (use unix/dos)", value),
+                        },
+                    };
+                }
                 cmd if cmd.starts_with("set ") => {
-                    // 設定値を変更: :set key=value
-                    let setting_part = &cmd[4..]; // "set " を除去
+                    // 設定値を変更: :set key=value / :set flag / :set noflag
+                    let setting_part = cmd[4..].trim(); // "set " を除去
                     if let Some(eq_pos) = setting_part.find('=') {
                         let key = setting_part[..eq_pos].trim().to_string();
                         let value = setting_part[eq_pos + 1..].trim().to_string();
                         app.set_config_value(&key, &value);
-                    } else {
+                    } else if setting_part.is_empty() {
                         app.status_message = "Usage: :set key=value".to_string();
+                    } else if let Some(flag) = setting_part.strip_prefix("no") {
+                        app.set_config_value(flag, "false");
+                    } else {
+                        app.set_config_value(setting_part, "true");
                     }
                 }
                 _ => {
                     // ファイル名が指定された場合の処理
-                    if command.starts_with("e ") || command.starts_with("edit ") {
+                    if let Some(path) = command.strip_prefix("w ") {
+                        // :w path で別のファイルへ書き出す（ファイル名が無ければそのファイル名になる）
+                        write_current_window(app, Some(path.trim()), false, false);
+                    } else if let Some(path) = command.strip_prefix("saveas ") {
+                        write_current_window(app, Some(path.trim()), true, false);
+                    } else if let Some(path) = command.strip_prefix("vs ").or_else(|| command.strip_prefix("vsplit ")) {
+                        app.split_open_file(path.trim(), true);
+                    } else if let Some(path) = command.strip_prefix("sp ").or_else(|| command.strip_prefix("split ")) {
+                        app.split_open_file(path.trim(), false);
+                    } else if command.starts_with("e ") || command.starts_with("edit ") {
                         let parts: Vec<&str> = command.split_whitespace().collect();
                         if parts.len() >= 2 {
                             let filename = parts[1..].join(" ");
                             app.open_file(&filename);
                         }
+                    } else if let Some(substitute) = Substitute::parse(&command) {
+                        // :s/foo/bar/ と :%s/foo/bar/g（区切り文字は任意、パターンは正規表現）
+                        app.status_message = match substitute {
+                            Ok(substitute) => match app.current_window_mut().substitute(&substitute) {
+                                (0, _) => format!("Pattern not found: {}", substitute.pattern),
+                                (count, lines) => {
+                                    app.last_change = Some(LastChange::Substitute(substitute));
+                                    format!("{} substitutions on {} lines", count, lines)
+                                }
+                            },
+                            Err(message) => message,
+                        };
                     } else {
                         app.status_message = format!("Not a command: {}", command);
                     }
                 }
             }
+            app.visual_range = None;
             app.mode = Mode::Normal;
         }
         _ => {}
     }
     Ok(None)
-}
\ No newline at end of file
+}
+
+/// :q / :q!: アクティブペインを閉じ、ルートペインならアプリを終了する（終了するなら true）。
+/// 他のペインに表示されていないバッファに未保存の変更があれば、`force` でない限り閉じない
+fn close_active_pane(app: &mut App, force: bool) -> bool {
+    let active_pane_id = app.pane_manager.get_active_pane_id();
+    let window_index = app.get_active_window_index();
+    let shown_elsewhere = app
+        .pane_manager
+        .get_leaf_panes()
+        .iter()
+        .any(|pane| pane.id != active_pane_id && pane.window_index == window_index);
+    if !force && !shown_elsewhere && app.current_window().is_modified() {
+        app.status_message = "No write since last change (add ! to override)".to_string();
+        return false;
+    }
+    // ルートペインを閉じようとした場合、アプリを終了
+    !app.pane_manager.close_pane(active_pane_id)
+}
+
+/// 現在のウィンドウを保存し、結果をステータスに表示する。`path` があればそこへ書き出し、
+/// `save_as` ならウィンドウのファイル名もそのパスに切り替える。`force` ならディスク上の変更を無視して上書きする
+fn write_current_window(app: &mut App, path: Option<&str>, save_as: bool, force: bool) -> bool {
+    let editor_config = app.config.editor.clone();
+    let path = path.map(|path| app.resolve_path(path));
+    let current_window = app.current_window_mut();
+    let result = match path.as_deref() {
+        Some(path) if save_as => current_window.save_file_as(path, &editor_config),
+        Some(path) => current_window.write_file(path, &editor_config),
+        None if force => current_window.overwrite_file(&editor_config),
+        None => current_window.save_file(&editor_config),
+    };
+    match result {
+        Ok(()) => {
+            let written = path.as_deref().or(current_window.filename()).unwrap_or("Untitled");
+            app.status_message = format!("\"{}\" written", written);
+            true
+        }
+        Err(e) => {
+            app.status_message = format!("Failed to write file: {}", e);
+            false
+        }
+    }
+}
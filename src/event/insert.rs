@@ -1,23 +1,54 @@
-use crate::app::App;
+use crate::app::{App, BACKSPACE};
+use crate::utils::{char_class, CharClass};
 use crossterm::event::KeyCode;
-use unicode_segmentation::UnicodeSegmentation;
+
+/// Ctrl+N / Ctrl+P / Ctrl+Space: 補完候補を表示して最初（最後）の候補を選び、表示中なら選択を次（前）に移す
+pub fn handle_completion_key(app: &mut App, forward: bool) {
+    if !app.show_completion {
+        app.update_completions();
+        if !app.show_completion {
+            app.status_message = "No completions".to_string();
+            return;
+        }
+    }
+    let last = app.completions.len() - 1;
+    app.selected_completion = Some(match app.selected_completion {
+        None if forward => 0,
+        None => last,
+        Some(i) if forward => (i + 1) % app.completions.len(),
+        Some(i) => i.checked_sub(1).unwrap_or(last),
+    });
+}
+
+/// Ctrl+Y / Ctrl+E: 表示中の補完候補を確定する / 補完を取り消す
+pub fn handle_completion_confirm_key(app: &mut App, accept: bool) {
+    if accept {
+        app.apply_completion();
+    } else {
+        app.show_completion = false;
+    }
+}
 
 pub fn handle_insert_mode_event(app: &mut App, key_code: KeyCode) {
     if app.show_completion {
+        let selected = app.selected_completion.is_some();
         match key_code {
+            // 候補を選んでいれば確定する。選んでいなければ補完を閉じ、通常の改行・字下げとして扱う
             KeyCode::Tab | KeyCode::Enter => {
                 app.apply_completion();
-                return;
+                if selected {
+                    return;
+                }
             }
             KeyCode::Up => {
                 if !app.completions.is_empty() {
-                    app.selected_completion = app.selected_completion.saturating_sub(1);
+                    app.selected_completion = Some(app.selected_completion.map_or(app.completions.len() - 1, |i| i.saturating_sub(1)));
                 }
                 return;
             }
             KeyCode::Down => {
                 if !app.completions.is_empty() {
-                    app.selected_completion = (app.selected_completion + 1).min(app.completions.len() - 1);
+                    app.selected_completion = Some(app.selected_completion.map_or(0, |i| (i + 1).min(app.completions.len() - 1)));
                 }
                 return;
             }
@@ -25,122 +56,69 @@ pub fn handle_insert_mode_event(app: &mut App, key_code: KeyCode) {
                 app.show_completion = false;
                 return;
             }
+            // 単語以外の文字を入力したら、選んでいた候補を確定してからその文字を入力する
+            KeyCode::Char(c) if char_class(c.encode_utf8(&mut [0; 4])) != CharClass::Word => {
+                app.apply_completion();
+            }
             _ => {}
         }
     }
 
-    let indent_width = app.config.editor.indent_width;
-    let _tab_size = app.config.editor.tab_size;
+    // `.` で繰り返せるよう入力したテキストを記録する
+    match key_code {
+        KeyCode::Char('\n') | KeyCode::Char('\r') | KeyCode::Enter => app.inserted_text.push('\n'),
+        KeyCode::Tab => app.inserted_text.push('\t'),
+        KeyCode::Char(c) => app.inserted_text.push(c),
+        KeyCode::Backspace => app.inserted_text.push(BACKSPACE),
+        _ => {}
+    }
+
+    let indent_unit = app.indent_unit();
+    let auto_indent = app.config.editor.auto_indent;
+    let auto_pairs = app.config.editor.auto_pairs;
     let _show_line_numbers = app.config.editor.show_line_numbers;
     let current_window = app.current_window_mut();
+
+    // 複数カーソルがある場合は文字入力と削除を全カーソルに適用する
+    if current_window.has_extra_cursors() {
+        match key_code {
+            KeyCode::Char(c) if c != '\n' && c != '\r' => {
+                current_window.insert_char_at_cursors(c);
+                return;
+            }
+            KeyCode::Backspace => {
+                current_window.backspace_at_cursors();
+                return;
+            }
+            // 改行などは主カーソルのみで扱う
+            _ => current_window.clear_extra_cursors(),
+        }
+    }
+
     match key_code {
         KeyCode::Char(c) => {
             if c == '\n' || c == '\r' {
                 // 改行処理
-                let y = current_window.cursor_y();
-                let x = current_window.cursor_x();
-                let current_line_ref = &mut current_window.buffer_mut()[y];
-                let byte_index = current_line_ref
-                    .grapheme_indices(true)
-                    .nth(x)
-                    .map(|(i, _)| i)
-                    .unwrap_or(current_line_ref.len());
-                let new_line = current_line_ref.split_off(byte_index);
-
-                // 前の行の先頭のスペースを取得
-                let mut indent = current_line_ref.chars()
-                    .take_while(|&ch| ch == ' ')
-                    .collect::<String>();
-
-                // カーソル位置の直前の文字を取得
-                let _char_before_cursor = current_line_ref.graphemes(true).nth(x.saturating_sub(1));
-
-                // 前の行の末尾が開き括弧の場合、インデントを深くする
-                if current_line_ref.ends_with('{') || current_line_ref.ends_with('[') || current_line_ref.ends_with('(') {
-                    let indent_spaces = " ".repeat(indent_width);
-                    indent.push_str(&indent_spaces);
-                } else if new_line.starts_with('}') || new_line.starts_with(')') || new_line.starts_with(']') {
-                    // 新しい行の先頭が閉じ括弧の場合、インデントを一段浅くする
-                    if indent.len() >= indent_width {
-                        indent.truncate(indent.len() - indent_width);
-                    }
-                }
-
-                let indented_new_line = format!("{}{}", indent, new_line);
-                current_window.buffer_mut().insert(y + 1, indented_new_line);
-                *current_window.cursor_y_mut() += 1;
-                *current_window.cursor_x_mut() = indent.len();
-                current_window.on_line_inserted(current_window.cursor_y());
-                // スクロール処理を即座に実行
+                current_window.insert_newline(&indent_unit, auto_indent);
             } else {
                 // 通常の文字挿入
-                let y = current_window.cursor_y();
-                let x = current_window.cursor_x();
-                if current_window.buffer().len() <= y{
-                    current_window.buffer_mut().resize(y + 1, String::new());
-                };
-                let line = &mut current_window.buffer_mut()[y];
-                let byte_index = line.grapheme_indices(true).nth(x).map(|(i, _)| i).unwrap_or(line.len());
-                line.insert(byte_index, c);
-                *current_window.cursor_x_mut() += 1;
-                current_window.on_char_inserted(y, x, c);
+                current_window.insert_char(c, auto_pairs);
             }
         }
         KeyCode::Backspace => {
-            let y = current_window.cursor_y();
-            let x = current_window.cursor_x();
-            if x > 0 {
-                let line = &mut current_window.buffer_mut()[y];
-                let prev_grapheme = line.grapheme_indices(true).nth(x - 1).map(|(i, _)| i).unwrap_or(0);
-                let removed = line[prev_grapheme..].chars().next().unwrap_or('\0');
-                line.drain(prev_grapheme..prev_grapheme + removed.len_utf8());
-                *current_window.cursor_x_mut() -= 1;
-                current_window.on_char_deleted(y, x - 1, removed);
-            } else if y > 0 {
-                // 行頭なら前の行と結合
-                let prev_line_len = current_window.buffer_mut()[y - 1].graphemes(true).count();
-                let current_line = current_window.buffer_mut().remove(y);
-                let prev_line = &mut current_window.buffer_mut()[y - 1];
-                prev_line.push_str(&current_line);
-                *current_window.cursor_y_mut() -= 1;
-                *current_window.cursor_x_mut() = prev_line_len;
-                current_window.on_line_deleted(y);
-            }
+            current_window.backspace(auto_pairs);
         }
         KeyCode::Enter => {
             // Enterキーでの改行処理
-            let y = current_window.cursor_y();
-            let x = current_window.cursor_x();
-            let current_line_ref = &mut current_window.buffer_mut()[y];
-            let byte_index = current_line_ref
-                .grapheme_indices(true)
-                .nth(x)
-                .map(|(i, _)| i)
-                .unwrap_or(current_line_ref.len());
-            let new_line = current_line_ref.split_off(byte_index);
-
-            // 前の行の先頭のスペースを取得
-            let mut indent = current_line_ref.chars()
-                .take_while(|&ch| ch == ' ')
-                .collect::<String>();
-
-            // 前の行の末尾が開き括弧の場合、インデントを深くする
-            if current_line_ref.ends_with('{') || current_line_ref.ends_with('[') || current_line_ref.ends_with('(') {
-                let indent_spaces = " ".repeat(indent_width);
-                indent.push_str(&indent_spaces);
-            } else if new_line.starts_with('}') || new_line.starts_with(')') || new_line.starts_with(']') {
-                // 新しい行の先頭が閉じ括弧の場合、インデントを一段浅くする
-                if indent.len() >= indent_width {
-                    indent.truncate(indent.len() - indent_width);
-                }
-            }
-
-            let indented_new_line = format!("{}{}", indent, new_line);
-            current_window.buffer_mut().insert(y + 1, indented_new_line);
-            *current_window.cursor_y_mut() += 1;
-            *current_window.cursor_x_mut() = indent.len();
-            current_window.on_line_inserted(current_window.cursor_y());
+            current_window.insert_newline(&indent_unit, auto_indent);
+        }
+        KeyCode::Tab => {
+            // 次のタブストップまで空白（expand_tab が無効ならタブ）を挿入する
+            current_window.insert_tab(&indent_unit);
+        }
+        KeyCode::BackTab => {
+            current_window.dedent_line(&indent_unit);
         }
         _ => {}
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,367 @@
-use crate::app::{App, FocusedPanel};
+use crate::app::{App, FocusedPanel, InsertEntry, LastChange, PendingOperator, BACKSPACE};
+use crate::window::{CharSearch, Motion, ScrollAlign, YankKind};
 use crate::app::Mode;
+use crate::constants::editor;
+use crate::registers;
+use super::insert;
 use crossterm::event::{KeyCode, KeyModifiers};
 use unicode_segmentation::UnicodeSegmentation;
 
-    
+/// オペレータ待ち状態で押されたキーを処理する
+fn handle_pending_operator(app: &mut App, operator: PendingOperator, key_code: KeyCode) {
+    let action = match key_code {
+        KeyCode::Char(c) => app.config.key_bindings.normal.get(&c.to_string()).cloned(),
+        _ => None,
+    };
+
+    // オペレータの前に入力された回数（例: 3dd）
+    let count = app.pending_count.take().unwrap_or(1).max(1);
+
+    match (operator, action.as_deref()) {
+        (PendingOperator::Delete, Some("operator_delete")) => {
+            delete_lines(app, count);
+        }
+        (PendingOperator::Delete, Some(motion @ ("word_forward" | "word_end" | "line_end"))) => {
+            // dw / de / d$: カーソルからモーションの移動先までを文字単位で削除する
+            let motion = match motion {
+                "word_forward" => Motion::WordForward,
+                "word_end" => Motion::WordEnd,
+                _ => Motion::LineEnd,
+            };
+            delete_to_motion(app, motion, count);
+        }
+        (PendingOperator::Change, Some("operator_change")) => {
+            // cc: インデントを残して行を変更する
+            enter_insert_mode(app, InsertEntry::ChangeLines(count));
+        }
+        (PendingOperator::Change, Some(motion @ ("word_forward" | "word_end" | "line_end"))) => {
+            // cw / ce / c$: 範囲を削除して挿入モードに入る
+            let motion = match motion {
+                "word_forward" => Motion::WordForward,
+                "word_end" => Motion::WordEnd,
+                _ => Motion::LineEnd,
+            };
+            enter_insert_mode(app, InsertEntry::ChangeToMotion(motion, count));
+        }
+        (PendingOperator::Yank, Some("operator_yank")) => {
+            yank_lines(app, count);
+        }
+        _ => {}
+    }
+}
+
+/// yy / Y: 現在行から `count` 行を行単位でヤンクする
+fn yank_lines(app: &mut App, count: usize) {
+    let current_window = app.current_window();
+    let yanked = current_window.yank_lines(current_window.cursor_y(), count);
+    let yanked_lines = yanked.lines().count();
+    app.set_yanked_text(yanked, YankKind::LineWise);
+    app.status_message = format!("{} line{} yanked", yanked_lines, if yanked_lines == 1 { "" } else { "s" });
+}
+
+/// dd: 現在行から `count` 行を削除して行単位でヤンクする
+fn delete_lines(app: &mut App, count: usize) {
+    let current_window = app.current_window_mut();
+    current_window.save_state();
+    let cy = current_window.cursor_y();
+    let deleted = current_window.delete_lines(cy, count);
+    app.set_yanked_text(deleted, YankKind::LineWise);
+    app.last_change = Some(LastChange::DeleteLines(count));
+}
+
+/// `>>` / `<<`: カーソル行から `count` 行のインデントを1段深く（浅く）する
+fn shift_lines(app: &mut App, count: usize, right: bool) {
+    let indent_unit = app.indent_unit();
+    let current_window = app.current_window_mut();
+    let cy = current_window.cursor_y();
+    current_window.shift_lines(cy, cy + count - 1, &indent_unit, right);
+    app.last_change = Some(LastChange::ShiftLines { count, right });
+}
+
+/// J / gJ: カーソル行から `count` 行を結合する
+fn join_lines(app: &mut App, count: usize, with_space: bool) {
+    if app.current_window_mut().join_lines(count, with_space) {
+        app.last_change = Some(LastChange::JoinLines { count, with_space });
+    }
+}
+
+/// gcc: カーソル行から `count` 行の行コメントを切り替える
+fn toggle_comment(app: &mut App, count: usize) {
+    let current_window = app.current_window_mut();
+    let cy = current_window.cursor_y();
+    if current_window.toggle_comment(cy, cy + count - 1) {
+        app.last_change = Some(LastChange::ToggleComment(count));
+    }
+}
+
+/// dw / de / d$: 削除したテキストを文字単位でヤンクする
+fn delete_to_motion(app: &mut App, motion: Motion, count: usize) {
+    let deleted = app.current_window_mut().delete_to_motion(motion, count);
+    if !deleted.is_empty() {
+        app.set_yanked_text(deleted, YankKind::CharWise);
+    }
+    app.last_change = Some(LastChange::DeleteToMotion { motion, count });
+}
+
+/// r: カーソル下の文字を置き換える
+fn replace_char(app: &mut App, replacement: char) {
+    if app.current_window_mut().replace_char(replacement) {
+        app.last_change = Some(LastChange::ReplaceChar(replacement));
+    } else {
+        app.status_message = "No character to replace".to_string();
+    }
+}
+
+/// x: カーソル位置から `count` 文字を削除する
+fn delete_chars(app: &mut App, count: usize) {
+    app.current_window_mut().delete_chars(count);
+    app.last_change = Some(LastChange::DeleteChars(count));
+}
+
+/// i / a / o / O / c: カーソルを入り方に合わせて動かし（c なら範囲を削除し）、挿入モードを開始する
+fn enter_insert_mode(app: &mut App, entry: InsertEntry) {
+    let auto_indent = app.config.editor.auto_indent;
+    let current_window = app.current_window_mut();
+    // 変更による削除も含めて1回の undo で戻せるよう、先に状態を保存する
+    current_window.start_insert_mode();
+    let changed = match entry {
+        InsertEntry::Insert => None,
+        InsertEntry::Append => {
+            let cy = current_window.cursor_y();
+            let grapheme_count = current_window.buffer()[cy].graphemes(true).count();
+            if current_window.cursor_x() < grapheme_count {
+                *current_window.cursor_x_mut() += 1;
+            }
+            None
+        }
+        InsertEntry::OpenLine { above } => {
+            current_window.open_new_line(above, auto_indent);
+            None
+        }
+        InsertEntry::ChangeToMotion(motion, count) => Some((current_window.change_to_motion(motion, count), YankKind::CharWise)),
+        InsertEntry::ChangeLines(count) => Some((current_window.change_lines(count), YankKind::LineWise)),
+    };
+    if let Some((text, kind)) = changed.filter(|(text, _)| !text.is_empty()) {
+        app.set_yanked_text(text, kind);
+    }
+    app.insert_entry = Some(entry);
+    app.inserted_text.clear();
+    app.mode = Mode::Insert;
+}
+
+/// ペインの枠を除いた表示可能な行数
+fn visible_height(app: &App) -> usize {
+    app.pane_manager
+        .get_active_pane()
+        .and_then(|pane| pane.rect)
+        .map_or(20, |rect| rect.height.saturating_sub(2) as usize)
+}
+
+/// s{char}: 表示中の `target` にラベルを付ける（候補が1つならそのまま移動する）
+fn start_char_jump(app: &mut App, target: char) {
+    let height = visible_height(app);
+    let targets = app.current_window().jump_targets(target, height);
+    match targets.as_slice() {
+        [] => app.status_message = format!("No visible match: {}", target),
+        &[(x, y)] => {
+            let current_window = app.current_window_mut();
+            *current_window.cursor_x_mut() = x;
+            *current_window.cursor_y_mut() = y;
+        }
+        _ => {
+            app.jump_labels = editor::JUMP_LABELS.chars().zip(targets).map(|(label, (x, y))| (label, x, y)).collect();
+        }
+    }
+}
+
+/// `.`: 直前の変更を現在のカーソル位置で繰り返す（それぞれ個別に undo できる）。
+/// 回数を付けると x / dd / dw などはその回数で、それ以外は回数分繰り返す
+fn repeat_last_change(app: &mut App, count: Option<usize>) {
+    let Some(change) = app.last_change.clone() else {
+        app.status_message = "No previous change to repeat".to_string();
+        return;
+    };
+    match change {
+        LastChange::DeleteChars(n) => delete_chars(app, count.unwrap_or(n)),
+        LastChange::DeleteLines(n) => delete_lines(app, count.unwrap_or(n)),
+        LastChange::DeleteToMotion { motion, count: n } => delete_to_motion(app, motion, count.unwrap_or(n)),
+        LastChange::ShiftLines { count: n, right } => shift_lines(app, count.unwrap_or(n), right),
+        LastChange::JoinLines { count: n, with_space } => join_lines(app, count.unwrap_or(n), with_space),
+        LastChange::ToggleComment(n) => toggle_comment(app, count.unwrap_or(n)),
+        change => {
+            for _ in 0..count.unwrap_or(1) {
+                repeat_change(app, change.clone());
+            }
+        }
+    }
+}
+
+fn repeat_change(app: &mut App, change: LastChange) {
+    match change {
+        LastChange::DeleteChars(count) => delete_chars(app, count),
+        LastChange::ReplaceChar(replacement) => replace_char(app, replacement),
+        LastChange::DeleteLines(count) => delete_lines(app, count),
+        LastChange::DeleteToMotion { motion, count } => delete_to_motion(app, motion, count),
+        LastChange::ShiftLines { count, right } => shift_lines(app, count, right),
+        LastChange::JoinLines { count, with_space } => join_lines(app, count, with_space),
+        LastChange::ToggleComment(count) => toggle_comment(app, count),
+        LastChange::Paste { text, kind, before } => app.current_window_mut().paste(&text, kind, before),
+        LastChange::Insert { entry, text } => {
+            enter_insert_mode(app, entry);
+            for c in text.chars() {
+                let key_code = match c {
+                    '\n' => KeyCode::Enter,
+                    '\t' => KeyCode::Tab,
+                    BACKSPACE => KeyCode::Backspace,
+                    c => KeyCode::Char(c),
+                };
+                insert::handle_insert_mode_event(app, key_code);
+            }
+            app.end_insert_mode();
+            app.mode = Mode::Normal;
+        }
+        LastChange::Substitute(substitute) => {
+            if app.current_window_mut().substitute(&substitute).0 == 0 {
+                app.status_message = format!("Pattern not found: {}", substitute.pattern);
+            }
+        }
+    }
+}
+
 pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers: KeyModifiers) {
         let _show_line_numbers = app.config.editor.show_line_numbers;
+    let virtual_edit = app.config.editor.virtual_edit;
+
+    // 文字ジャンプのラベル表示中なら、このキーをラベルとして移動する（ラベル以外のキーは取り消す）
+    if !app.jump_labels.is_empty() {
+        let labels = std::mem::take(&mut app.jump_labels);
+        if let Some(&(_, x, y)) = labels.iter().find(|&&(label, _, _)| KeyCode::Char(label) == key_code) {
+            let current_window = app.current_window_mut();
+            *current_window.cursor_x_mut() = x;
+            *current_window.cursor_y_mut() = y;
+        }
+        return;
+    }
+
+    // オペレータ待ち状態なら、このキーで操作を確定する
+    if let Some(operator) = app.pending_operator.take() {
+        handle_pending_operator(app, operator, key_code);
+        return;
+    }
     
+    // `g` の後に続くキーで2打鍵コマンドを確定する
+    if let Some(prefix) = app.pending_key.take() {
+        let count = app.pending_count.take();
+        if prefix == 'r' {
+            // r の次に入力した文字でカーソル下の文字を置き換える（r<Enter> は行を分割する）
+            match key_code {
+                KeyCode::Char(c) => replace_char(app, c),
+                KeyCode::Enter => replace_char(app, '\n'),
+                _ => {}
+            }
+            return;
+        }
+        if let ('f' | 'F' | 't' | 'T', KeyCode::Char(target)) = (prefix, key_code) {
+            let search = CharSearch { target, forward: prefix.is_lowercase(), till: prefix.eq_ignore_ascii_case(&'t') };
+            app.current_window_mut().find_char(search, count.unwrap_or(1), false);
+            app.last_char_search = Some(search);
+            return;
+        }
+        let action = match key_code {
+            KeyCode::Char(c) => app.config.key_bindings.normal.get(&c.to_string()).cloned(),
+            _ => None,
+        };
+        match (prefix, key_code, action.as_deref()) {
+            ('g', _, Some("g_prefix")) => {
+                // gg: 先頭行（回数指定があればその行）へ移動
+                app.current_window_mut().goto_line(count.unwrap_or(1));
+            }
+            // gj / gk: 折り返した表示行単位で上下に移動
+            ('g', KeyCode::Char('j'), _) => app.current_window_mut().move_display_rows(true, count.unwrap_or(1)),
+            ('g', KeyCode::Char('k'), _) => app.current_window_mut().move_display_rows(false, count.unwrap_or(1)),
+            // gJ: 空白を挟まずに行を結合
+            ('g', KeyCode::Char('J'), _) => join_lines(app, count.unwrap_or(1), false),
+            // gc の後の c で gcc（行コメントの切り替え）になる
+            ('g', KeyCode::Char('c'), _) => {
+                app.pending_key = Some('c');
+                app.pending_count = count;
+            }
+            ('c', KeyCode::Char('c'), _) => toggle_comment(app, count.unwrap_or(1).max(1)),
+            // zt / zz / zb: カーソル行を画面の上端・中央・下端に合わせてスクロール
+            ('z', KeyCode::Char('t'), _) => app.current_window_mut().scroll_cursor_line(ScrollAlign::Top),
+            ('z', KeyCode::Char('z'), _) => app.current_window_mut().scroll_cursor_line(ScrollAlign::Center),
+            ('z', KeyCode::Char('b'), _) => app.current_window_mut().scroll_cursor_line(ScrollAlign::Bottom),
+            // ]s / [s: 次（前）の綴りの誤りへ移動
+            (']', KeyCode::Char('s'), _) => app.jump_to_misspelling(true),
+            ('[', KeyCode::Char('s'), _) => app.jump_to_misspelling(false),
+            ('s', KeyCode::Char(target), _) => start_char_jump(app, target),
+            ('>', _, Some("indent_prefix")) => shift_lines(app, count.unwrap_or(1).max(1), true),
+            ('<', _, Some("dedent_prefix")) => shift_lines(app, count.unwrap_or(1).max(1), false),
+            // "{a-z}: 次のヤンク・削除・貼り付けで使うレジスタを選ぶ（回数指定は引き継ぐ）
+            ('"', KeyCode::Char(name), _) if registers::is_register_name(name) => {
+                app.selected_register = Some(name);
+                app.pending_count = count;
+            }
+            // m{a-z} / `{a-z}: マークの記録と移動
+            ('m', KeyCode::Char(name), _) if name.is_ascii_lowercase() => {
+                app.current_window_mut().set_mark(name);
+            }
+            ('`', KeyCode::Char(name), _) if name.is_ascii_lowercase() => {
+                let jumped = app.current_window_mut().jump_to_mark(name);
+                if !jumped {
+                    app.status_message = format!("Mark not set: {}", name);
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    // 数字は回数指定として蓄積する（先頭の 0 は除く）
+    if let KeyCode::Char(c) = key_code {
+        if let Some(digit) = c.to_digit(10) {
+            if digit != 0 || app.pending_count.is_some() {
+                let count = app.pending_count.unwrap_or(0);
+                app.pending_count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return;
+            }
+        }
+    }
+    let count = app.pending_count.take();
+    let repeat = count.unwrap_or(1).max(1);
+
+    // Ctrl+D: 現在の単語の次の出現位置にカーソルを追加
+    if key_modifiers == KeyModifiers::CONTROL && key_code == KeyCode::Char('d') {
+        let current_window = app.current_window_mut();
+        if current_window.add_cursor_at_next_occurrence() {
+            let cursor_count = current_window.extra_cursors().len() + 1;
+            app.status_message = format!("{} cursors", cursor_count);
+        } else {
+            app.status_message = "No more matches".to_string();
+        }
+        return;
+    }
+
+    // Ctrl+R: やり直し（r は置換に割り当てているので先に処理する）
+    if key_modifiers == KeyModifiers::CONTROL && key_code == KeyCode::Char('r') {
+        let current_window = app.current_window_mut();
+        if current_window.redo() {
+            app.status_message = "Redone".to_string();
+        } else {
+            app.status_message = "Nothing to redo".to_string();
+        }
+        return;
+    }
+
+    // Ctrl+V で矩形選択のビジュアルモードに入る
+    if key_modifiers == KeyModifiers::CONTROL && key_code == KeyCode::Char('v') {
+        let current_window = app.current_window_mut();
+        let cursor = (current_window.cursor_x(), current_window.cursor_y());
+        *current_window.visual_start_mut() = Some(cursor);
+        app.mode = Mode::VisualBlock;
+        return;
+    }
+
     // Shift+H と Shift+L の処理
     if key_modifiers == KeyModifiers::SHIFT {
         match key_code {
@@ -20,17 +375,7 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
             }
             KeyCode::Char('L') => {
                 if app.focused_panel == FocusedPanel::Editor {
-                    // 現在のペインの表示可能な高さを取得
-                    let visible_height = if let Some(active_pane) = app.pane_manager.get_active_pane() {
-                        if let Some(rect) = active_pane.rect {
-                            rect.height.saturating_sub(2) as usize // ボーダーを除く
-                        } else {
-                            20 // デフォルト値
-                        }
-                    } else {
-                        20 // デフォルト値
-                    };
-                    
+                    let visible_height = visible_height(app);
                     let current_window = app.current_window_mut();
                     current_window.move_to_screen_bottom(visible_height);
                     app.status_message = "Moved to screen bottom".to_string();
@@ -40,6 +385,7 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
             _ => {}
         }
     }
+
     if let KeyCode::Char(c) = key_code {
         if let Some(action) = app.config.key_bindings.normal.get(&c.to_string()) {
             let visible_height = if app.show_directory && app.config.ui.directory_pane_floating {
@@ -55,10 +401,8 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         app.activate_left_pane();
                     } else {
                         let current_window = app.current_window_mut();
-                        if *current_window.cursor_x_mut() > 0 {
-                            *current_window.cursor_x_mut() -= 1;
-                            // スクロール処理を即座に実行
-                        }
+                        let cx = current_window.cursor_x();
+                        *current_window.cursor_x_mut() = cx.saturating_sub(repeat);
                     }
                 }
                 "move_down" => {
@@ -75,11 +419,13 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         let cy = *current_window.cursor_y_mut();
 
                         if len > 0 && cy < len - 1 {
-                            *current_window.cursor_y_mut() += 1;
+                            *current_window.cursor_y_mut() = (cy + repeat).min(len - 1);
                             let cy2 = *current_window.cursor_y_mut();
                             let current_line_len_graphemes = current_window.buffer()[cy2].graphemes(true).count();
                             let cx = *current_window.cursor_x_mut();
-                            *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                            if !virtual_edit {
+                                *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                            }
                             // スクロール処理を即座に実行
                         }
                         app.status_message = format!("EDITOR DOWN: dir={}, right={}, focus={:?}", app.show_directory, app.show_right_panel, app.focused_panel);
@@ -97,11 +443,13 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         let current_window = app.current_window_mut();
                         let cy = *current_window.cursor_y_mut();
                         if cy > 0 {
-                            *current_window.cursor_y_mut() -= 1;
+                            *current_window.cursor_y_mut() = cy.saturating_sub(repeat);
                             let cy2 = *current_window.cursor_y_mut();
                             let current_line_len_graphemes = current_window.buffer()[cy2].graphemes(true).count();
                             let cx = *current_window.cursor_x_mut();
-                            *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                            if !virtual_edit {
+                                *current_window.cursor_x_mut() = cx.min(current_line_len_graphemes);
+                            }
                             // スクロール処理を即座に実行
                         }
                         app.status_message = format!("EDITOR UP: dir={}, right={}, focus={:?}", app.show_directory, app.show_right_panel, app.focused_panel);
@@ -116,9 +464,100 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         let current_line = &current_window.buffer()[cy];
                         let grapheme_count = current_line.graphemes(true).count();
                         let cx = *current_window.cursor_x_mut();
-                        if cx < grapheme_count.saturating_sub(1) {
-                            *current_window.cursor_x_mut() += 1;
-                            // スクロール処理を即座に実行
+                        let new_cx = if virtual_edit {
+                            cx + repeat
+                        } else {
+                            (cx + repeat).min(grapheme_count.saturating_sub(1)).max(cx)
+                        };
+                        *current_window.cursor_x_mut() = new_cx;
+                    }
+                }
+                "word_forward" => {
+                    let current_window = app.current_window_mut();
+                    for _ in 0..repeat {
+                        current_window.move_word_forward();
+                    }
+                }
+                "word_backward" => {
+                    let current_window = app.current_window_mut();
+                    for _ in 0..repeat {
+                        current_window.move_word_backward();
+                    }
+                }
+                "word_end" => {
+                    let current_window = app.current_window_mut();
+                    for _ in 0..repeat {
+                        current_window.move_word_end();
+                    }
+                }
+                "line_start" => {
+                    app.current_window_mut().move_to_line_start();
+                }
+                "line_end" => {
+                    app.current_window_mut().move_to_line_end();
+                }
+                "first_non_blank" => {
+                    app.current_window_mut().move_to_first_non_blank();
+                }
+                "g_prefix" => {
+                    app.pending_key = Some('g');
+                    app.pending_count = count;
+                }
+                "z_prefix" => {
+                    app.pending_key = Some('z');
+                }
+                "jump_to_char" => {
+                    app.pending_key = Some('s');
+                }
+                "find_char" | "find_char_backward" | "till_char" | "till_char_backward" => {
+                    // 次に入力した文字を探す（f / F / t / T の区別はプレフィックスで覚えておく）
+                    app.pending_key = match action.as_str() {
+                        "find_char" => Some('f'),
+                        "find_char_backward" => Some('F'),
+                        "till_char" => Some('t'),
+                        _ => Some('T'),
+                    };
+                    app.pending_count = count;
+                }
+                "repeat_char_search" | "repeat_char_search_reverse" => {
+                    if let Some(search) = app.last_char_search {
+                        let forward = search.forward == (action == "repeat_char_search");
+                        app.current_window_mut().find_char(CharSearch { forward, ..search }, repeat, true);
+                    }
+                }
+                "set_mark" => {
+                    app.pending_key = Some('m');
+                }
+                "jump_to_mark" => {
+                    app.pending_key = Some('`');
+                }
+                "next_prefix" => {
+                    app.pending_key = Some(']');
+                }
+                "indent_prefix" => {
+                    app.pending_key = Some('>');
+                    app.pending_count = count;
+                }
+                "dedent_prefix" => {
+                    app.pending_key = Some('<');
+                    app.pending_count = count;
+                }
+                "prev_prefix" => {
+                    app.pending_key = Some('[');
+                }
+                "goto_line" => {
+                    // G: 最終行（回数指定があればその行）へ移動
+                    let current_window = app.current_window_mut();
+                    let last_line = current_window.buffer().len();
+                    current_window.goto_line(count.unwrap_or(last_line));
+                }
+                "jump_percent" => {
+                    let current_window = app.current_window_mut();
+                    match count {
+                        Some(percent) => current_window.jump_to_percentage(percent),
+                        None => {
+                            // 回数指定なしは対応する括弧へ移動
+                            current_window.jump_to_matching_bracket();
                         }
                     }
                 }
@@ -132,90 +571,56 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         *app.current_window_mut().visual_start_mut() = Some((cursor_x, cursor_y));
                     }
                 }
-                "hsplit" => {
-                    if app.show_directory {
-                        app.hsplit_selected_item();
-                    }
+                "mode_visual_line" => {
+                    let current_window = app.current_window_mut();
+                    let cursor = (current_window.cursor_x(), current_window.cursor_y());
+                    *current_window.visual_start_mut() = Some(cursor);
+                    app.mode = Mode::VisualLine;
+                }
+                "hsplit" if app.show_directory => {
+                    app.hsplit_selected_item();
                 }
                 "delete_char" => {
-                    let current_window = app.current_window_mut();
-                    current_window.save_state(); // 変更前の状態を保存
-                    let cy = *current_window.cursor_y_mut();
-                    let mut graphemes: Vec<String> = current_window.buffer()[cy].graphemes(true).map(String::from).collect();
-                    let cx = *current_window.cursor_x_mut();
-                    if cx < graphemes.len() {
-                        let deleted_char = graphemes[cx].chars().next().unwrap_or(' ');
-                        graphemes.remove(cx);
-                        current_window.buffer_mut()[cy] = graphemes.join("");
-                        let new_cx = if cx >= graphemes.len() && !graphemes.is_empty() {
-                            graphemes.len().saturating_sub(1)
-                        } else if graphemes.is_empty() {
-                            0
-                        } else {
-                            cx
-                        };
-                        *current_window.cursor_x_mut() = new_cx;
-                        current_window.on_char_deleted(cy, new_cx, deleted_char);
-                    }
+                    delete_chars(app, repeat);
                 }
                 "mode_insert" => {
                     if app.show_right_panel && app.focused_panel == FocusedPanel::RightPanel {
                         app.mode = Mode::RightPanelInput;
                     } else {
-                        let current_window = app.current_window_mut();
-                        current_window.start_insert_mode(); // 挿入モード開始時に状態を保存
-                        app.mode = Mode::Insert;
+                        enter_insert_mode(app, InsertEntry::Insert);
                     }
                 }
                 "append" => {
-                    let current_window_ref = app.current_window_mut();
-                    let cy = *current_window_ref.cursor_y_mut();
-                    let grapheme_count = current_window_ref.buffer()[cy].graphemes(true).count();
-                    let cx = *current_window_ref.cursor_x_mut();
-                    if cx < grapheme_count {
-                        *current_window_ref.cursor_x_mut() += 1;
-                    }
-                    current_window_ref.start_insert_mode(); // 挿入モード開始時に状態を保存
-                    app.mode = Mode::Insert;
+                    enter_insert_mode(app, InsertEntry::Append);
+                }
+                "repeat_last_change" => {
+                    repeat_last_change(app, count);
                 }
                 "mode_command" => {
                     app.mode = Mode::Command;
                     app.command_buffer.clear();
                 }
-                "paste" => {
-                    let text_to_paste = app.get_clipboard_text();
-                    if let Ok(text) = text_to_paste {
-                        let current_window = app.current_window_mut();
-                        if !text.is_empty() {
-                            current_window.save_state(); // 変更前の状態を保存
-                            let cy = *current_window.cursor_y_mut();
-                            let mut cx = *current_window.cursor_x_mut();
-                            if text.contains('\n') {
-                                let mut lines: Vec<String> = text.lines().map(String::from).collect();
-                                let current_line_ref = &mut current_window.buffer_mut()[cy];
-                                let byte_index = current_line_ref.grapheme_indices(true).nth(cx).map(|(i, _)| i).unwrap_or(current_line_ref.len());
-                                let rest_of_current_line = current_line_ref.split_off(byte_index);
-                                current_line_ref.push_str(&lines[0]);
-                                let last_line_index = lines.len() - 1;
-                                lines[last_line_index].push_str(&rest_of_current_line);
-                                for (i, line) in lines.iter().skip(1).enumerate() {
-                                    current_window.buffer_mut().insert(cy + 1 + i, line.clone());
-                                    current_window.on_line_inserted(cy + 1 + i);
-                                }
-                                current_window.mark_line_modified(cy);
-                            } else {
-                                if !current_window.buffer()[cy].is_empty() {
-                                    cx += 1;
-                                }
-                                let current_line_ref = &mut current_window.buffer_mut()[cy];
-                                let byte_index = current_line_ref.grapheme_indices(true).nth(cx).map(|(i, _)| i).unwrap_or(current_line_ref.len());
-                                current_line_ref.insert_str(byte_index, &text);
-                                *current_window.cursor_x_mut() = cx + text.graphemes(true).count();
-                                current_window.mark_line_modified(cy);
-                            }
-                        }
+                "mode_search" | "mode_search_backward" => {
+                    app.search_forward = action == "mode_search";
+                    app.mode = Mode::Search;
+                    app.command_buffer.clear();
+                }
+                "search_next" => {
+                    for _ in 0..repeat {
+                        app.repeat_search(false);
+                    }
+                }
+                "search_prev" => {
+                    for _ in 0..repeat {
+                        app.repeat_search(true);
                     }
                 }
+                "paste" => {
+                    app.paste(false);
+                }
+                "paste_before" => {
+                    app.paste(true);
+                }
                 "undo" => {
                     let current_window = app.current_window_mut();
                     if current_window.undo() {
@@ -224,12 +629,47 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
                         app.status_message = "Nothing to undo".to_string();
                     }
                 }
+                "operator_delete" => {
+                    app.pending_operator = Some(PendingOperator::Delete);
+                    app.pending_count = count;
+                }
+                "replace_char" => {
+                    app.pending_key = Some('r');
+                }
+                "register_prefix" => {
+                    app.pending_key = Some('"');
+                    app.pending_count = count;
+                }
+                "change_to_line_end" => {
+                    // C: c$ と同じ
+                    enter_insert_mode(app, InsertEntry::ChangeToMotion(Motion::LineEnd, repeat));
+                }
+                "join_lines" => {
+                    // J: 次の行を空白1つでつなぐ（3J は3行を結合する）
+                    join_lines(app, repeat, true);
+                }
+                "delete_to_line_end" => {
+                    // D: d$ と同じ（2D は次の行も削除する）
+                    delete_to_motion(app, Motion::LineEnd, repeat);
+                }
+                "yank_line" => {
+                    // Y: yy と同じ
+                    yank_lines(app, repeat);
+                }
+                "operator_change" => {
+                    app.pending_operator = Some(PendingOperator::Change);
+                    app.pending_count = count;
+                }
+                "operator_yank" => {
+                    app.pending_operator = Some(PendingOperator::Yank);
+                    app.pending_count = count;
+                }
                 "open_new_line" => {
                     app.status_message = "o key pressed".to_string();
-                    let current_window = app.current_window_mut();
-                    current_window.open_new_line();
-                    current_window.start_insert_mode();
-                    app.mode = Mode::Insert;
+                    enter_insert_mode(app, InsertEntry::OpenLine { above: false });
+                }
+                "open_new_line_above" => {
+                    enter_insert_mode(app, InsertEntry::OpenLine { above: true });
                 }
                 _ => {}
             }
@@ -256,31 +696,10 @@ pub fn handle_normal_mode_event(app: &mut App, key_code: KeyCode, key_modifiers:
             // 右側パネルの入力欄から文字を削除
             app.right_panel_input.pop();
         }
-    } else if key_code == KeyCode::Char('r') && key_modifiers == KeyModifiers::CONTROL {
-        // Ctrl+R for redo
-        let current_window = app.current_window_mut();
-        if current_window.redo() {
-            app.status_message = "Redone".to_string();
-        } else {
-            app.status_message = "Nothing to redo".to_string();
-        }
-    } else if key_code == KeyCode::Char('r') && key_modifiers == KeyModifiers::CONTROL {
-        // Ctrl+R for redo
-        let current_window = app.current_window_mut();
-        if current_window.redo() {
-            app.status_message = "Redone".to_string();
-        } else {
-            app.status_message = "Nothing to redo".to_string();
-        }
-    // F5キーでAI状態を変更（リアルタイムテスト用）
-    if key_code == KeyCode::F(5) {
-        app.ai_status = "LLM再接続中".to_string();
-        app.status_message = "AI状態を「LLM再接続中」に変更".to_string();
     }
-}
     // F5キーでAI状態を変更（リアルタイムテスト用）
     if key_code == KeyCode::F(5) {
         app.ai_status = "LLM再接続中".to_string();
         app.status_message = "AI状態を「LLM再接続中」に変更".to_string();
     }
-}
\ No newline at end of file
+}
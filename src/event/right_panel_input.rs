@@ -26,30 +26,26 @@ pub fn handle_right_panel_input_mode_event(app: &mut App, key_event: KeyEvent) {
             }
             app.mode = Mode::RightPanelInput;
         }
-        (KeyCode::Backspace, _) => {
-            if app.right_panel_input_cursor > 0 {
-                let graphemes: Vec<&str> = app.right_panel_input.graphemes(true).collect();
-                if app.right_panel_input_cursor <= graphemes.len() {
-                    let byte_index = app.right_panel_input
-                        .grapheme_indices(true)
-                        .nth(app.right_panel_input_cursor - 1)
-                        .map(|(i, _)| i)
-                        .unwrap_or(0);
-                    let next_byte_index = app.right_panel_input
-                        .grapheme_indices(true)
-                        .nth(app.right_panel_input_cursor)
-                        .map(|(i, _)| i)
-                        .unwrap_or(app.right_panel_input.len());
-                    app.right_panel_input.drain(byte_index..next_byte_index);
-                    app.right_panel_input_cursor -= 1;
-                }
-            }
-        }
-        (KeyCode::Left, _) => {
-            if app.right_panel_input_cursor > 0 {
+        (KeyCode::Backspace, _) if app.right_panel_input_cursor > 0 => {
+            let graphemes: Vec<&str> = app.right_panel_input.graphemes(true).collect();
+            if app.right_panel_input_cursor <= graphemes.len() {
+                let byte_index = app.right_panel_input
+                    .grapheme_indices(true)
+                    .nth(app.right_panel_input_cursor - 1)
+                    .map(|(i, _)| i)
+                    .unwrap_or(0);
+                let next_byte_index = app.right_panel_input
+                    .grapheme_indices(true)
+                    .nth(app.right_panel_input_cursor)
+                    .map(|(i, _)| i)
+                    .unwrap_or(app.right_panel_input.len());
+                app.right_panel_input.drain(byte_index..next_byte_index);
                 app.right_panel_input_cursor -= 1;
             }
         }
+        (KeyCode::Left, _) if app.right_panel_input_cursor > 0 => {
+            app.right_panel_input_cursor -= 1;
+        }
         (KeyCode::Right, _) => {
             let grapheme_count = app.right_panel_input.graphemes(true).count();
             if app.right_panel_input_cursor < grapheme_count {
@@ -0,0 +1,26 @@
+use crate::app::App;
+use crate::app::Mode;
+use crossterm::event::KeyCode;
+
+pub fn handle_search_mode_event(app: &mut App, key_code: KeyCode) {
+    match key_code {
+        KeyCode::Char(c) => {
+            app.command_buffer.push(c);
+        }
+        // 空の状態で Backspace を押したら検索を取り消す
+        KeyCode::Backspace if app.command_buffer.pop().is_none() => {
+            app.mode = Mode::Normal;
+        }
+        KeyCode::Enter => {
+            let pattern = app.command_buffer.clone();
+            if pattern.is_empty() {
+                // 空のパターンは前回の検索を繰り返す
+                app.repeat_search(false);
+            } else {
+                app.search(&pattern);
+            }
+            app.mode = Mode::Normal;
+        }
+        _ => {}
+    }
+}
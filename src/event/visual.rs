@@ -1,23 +1,126 @@
 use crate::app::App;
-use crate::app::Mode;
+use crate::app::{LastChange, Mode};
+use crate::window::YankKind;
 use crossterm::event::KeyCode;
 use unicode_segmentation::UnicodeSegmentation;
 
 pub fn handle_visual_mode_event(app: &mut App, key_code: KeyCode) {
+    let virtual_edit = app.config.editor.virtual_edit;
+    let mode = app.mode;
+    // sticky_visual なら削除以外の操作の後も選択を残す
+    let keep_selection = app.config.editor.sticky_visual && key_code != KeyCode::Char('d');
+    let indent_unit = app.indent_unit();
+    // gc: 選択した行の行コメントを切り替える
+    if app.pending_key.take() == Some('g') {
+        let current_window = app.current_window_mut();
+        if let (KeyCode::Char('c'), Some((first, last))) = (key_code, current_window.line_selection()) {
+            *current_window.cursor_y_mut() = first;
+            if current_window.toggle_comment(first, last) {
+                app.last_change = Some(LastChange::ToggleComment(last - first + 1));
+            }
+            if !keep_selection {
+                *app.current_window_mut().visual_start_mut() = None;
+                app.mode = Mode::Normal;
+            }
+        }
+        return;
+    }
     let current_window = app.current_window_mut();
     match key_code {
-        KeyCode::Char('h') => {
-            if current_window.cursor_x() > 0 {
-                *current_window.cursor_x_mut() -= 1;
+        KeyCode::Char('g') => {
+            app.pending_key = Some('g');
+        }
+        // v と V で選択の種類を切り替える（同じキーなら選択を終了する）
+        KeyCode::Char('v') | KeyCode::Char('V') => {
+            let target = if key_code == KeyCode::Char('v') { Mode::Visual } else { Mode::VisualLine };
+            if target == mode {
+                *current_window.visual_start_mut() = None;
+                app.mode = Mode::Normal;
+            } else {
+                app.mode = target;
             }
         }
+        KeyCode::Char('d') | KeyCode::Char('y') if mode == Mode::VisualLine => {
+            // 行単位の選択は行全体を削除・ヤンクする
+            let text = if key_code == KeyCode::Char('d') {
+                let deleted = current_window.delete_line_selection();
+                if let Some(text) = &deleted {
+                    app.last_change = Some(LastChange::DeleteLines(text.lines().count()));
+                }
+                deleted
+            } else {
+                current_window.line_selection().map(|(first, last)| {
+                    if !keep_selection {
+                        *current_window.cursor_y_mut() = first;
+                        *current_window.visual_start_mut() = None;
+                    }
+                    current_window.yank_lines(first, last - first + 1)
+                })
+            };
+            if let Some(text) = text {
+                app.set_yanked_text(text, YankKind::LineWise);
+            }
+            if !keep_selection {
+                app.mode = Mode::Normal;
+            }
+        }
+        KeyCode::Char('d') | KeyCode::Char('y') if mode == Mode::VisualBlock => {
+            // 矩形選択は各行の同じ表示カラム範囲を削除・ヤンクする
+            if let Some((top, bottom, left, right)) = current_window.block_selection() {
+                let text = if key_code == KeyCode::Char('d') {
+                    current_window.delete_block(top, bottom, left, right)
+                } else {
+                    let text = current_window.yank_block(top, bottom, left, right);
+                    if !keep_selection {
+                        let (start, _) = current_window.block_range_in_line(top, left, right);
+                        *current_window.cursor_y_mut() = top;
+                        *current_window.cursor_x_mut() = start;
+                    }
+                    text
+                };
+                app.set_yanked_text(text, YankKind::Block);
+            }
+            if !keep_selection {
+                *app.current_window_mut().visual_start_mut() = None;
+                app.mode = Mode::Normal;
+            }
+        }
+        KeyCode::Char('I') | KeyCode::Char('A') if mode == Mode::VisualBlock => {
+            // 矩形の左端（A なら右端）に挿入し、Esc で残りの行にも同じテキストを挿入する
+            if let Some((top, bottom, left, right)) = current_window.block_selection() {
+                let append = key_code == KeyCode::Char('A');
+                current_window.start_block_insert(top, bottom, if append { right } else { left }, append);
+                *current_window.visual_start_mut() = None;
+                app.insert_entry = None;
+                app.inserted_text.clear();
+                app.mode = Mode::Insert;
+            }
+        }
+        KeyCode::Char('>') | KeyCode::Char('<') => {
+            // 選択した各行のインデントを1段深く（浅く）する
+            if let Some((first, last)) = current_window.line_selection() {
+                let right = key_code == KeyCode::Char('>');
+                *current_window.cursor_y_mut() = first;
+                current_window.shift_lines(first, last, &indent_unit, right);
+                if !keep_selection {
+                    *current_window.visual_start_mut() = None;
+                    app.mode = Mode::Normal;
+                }
+                app.last_change = Some(LastChange::ShiftLines { count: last - first + 1, right });
+            }
+        }
+        KeyCode::Char('h') if current_window.cursor_x() > 0 => {
+            *current_window.cursor_x_mut() -= 1;
+        }
         KeyCode::Char('j') => {
             let y = current_window.cursor_y();
-            if y < current_window.buffer_mut().len() - 1 {
+            if y < current_window.buffer().len() - 1 {
                 *current_window.cursor_y_mut() += 1;
                 let x = current_window.cursor_x();
-                let line_len = current_window.buffer_mut()[y + 1].graphemes(true).count();
-                *current_window.cursor_x_mut() = x.min(line_len);
+                let line_len = current_window.buffer()[y + 1].graphemes(true).count();
+                if !virtual_edit {
+                    *current_window.cursor_x_mut() = x.min(line_len);
+                }
             }
         }
         KeyCode::Char('k') => {
@@ -25,22 +128,34 @@ pub fn handle_visual_mode_event(app: &mut App, key_code: KeyCode) {
             if y > 0 {
                 *current_window.cursor_y_mut() -= 1;
                 let x = current_window.cursor_x();
-                let line_len = current_window.buffer_mut()[y - 1].graphemes(true).count();
-                *current_window.cursor_x_mut() = x.min(line_len);
+                let line_len = current_window.buffer()[y - 1].graphemes(true).count();
+                if !virtual_edit {
+                    *current_window.cursor_x_mut() = x.min(line_len);
+                }
             }
         }
         KeyCode::Char('l') => {
             let y = current_window.cursor_y();
-            let current_line = &current_window.buffer_mut()[y];
+            let current_line = &current_window.buffer()[y];
             let grapheme_count = current_line.graphemes(true).count();
             let x = current_window.cursor_x();
-            if x < grapheme_count.saturating_sub(1) {
+            if virtual_edit || x < grapheme_count.saturating_sub(1) {
                 *current_window.cursor_x_mut() += 1;
             }
         }
+        KeyCode::Char(':') => {
+            // 選択行の範囲を '<,'> としてコマンドモードに渡す
+            let cursor_y = current_window.cursor_y();
+            let range = current_window.line_selection().unwrap_or((cursor_y, cursor_y));
+            *current_window.visual_start_mut() = None;
+            app.visual_range = Some(range);
+            app.command_buffer = "'<,'>".to_string();
+            app.mode = Mode::Command;
+        }
         KeyCode::Char('d') | KeyCode::Char('y') => {
             let mut yanked_text = String::new();
             let new_mode = Mode::Normal; // 新しいモードを保持する変数
+            let mut change = None; // `.` で繰り返せる削除（1行内の削除のみ）
 
             if let Some(start) = current_window.visual_start() {
                 if key_code == KeyCode::Char('d') {
@@ -59,7 +174,7 @@ pub fn handle_visual_mode_event(app: &mut App, key_code: KeyCode) {
 
                 if sel_start_y == sel_end_y {
                     // Single line
-                    let line = &current_window.buffer_mut()[sel_start_y];
+                    let line = &current_window.buffer()[sel_start_y];
                     let start_byte = line.grapheme_indices(true).nth(sel_start_x).map(|(i, _)| i).unwrap_or(line.len());
                     let end_byte = line.grapheme_indices(true).nth(sel_end_x + 1).map(|(i, _)| i).unwrap_or(line.len());
                     if start_byte < end_byte {
@@ -67,15 +182,15 @@ pub fn handle_visual_mode_event(app: &mut App, key_code: KeyCode) {
                     }
                 } else {
                     // Multi-line
-                    let start_line = &current_window.buffer_mut()[sel_start_y];
+                    let start_line = &current_window.buffer()[sel_start_y];
                     let start_byte = start_line.grapheme_indices(true).nth(sel_start_x).map(|(i, _)| i).unwrap_or(start_line.len());
                     yanked_text.push_str(&start_line[start_byte..]);
                     yanked_text.push('\n');
                     for y in (sel_start_y + 1)..sel_end_y {
-                        yanked_text.push_str(&current_window.buffer_mut()[y]);
+                        yanked_text.push_str(&current_window.buffer()[y]);
                         yanked_text.push('\n');
                     }
-                    let end_line = &current_window.buffer_mut()[sel_end_y];
+                    let end_line = &current_window.buffer()[sel_end_y];
                     let end_byte = end_line.grapheme_indices(true).nth(sel_end_x + 1).map(|(i, _)| i).unwrap_or(end_line.len());
                     yanked_text.push_str(&end_line[..end_byte]);
                 }
@@ -83,53 +198,63 @@ pub fn handle_visual_mode_event(app: &mut App, key_code: KeyCode) {
                 if key_code == KeyCode::Char('d') {
                     if sel_start_y == sel_end_y {
                         // Single line deletion
-                        let line = &mut current_window.buffer_mut()[sel_start_y];
+                        let line = current_window.line_mut(sel_start_y);
                         let start_byte = line.grapheme_indices(true).nth(sel_start_x).map(|(i, _)| i).unwrap_or(line.len());
                         let end_byte = line.grapheme_indices(true).nth(sel_end_x + 1).map(|(i, _)| i).unwrap_or(line.len());
                         if start_byte < end_byte {
-                            line.drain(start_byte..end_byte);
+                            let deleted = line.drain(start_byte..end_byte).as_str().graphemes(true).count();
+                            change = Some(LastChange::DeleteChars(deleted));
                         }
                     } else {
                         // Multi-line deletion
-                        let end_line = &current_window.buffer_mut()[sel_end_y];
+                        let end_line = &current_window.buffer()[sel_end_y];
                         let split_point_byte = end_line.grapheme_indices(true).nth(sel_end_x + 1).map(|(i, _)| i).unwrap_or(end_line.len());
                         let end_line_suffix = end_line[split_point_byte..].to_string();
 
-                        let start_line = &mut current_window.buffer_mut()[sel_start_y];
+                        let start_line = current_window.line_mut(sel_start_y);
                         let start_byte = start_line.grapheme_indices(true).nth(sel_start_x).map(|(i, _)| i).unwrap_or(start_line.len());
                         start_line.truncate(start_byte);
                         start_line.push_str(&end_line_suffix);
 
                         let start_of_removal = sel_start_y + 1;
                         if start_of_removal <= sel_end_y {
-                            current_window.buffer_mut().drain(start_of_removal..=sel_end_y);
+                            current_window.delete_lines(start_of_removal, sel_end_y + 1 - start_of_removal);
                         }
                     }
                 }
 
+                if keep_selection {
+                    // 選択とビジュアルモードをそのまま残す
+                    app.set_yanked_text(yanked_text, YankKind::CharWise);
+                    return;
+                }
+
                 // Set cursor position
                 *current_window.cursor_x_mut() = sel_start_x;
                 *current_window.cursor_y_mut() = sel_start_y;
 
-                if current_window.buffer_mut().is_empty() {
+                if current_window.buffer().is_empty() {
                     current_window.buffer_mut().push(String::new());
                     *current_window.cursor_y_mut() = 0;
                     *current_window.cursor_x_mut() = 0;
                 } else {
                     let y = current_window.cursor_y();
-                    if y >= current_window.buffer_mut().len() {
-                        *current_window.cursor_y_mut() = current_window.buffer_mut().len().saturating_sub(1);
+                    if y >= current_window.buffer().len() {
+                        *current_window.cursor_y_mut() = current_window.buffer().len().saturating_sub(1);
                     }
                     let x = current_window.cursor_x();
                     let y2 = current_window.cursor_y();
-                    let line_len = current_window.buffer_mut()[y2].graphemes(true).count();
+                    let line_len = current_window.buffer()[y2].graphemes(true).count();
                     if x > line_len {
                         *current_window.cursor_x_mut() = line_len;
                     }
                 }
                 *current_window.visual_start_mut() = None;
             }
-            app.set_yanked_text(yanked_text);
+            app.set_yanked_text(yanked_text, YankKind::CharWise);
+            if change.is_some() {
+                app.last_change = change;
+            }
             app.mode = new_mode;
         }
         _ => {}
@@ -2,8 +2,14 @@ pub mod app;
 pub mod app_config;
 pub mod config;
 pub mod constants;
+pub mod dictionary;
+pub mod spell;
 pub mod event;
+pub mod line_buffer;
 pub mod pane;
+pub mod positions;
+pub mod registers;
+pub mod substitute;
 pub mod syntax;
 pub mod ui;
 pub mod utils;
@@ -0,0 +1,220 @@
+use std::ops::{Bound, Deref, Index, IndexMut, Range, RangeBounds};
+use std::slice::SliceIndex;
+
+/// バッファの行と、undo 用に記録中の変更。
+/// 書き換えはすべてこの型を通すので、変更のあった行の範囲の変更前の内容だけを控えておける
+#[derive(Default)]
+pub struct LineBuffer {
+    lines: Vec<String>,
+    recording: Option<Recording>,
+}
+
+/// 記録を始めてから変更のあった行の範囲（現在の行番号）と、その範囲の変更前の内容
+#[derive(Default)]
+struct Recording {
+    touched: Option<Range<usize>>,
+    before: Vec<String>,
+    /// 外から直接書き換えられたので、範囲を末尾まで広げたままにする
+    to_end: bool,
+}
+
+/// 記録した変更。`start` 行目からの `before` が `after` に置き換わった
+pub struct LineChange {
+    pub start: usize,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl LineBuffer {
+    /// 変更の記録を始める（記録中だった変更は捨てる）
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::default());
+    }
+
+    /// 記録した変更を取り出して記録を終える。前後で同じ内容の行は範囲から除く
+    pub fn take_recording(&mut self) -> Option<LineChange> {
+        let recording = self.recording.take()?;
+        let Some(touched) = recording.touched else {
+            return Some(LineChange { start: 0, before: Vec::new(), after: Vec::new() });
+        };
+        let end = if recording.to_end { self.lines.len() } else { touched.end };
+        let mut before = recording.before;
+        let after = &self.lines[touched.start..end];
+        let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let suffix = before[prefix..]
+            .iter()
+            .rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        before.truncate(before.len() - suffix);
+        Some(LineChange {
+            start: touched.start + prefix,
+            before: before.split_off(prefix),
+            after: after[prefix..after.len() - suffix].to_vec(),
+        })
+    }
+
+    /// 記録中なら、変更のあった範囲を `range` まで広げ、新しく入った行の今の内容を控える
+    fn cover(&mut self, range: Range<usize>) {
+        let Some(recording) = self.recording.as_mut() else {
+            return;
+        };
+        match recording.touched.as_mut() {
+            None => {
+                recording.before = self.lines[range.clone()].to_vec();
+                recording.touched = Some(range);
+            }
+            Some(touched) => {
+                if range.start < touched.start {
+                    recording.before.splice(0..0, self.lines[range.start..touched.start].iter().cloned());
+                    touched.start = range.start;
+                }
+                let end = if recording.to_end { self.lines.len() } else { touched.end };
+                if range.end > end {
+                    recording.before.extend(self.lines[end..range.end].iter().cloned());
+                    touched.end = range.end;
+                }
+            }
+        }
+    }
+
+    /// `range` の行を `lines` で置き換え、取り除いた行を返す
+    pub fn splice(&mut self, range: impl RangeBounds<usize>, lines: impl IntoIterator<Item = String>) -> Vec<String> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.lines.len(),
+        };
+        let range = start..end;
+        self.cover(range.clone());
+        let old_len = self.lines.len();
+        let removed: Vec<String> = self.lines.splice(range, lines).collect();
+        let recording = self.recording.as_mut().filter(|recording| !recording.to_end);
+        if let Some(touched) = recording.and_then(|recording| recording.touched.as_mut()) {
+            touched.end = touched.end + self.lines.len() - old_len;
+        }
+        removed
+    }
+
+    pub fn insert(&mut self, index: usize, line: String) {
+        self.splice(index..index, [line]);
+    }
+
+    pub fn remove(&mut self, index: usize) -> String {
+        self.splice(index..index + 1, []).remove(0)
+    }
+
+    pub fn push(&mut self, line: String) {
+        self.insert(self.lines.len(), line);
+    }
+
+    pub fn drain(&mut self, range: impl RangeBounds<usize>) -> Vec<String> {
+        self.splice(range, [])
+    }
+
+    pub fn resize(&mut self, len: usize, line: String) {
+        let current = self.lines.len();
+        if len < current {
+            self.splice(len..current, []);
+        } else {
+            self.splice(current..current, std::iter::repeat_n(line, len - current));
+        }
+    }
+
+    /// 全体を `lines` で置き換える
+    pub fn replace_all(&mut self, lines: impl IntoIterator<Item = String>) {
+        self.splice(.., lines);
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut String> {
+        if index < self.lines.len() {
+            Some(&mut self[index])
+        } else {
+            None
+        }
+    }
+
+    /// 行の並びを直接書き換える。何が変わるかわからないので、記録中なら全体を変更の範囲にする
+    pub fn lines_mut(&mut self) -> &mut Vec<String> {
+        self.cover(0..self.lines.len());
+        if let Some(recording) = self.recording.as_mut() {
+            recording.to_end = true;
+        }
+        &mut self.lines
+    }
+}
+
+impl From<Vec<String>> for LineBuffer {
+    fn from(lines: Vec<String>) -> Self {
+        Self { lines, recording: None }
+    }
+}
+
+impl<'a> IntoIterator for &'a LineBuffer {
+    type Item = &'a String;
+    type IntoIter = std::slice::Iter<'a, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.lines.iter()
+    }
+}
+
+impl Deref for LineBuffer {
+    type Target = Vec<String>;
+
+    fn deref(&self) -> &Vec<String> {
+        &self.lines
+    }
+}
+
+impl<I: SliceIndex<[String]>> Index<I> for LineBuffer {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &I::Output {
+        &self.lines[index]
+    }
+}
+
+impl IndexMut<usize> for LineBuffer {
+    fn index_mut(&mut self, index: usize) -> &mut String {
+        self.cover(index..index + 1);
+        &mut self.lines[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(lines: &[&str]) -> LineBuffer {
+        LineBuffer::from(lines.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_recording_keeps_only_touched_lines() {
+        let mut lines = buffer(&["a", "b", "c", "d", "e"]);
+        lines.start_recording();
+        lines[3].push('!');
+        lines.insert(1, "new".to_string());
+        lines.remove(3);
+        let change = lines.take_recording().unwrap();
+        assert_eq!(change.start, 1);
+        assert_eq!(change.before, vec!["b", "c", "d"]);
+        assert_eq!(change.after, vec!["new", "b", "d!"]);
+        assert!(lines.take_recording().is_none());
+
+        // 書き換えても元と同じ内容に戻った行は範囲に含めない
+        lines.start_recording();
+        lines[0].push('x');
+        lines[0].pop();
+        lines.lines_mut().push("f".to_string());
+        let change = lines.take_recording().unwrap();
+        assert_eq!((change.start, change.before.len(), change.after), (5, 0, vec!["f".to_string()]));
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use ratatui::layout::{Direction, Constraint, Layout, Rect};
+use crate::window::ViewState;
 
 /// ペインの分割方向を表す
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -24,6 +25,8 @@ pub struct Pane {
     pub split: Option<Split>,
     pub children: Vec<usize>, // 子ペインのID
     pub parent: Option<usize>, // 親ペインのID
+    /// アクティブでない間のカーソルとスクロール位置（同じファイルを複数のペインで開いたときに使う）
+    pub view: Option<ViewState>,
 }
 
 impl Pane {
@@ -35,6 +38,7 @@ impl Pane {
             split: None,
             children: Vec::new(),
             parent: None,
+            view: None,
         }
     }
 
@@ -86,7 +90,6 @@ impl PaneManager {
     }
 
     /// ペインを取得
-    #[allow(dead_code)]
     pub fn get_pane(&self, pane_id: usize) -> Option<&Pane> {
         self.panes.get(&pane_id)
     }
@@ -175,6 +178,8 @@ impl PaneManager {
         let sibling_pane = self.panes[&sibling_id].clone();
         if let Some(parent_pane) = self.panes.get_mut(&parent_id) {
             parent_pane.window_index = sibling_pane.window_index;
+            // アクティブな兄弟を吸収する場合、表示位置はウィンドウにあるので保存値は使わない
+            parent_pane.view = if self.active_pane == sibling_id { None } else { sibling_pane.view };
             parent_pane.split = sibling_pane.split;
             parent_pane.children = sibling_pane.children.clone();
         }
@@ -190,14 +195,30 @@ impl PaneManager {
         self.panes.remove(&pane_id);
         self.panes.remove(&sibling_id);
 
-        // アクティブペインが閉じられた場合、親に変更
-        if self.active_pane == pane_id {
+        // アクティブペイン（またはその内容を引き継いだ兄弟）が消えた場合、親に変更
+        if self.active_pane == pane_id || self.active_pane == sibling_id {
             self.active_pane = parent_id;
         }
 
         true
     }
 
+    /// アクティブペインから見て指定方向にあるペインをすべて閉じ、閉じた数を返す
+    pub fn close_panes_in_direction(&mut self, direction: SplitDirection, forward: bool) -> usize {
+        let mut closed = 0;
+        while let Some(pane_id) = self.find_adjacent_pane(direction, forward) {
+            if !self.close_pane(pane_id) {
+                break;
+            }
+            closed += 1;
+            // 隣接判定のために、閉じた後のレイアウトを直前の領域で計算し直す
+            if let Some(area) = self.panes.get(&self.root_pane).and_then(|pane| pane.rect) {
+                self.calculate_layout(area);
+            }
+        }
+        closed
+    }
+
     /// レイアウトを計算してペインの描画領域を設定
     pub fn calculate_layout(&mut self, area: Rect) {
         self.calculate_pane_layout(self.root_pane, area);
@@ -447,6 +468,19 @@ impl PaneManager {
         best_candidate.map(|(id, _)| id)
     }
 
+    /// ウィンドウ `removed` を表示していたペインを `replacement` に切り替え、後ろのウィンドウ番号を詰める
+    pub fn remove_window(&mut self, removed: usize, replacement: usize) {
+        for pane in self.panes.values_mut() {
+            if pane.window_index == removed {
+                pane.window_index = replacement;
+                pane.view = None;
+            }
+            if pane.window_index > removed {
+                pane.window_index -= 1;
+            }
+        }
+    }
+
     /// アクティブペインIDを取得
     pub fn get_active_pane_id(&self) -> usize {
         self.active_pane
@@ -458,3 +492,51 @@ impl PaneManager {
         self.root_pane
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_panes_to_the_right() {
+        let area = Rect::new(0, 0, 120, 40);
+        let mut manager = PaneManager::new(0);
+        // 左右に3分割し、下段も作る: [left | middle | right] の middle を上下に分割
+        let right = manager.vsplit(0, 1).unwrap();
+        let left = manager.get_pane(0).unwrap().children[0];
+        let middle = manager.vsplit(left, 2).unwrap();
+        manager.calculate_layout(area);
+        manager.set_active_pane(manager.get_pane(left).unwrap().children[0]);
+        let active = manager.get_active_pane_id();
+
+        assert_eq!(manager.close_panes_in_direction(SplitDirection::Horizontal, true), 2);
+        assert_eq!(manager.get_leaf_panes().len(), 1);
+        assert!(manager.get_pane(right).is_none());
+        assert!(manager.get_pane(middle).is_none());
+        // アクティブペインの内容はルートに引き継がれる
+        assert!(manager.get_pane(active).is_none());
+        assert_eq!(manager.get_active_pane_id(), 0);
+        assert_eq!(manager.get_active_pane().unwrap().window_index, 0);
+    }
+
+    #[test]
+    fn test_remove_window_renumbers_panes() {
+        let mut manager = PaneManager::new(0);
+        let right = manager.vsplit(0, 2).unwrap();
+        let left = manager.get_pane(0).unwrap().children[0];
+        let bottom = manager.hsplit(left, 1).unwrap();
+        let top = manager.get_pane(left).unwrap().children[0];
+
+        manager.remove_window(1, 2);
+        assert_eq!(manager.get_pane(top).unwrap().window_index, 0);
+        assert_eq!(manager.get_pane(bottom).unwrap().window_index, 1);
+        assert_eq!(manager.get_pane(right).unwrap().window_index, 1);
+    }
+
+    #[test]
+    fn test_close_panes_in_direction_without_neighbors() {
+        let mut manager = PaneManager::new(0);
+        manager.calculate_layout(Rect::new(0, 0, 80, 24));
+        assert_eq!(manager.close_panes_in_direction(SplitDirection::Vertical, true), 0);
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use crate::constants::editor::POSITION_CACHE_ENTRIES;
+use crate::utils;
+
+/// ファイルごとの (cursor_x, cursor_y, scroll_y)
+pub type Position = (usize, usize, usize);
+
+/// カーソル位置のキャッシュファイル（`~/.cache/vim-clone/positions.json`）
+pub fn cache_path() -> Option<PathBuf> {
+    Some(utils::cache_dir()?.join("positions.json"))
+}
+
+/// キャッシュに使うファイルのキー（解決できなければそのままのパス）
+pub fn key(filename: &str) -> String {
+    fs::canonicalize(filename).map_or_else(|_| filename.to_string(), |path| path.to_string_lossy().to_string())
+}
+
+/// キャッシュを読み込む（古く記録したものから順に並ぶ）。無い・壊れている場合は空として扱う
+fn load(cache: &Path) -> Vec<(String, Position)> {
+    fs::read_to_string(cache)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn lookup(cache: &Path, filename: &str) -> Option<Position> {
+    let key = key(filename);
+    load(cache).into_iter().find(|(path, _)| *path == key).map(|(_, position)| position)
+}
+
+/// `filename` の位置を記録する。消えたファイルの記録は捨て、`POSITION_CACHE_ENTRIES` を超えたら古いものから消す
+pub fn store(cache: &Path, filename: &str, position: Position) -> io::Result<()> {
+    let key = key(filename);
+    let mut positions = load(cache);
+    positions.retain(|(path, _)| *path != key && Path::new(path).exists());
+    positions.push((key, position));
+    let excess = positions.len().saturating_sub(POSITION_CACHE_ENTRIES);
+    positions.drain(..excess);
+    if let Some(parent) = cache.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(cache, serde_json::to_string(&positions)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_lookup_ignores_corrupt_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let cache = dir.join("positions.json");
+
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+        fs::write(a, "").unwrap();
+        fs::write(b, "").unwrap();
+
+        assert_eq!(lookup(&cache, a), None);
+        store(&cache, a, (3, 10, 4)).unwrap();
+        store(&cache, b, (0, 1, 0)).unwrap();
+        assert_eq!(lookup(&cache, a), Some((3, 10, 4)));
+        assert_eq!(lookup(&cache, b), Some((0, 1, 0)));
+
+        // 消えたファイルの記録は次に書き出すときに捨てる
+        fs::remove_file(b).unwrap();
+        store(&cache, a, (1, 2, 0)).unwrap();
+        assert_eq!(load(&cache), vec![(key(a), (1, 2, 0))]);
+
+        // 壊れたキャッシュは無視して作り直す
+        fs::write(&cache, "{not json").unwrap();
+        assert_eq!(lookup(&cache, a), None);
+        store(&cache, a, (1, 2, 0)).unwrap();
+        assert_eq!(lookup(&cache, a), Some((1, 2, 0)));
+    }
+}
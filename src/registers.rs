@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use crate::window::YankKind;
+
+/// レジスタ名ごとのヤンク内容とその種類
+pub type Registers = HashMap<char, (String, YankKind)>;
+
+/// 無名レジスタ（名前を指定しないヤンク・貼り付けで使う）
+pub const UNNAMED: char = '"';
+
+/// `"{name}` で指定できるレジスタ名か（無名レジスタと a–z）
+pub fn is_register_name(name: char) -> bool {
+    name == UNNAMED || name.is_ascii_lowercase()
+}
+
+/// ヤンクしたテキストを記録する。名前付きレジスタへのヤンクは無名レジスタにも入れる
+pub fn store(registers: &mut Registers, name: Option<char>, text: String, kind: YankKind) {
+    if let Some(name) = name.filter(|&name| name != UNNAMED) {
+        registers.insert(name, (text.clone(), kind));
+    }
+    registers.insert(UNNAMED, (text, kind));
+}
+
+pub fn get(registers: &Registers, name: Option<char>) -> Option<&(String, YankKind)> {
+    registers.get(&name.unwrap_or(UNNAMED))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::Window;
+
+    #[test]
+    fn test_named_register_survives_unnamed_yank() {
+        let mut registers = Registers::new();
+        let mut window = Window::new(None);
+        *window.buffer_mut() = vec!["first".to_string(), "second".to_string()];
+
+        // "ayy で 1 行目を a に、続く yw 相当で無名レジスタだけを上書きする
+        store(&mut registers, Some('a'), window.yank_lines(0, 1), YankKind::LineWise);
+        store(&mut registers, None, "sec".to_string(), YankKind::CharWise);
+        assert_eq!(get(&registers, None), Some(&("sec".to_string(), YankKind::CharWise)));
+        assert_eq!(get(&registers, Some(UNNAMED)), Some(&("sec".to_string(), YankKind::CharWise)));
+        assert_eq!(get(&registers, Some('b')), None);
+
+        // "ap は a の内容を行単位で貼り付ける
+        let (text, kind) = get(&registers, Some('a')).cloned().unwrap();
+        *window.cursor_y_mut() = 1;
+        window.paste(&text, kind, false);
+        assert_eq!(window.buffer(), &vec!["first".to_string(), "second".to_string(), "first".to_string()]);
+    }
+}
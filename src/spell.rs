@@ -0,0 +1,84 @@
+use crate::dictionary::Dictionary;
+use crate::syntax::{count_leading_spaces, tokenize_with_state, BracketState, Language, TokenType};
+
+/// 文章として全体の綴りを確認するファイルか（それ以外はコードとしてコメントと文字列だけを確認する）
+pub fn is_prose_file(filename: Option<&str>) -> bool {
+    let extension = filename
+        .and_then(|name| std::path::Path::new(name).extension())
+        .and_then(|ext| ext.to_str());
+    matches!(extension, None | Some("txt" | "md" | "markdown" | "rst"))
+}
+
+/// 辞書にない単語の位置 (行, 開始バイト, 終了バイト)
+pub type Misspelling = (usize, usize, usize);
+
+/// 辞書にない単語の位置を返す。辞書が空なら何も返さない
+pub fn find_misspellings(lines: &[String], filename: Option<&str>, dictionary: &Dictionary) -> Vec<Misspelling> {
+    let mut misspellings = Vec::new();
+    if dictionary.is_empty() {
+        return misspellings;
+    }
+    if is_prose_file(filename) {
+        for (y, line) in lines.iter().enumerate() {
+            check_words(line, 0, y, dictionary, &mut misspellings);
+        }
+        return misspellings;
+    }
+
+    let mut state = BracketState::with_language(Language::from_filename(filename));
+    for (y, line) in lines.iter().enumerate() {
+        let space_count = count_leading_spaces(line);
+        for token in tokenize_with_state(&line[space_count..], y, space_count, &mut state) {
+            if matches!(token.token_type, TokenType::Comment | TokenType::DocComment | TokenType::String) {
+                check_words(&token.content, space_count + token.start, y, dictionary, &mut misspellings);
+            }
+        }
+    }
+    misspellings
+}
+
+/// `text` 中の英字の並びを単語として辞書と照合する（先頭が大文字の単語は小文字でも照合する）
+fn check_words(text: &str, offset: usize, y: usize, dictionary: &Dictionary, misspellings: &mut Vec<Misspelling>) {
+    let mut word_start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (word_start, c.is_alphabetic()) {
+            (None, true) => word_start = Some(i),
+            (Some(start), false) => {
+                let word = &text[start..i];
+                if word.chars().count() > 1 && !dictionary.contains(word) && !dictionary.contains(&word.to_lowercase()) {
+                    misspellings.push((y, offset + start, offset + i));
+                }
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prose_checks_every_word() {
+        let dictionary = Dictionary::from_words(["the", "cat", "sat"]);
+        let found = find_misspellings(&lines(&["The cat szt.", "a sat"]), Some("notes.md"), &dictionary);
+        // 1文字の単語は確認しない
+        assert_eq!(found, vec![(0, 8, 11)]);
+    }
+
+    #[test]
+    fn test_code_checks_only_comments_and_strings() {
+        let dictionary = Dictionary::from_words(["hello", "world"]);
+        let code = lines(&["fn helo() {", "    // helo world", "    let s = \"wrold\";", "}"]);
+        let found = find_misspellings(&code, Some("main.rs"), &dictionary);
+        assert_eq!(found, vec![(1, 7, 11), (2, 13, 18)]);
+
+        // 辞書が無ければ何も報告しない
+        assert!(find_misspellings(&code, Some("main.rs"), &Dictionary::default()).is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use regex::Regex;
+
+/// `:s` / `:%s` の置換範囲
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SubstituteRange {
+    CurrentLine,
+    All,
+}
+
+/// `:s/pattern/replacement/flags` を解析した結果
+#[derive(Clone, Debug)]
+pub struct Substitute {
+    pub range: SubstituteRange,
+    pub pattern: String,
+    /// 置換文字列（`$1` などでキャプチャグループを参照できる）
+    pub replacement: String,
+    /// `g` フラグ: 行内のすべての一致を置換する
+    pub global: bool,
+    regex: Regex,
+}
+
+impl Substitute {
+    /// コマンド文字列を解析する。置換コマンドでなければ `None`、
+    /// パターンが空または不正なら `Some(Err(..))` を返す
+    pub fn parse(command: &str) -> Option<Result<Self, String>> {
+        let (range, rest) = if let Some(rest) = command.strip_prefix("%s") {
+            (SubstituteRange::All, rest)
+        } else {
+            (SubstituteRange::CurrentLine, command.strip_prefix('s')?)
+        };
+        // `s` の直後の文字を区切り文字とする（`/` 以外に `#` や `|` なども使える）
+        let mut chars = rest.chars().peekable();
+        let delimiter = chars.next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' || delimiter == '"' {
+            return None;
+        }
+
+        // `\<区切り文字>` はエスケープされた区切り文字として扱う
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&delimiter) => {
+                    chars.next();
+                    parts.last_mut()?.push(delimiter);
+                }
+                c if c == delimiter && parts.len() < 3 => parts.push(String::new()),
+                _ => parts.last_mut()?.push(c),
+            }
+        }
+
+        let mut parts = parts.into_iter();
+        let pattern = parts.next()?;
+        let replacement = parts.next()?;
+        let flags = parts.next().unwrap_or_default();
+        if flags.chars().any(|c| c != 'g') {
+            return None;
+        }
+        if pattern.is_empty() {
+            return Some(Err("Empty pattern in substitute command".to_string()));
+        }
+        let regex = match Regex::new(&pattern) {
+            Ok(regex) => regex,
+            Err(e) => return Some(Err(format!("Invalid pattern: {}", e))),
+        };
+        Some(Ok(Self {
+            range,
+            pattern,
+            replacement,
+            global: flags.contains('g'),
+            regex,
+        }))
+    }
+
+    /// 1行に置換を適用し、置換後の行と置換数を返す
+    pub fn apply_to_line(&self, line: &str) -> (String, usize) {
+        let limit = if self.global { 0 } else { 1 };
+        let found = self.regex.find_iter(line).count();
+        let count = if self.global { found } else { found.min(1) };
+        if count == 0 {
+            return (line.to_string(), 0);
+        }
+        (self.regex.replacen(line, limit, self.replacement.as_str()).into_owned(), count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Substitute {
+        Substitute::parse(command).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_substitute() {
+        let sub = parse("%s/foo/bar/g");
+        assert_eq!(sub.range, SubstituteRange::All);
+        assert_eq!((sub.pattern.as_str(), sub.replacement.as_str(), sub.global), ("foo", "bar", true));
+
+        let sub = parse("s/a\\/b/c/");
+        assert_eq!(sub.range, SubstituteRange::CurrentLine);
+        assert_eq!((sub.pattern.as_str(), sub.replacement.as_str(), sub.global), ("a/b", "c", false));
+
+        // 末尾の区切り文字は省略できる
+        assert_eq!(parse("s/x/y").replacement, "y");
+        assert!(Substitute::parse("set foo").is_none());
+        assert!(Substitute::parse("s/x/y/q").is_none());
+
+        // 任意の区切り文字
+        let sub = parse("%s#/usr/bin#/opt\\#bin#g");
+        assert_eq!((sub.pattern.as_str(), sub.replacement.as_str(), sub.global), ("/usr/bin", "/opt#bin", true));
+
+        // 空のパターンや不正な正規表現はエラーになる
+        assert!(Substitute::parse("s//y/").unwrap().is_err());
+        assert!(Substitute::parse("s/(/y/").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_apply_to_line() {
+        let sub = parse("s/foo/bar/");
+        assert_eq!(sub.apply_to_line("foo foo"), ("bar foo".to_string(), 1));
+        let sub = parse("s/foo/bar/g");
+        assert_eq!(sub.apply_to_line("foo 日本 foo"), ("bar 日本 bar".to_string(), 2));
+        assert_eq!(sub.apply_to_line("nothing"), ("nothing".to_string(), 0));
+    }
+
+    #[test]
+    fn test_regex_capture_groups() {
+        let sub = parse("s/(\\w+) = (\\d+)/${2} = ${1}/g");
+        assert_eq!(sub.apply_to_line("x = 1, y = 2"), ("1 = x, 2 = y".to_string(), 2));
+    }
+}
@@ -4,16 +4,121 @@ use std::collections::HashSet;
 use std::iter::Peekable;
 use std::str::CharIndices;
 
+/// ハイライト対象の言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    Rust,
+    Python,
+    JavaScript,
+    Json,
+    Toml,
+    Markdown,
+    /// キーワード・コメント・文字列を持たないテキスト
+    PlainText,
+}
+
+impl Language {
+    /// ファイル名の拡張子から言語を判定する（未保存のバッファは Rust、不明な拡張子はプレーンテキスト）
+    pub fn from_filename(filename: Option<&str>) -> Self {
+        let Some(filename) = filename else {
+            return Language::Rust;
+        };
+        let extension = std::path::Path::new(filename).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match extension {
+            "rs" => Language::Rust,
+            "py" | "pyw" => Language::Python,
+            "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx" => Language::JavaScript,
+            "json" => Language::Json,
+            "toml" => Language::Toml,
+            "md" | "markdown" => Language::Markdown,
+            _ => Language::PlainText,
+        }
+    }
+
+    fn keywords(self) -> &'static HashSet<&'static str> {
+        match self {
+            Language::Rust => &RUST_KEYWORDS,
+            Language::Python => &PYTHON_KEYWORDS,
+            Language::JavaScript => &JAVASCRIPT_KEYWORDS,
+            Language::Json | Language::Toml => &DATA_KEYWORDS,
+            Language::Markdown | Language::PlainText => &NO_KEYWORDS,
+        }
+    }
+
+    /// 行コメントの開始文字列
+    pub fn line_comment(self) -> Option<&'static str> {
+        match self {
+            Language::Python | Language::Toml => Some("#"),
+            Language::Rust | Language::JavaScript => Some("//"),
+            Language::Json | Language::Markdown | Language::PlainText => None,
+        }
+    }
+
+    /// ブロックコメントの開始・終了文字列
+    fn block_comment(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Language::Rust | Language::JavaScript => Some(("/*", "*/")),
+            Language::Markdown => Some(("<!--", "-->")),
+            Language::Python | Language::Json | Language::Toml | Language::PlainText => None,
+        }
+    }
+
+    /// 文字列を囲む引用符（Rust の ' は文字リテラルとライフタイムとして別に扱う）
+    fn string_quotes(self) -> &'static [char] {
+        match self {
+            Language::Rust | Language::Json => &['"'],
+            Language::Python | Language::Toml => &['"', '\''],
+            Language::JavaScript => &['"', '\'', '`'],
+            Language::Markdown => &['`'],
+            Language::PlainText => &[],
+        }
+    }
+
+    /// 改行をそのまま含められる文字列の引用符（それ以外は行末の `\` で次の行へ続く）
+    fn multiline_quotes(self) -> &'static [char] {
+        match self {
+            Language::Rust => &['"'],
+            Language::JavaScript => &['`'],
+            _ => &[],
+        }
+    }
+
+    /// 関数呼び出しや大文字で始まる型名を見分けるプログラミング言語か
+    fn is_code(self) -> bool {
+        matches!(self, Language::Rust | Language::Python | Language::JavaScript)
+    }
+}
+
 /// かっこの入れ子状態を追跡する構造体
 #[derive(Debug, Clone, Default)]
 pub struct BracketState {
     pub stack: Vec<(char, usize, usize)>, // (かっこの文字, 行番号, 列番号)
+    /// トークン化に使う言語
+    pub language: Language,
+    /// 前の行から続いているブロックコメントの入れ子の深さ（0 ならコメントの外）
+    pub block_comment_depth: usize,
+    /// 前の行から生文字列（r#"..."#）が続いていれば、閉じるのに必要な `#` の数
+    pub raw_string_hashes: Option<usize>,
+    /// 前の行から文字列が続いていれば、その引用符
+    pub string_quote: Option<char>,
+    /// 属性 `#[...]` の中なら、属性が始まる前の括弧の深さ（ここまで閉じたら属性の終わり）
+    pub attribute_depth: Option<usize>,
 }
 
 impl BracketState {
     pub fn new() -> Self {
         Self::default()
     }
+
+    pub fn with_language(language: Language) -> Self {
+        Self { language, ..Self::new() }
+    }
+
+    /// 前の行からブロックコメントが続いているか
+    pub fn in_block_comment(&self) -> bool {
+        self.block_comment_depth > 0
+    }
 }
 
 // HashSetを使用してキーワード検索を高速化
@@ -27,11 +132,29 @@ lazy_static::lazy_static! {
         ];
         keywords.iter().copied().collect()
     };
+    static ref PYTHON_KEYWORDS: HashSet<&'static str> = {
+        let keywords = [
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+            "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return",
+            "True", "try", "while", "with", "yield", "self",
+        ];
+        keywords.iter().copied().collect()
+    };
+    static ref JAVASCRIPT_KEYWORDS: HashSet<&'static str> = {
+        let keywords = [
+            "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+            "delete", "do", "else", "export", "extends", "false", "finally", "for", "function", "if",
+            "import", "in", "instanceof", "let", "new", "null", "of", "return", "static", "super",
+            "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "void", "while",
+            "yield",
+        ];
+        keywords.iter().copied().collect()
+    };
+    static ref DATA_KEYWORDS: HashSet<&'static str> = ["true", "false", "null"].into_iter().collect();
+    static ref NO_KEYWORDS: HashSet<&'static str> = HashSet::new();
 }
 
-// 事前に計算されたスペース文字列を使用してメモリアロケーションを削減
-const INDENT_SPACES: &str = "    ";
-
 /// インデント部分のスペース数を計算する関数
 #[inline]
 pub fn count_leading_spaces(line: &str) -> usize {
@@ -55,7 +178,7 @@ pub fn create_indent_spans(line: &str, indent_width: usize, theme: &Theme) -> Ve
     // 各インデントレベルに対応する背景色付きスペースを追加
     for i in 0..full_indents {
         let color = indent_colors[i % indent_colors.len()];
-        spans.push(Span::styled(INDENT_SPACES.to_string(), Style::default().bg(color)));
+        spans.push(Span::styled(" ".repeat(indent_width), Style::default().bg(color)));
     }
 
     // 残りのスペースがあれば追加（背景色なし）
@@ -73,6 +196,10 @@ pub enum TokenType {
     String,
     Number,
     Comment,
+    /// `///` / `//!` のドキュメントコメント
+    DocComment,
+    /// `#[...]` / `#![...]` の属性（中の括弧は Bracket のまま）
+    Attribute,
     Function,
     Macro,
     Type,
@@ -119,24 +246,60 @@ impl<'a> Tokenizer<'a> {
 
     fn run(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
+        // 前の行から続くブロックコメント
+        if self.bracket_state.in_block_comment() {
+            tokens.push(self.tokenize_block_comment(0, 0));
+        } else if let Some(hashes) = self.bracket_state.raw_string_hashes {
+            tokens.push(self.tokenize_raw_string(0, 0, hashes));
+        } else if let Some(quote) = self.bracket_state.string_quote {
+            tokens.push(self.tokenize_string_body(0, quote));
+        }
         while self.chars.peek().is_some() {
-            tokens.push(self.next_token());
+            let mut token = self.next_token();
+            if let Some(depth) = self.bracket_state.attribute_depth {
+                match token.token_type {
+                    TokenType::Bracket { .. } if self.bracket_state.stack.len() <= depth => self.bracket_state.attribute_depth = None,
+                    TokenType::Keyword
+                    | TokenType::Function
+                    | TokenType::Macro
+                    | TokenType::Type
+                    | TokenType::Identifier
+                    | TokenType::Operator
+                    | TokenType::Symbol => token.token_type = TokenType::Attribute,
+                    _ => {}
+                }
+            }
+            tokens.push(token);
         }
         tokens
     }
 
     fn next_token(&mut self) -> Token {
         let (start, ch) = self.peek_char_and_index().unwrap();
+        let language = self.bracket_state.language;
+        let rest = &self.content[start..];
+        if language.line_comment().is_some_and(|marker| rest.starts_with(marker)) {
+            return self.tokenize_comment(start);
+        }
+        if let Some((open, _)) = language.block_comment().filter(|(open, _)| rest.starts_with(open)) {
+            return self.tokenize_block_comment(start, open.len());
+        }
+        if let Some(hashes) = raw_string_hashes(rest).filter(|_| language == Language::Rust) {
+            return self.tokenize_raw_string(start, start + hashes + 2, hashes);
+        }
+        if language == Language::Rust && (rest.starts_with("#[") || rest.starts_with("#![")) {
+            return self.tokenize_attribute_start(start);
+        }
         match ch {
-            '/' if self.peek_next_char() == Some('/') => self.tokenize_comment(start),
-            '"' => self.tokenize_quoted_string(start, '"'),
-            '\'' => self.tokenize_char_literal_or_lifetime(start),
+            '\'' if language == Language::Rust => self.tokenize_char_literal_or_lifetime(start),
+            c if language.string_quotes().contains(&c) => self.tokenize_quoted_string(start, c),
             '(' | '[' | '{' => self.tokenize_open_bracket(start, ch),
             ')' | ']' | '}' => self.tokenize_close_bracket(start, ch),
             c if c.is_ascii_digit() => self.tokenize_number(start),
             c if c.is_alphanumeric() || c == '_' => self.tokenize_identifier(start),
             c if c.is_whitespace() => self.tokenize_whitespace(start),
             ':' if self.peek_next_char() == Some(':') => self.tokenize_operator(start, 2),
+            '.' if self.peek_next_char() == Some('.') => self.tokenize_operator(start, 2),
             _ => self.tokenize_symbol(start),
         }
     }
@@ -171,6 +334,66 @@ impl<'a> Tokenizer<'a> {
 
     fn tokenize_comment(&mut self, start: usize) -> Token {
         let end = self.take_while(|_| true);
+        let content = &self.content[start..end];
+        // //// 以上はドキュメントコメントではない
+        let is_doc = self.bracket_state.language == Language::Rust
+            && ((content.starts_with("///") && !content.starts_with("////")) || content.starts_with("//!"));
+        Token {
+            content: content.to_string(),
+            token_type: if is_doc { TokenType::DocComment } else { TokenType::Comment },
+            start,
+            end,
+        }
+    }
+
+    /// 属性の `#` / `#!` を読み、続く `[` が閉じるまでを属性として扱うよう括弧の深さを覚えておく
+    fn tokenize_attribute_start(&mut self, start: usize) -> Token {
+        let end = if self.content[start..].starts_with("#!") { start + 2 } else { start + 1 };
+        while self.peek_char_and_index().is_some_and(|(i, _)| i < end) {
+            self.advance();
+        }
+        self.bracket_state.attribute_depth = Some(self.bracket_state.stack.len());
+        Token {
+            content: self.content[start..end].to_string(),
+            token_type: TokenType::Attribute,
+            start,
+            end,
+        }
+    }
+
+    /// ブロックコメントを閉じるまで（または行末まで）読み進める。
+    /// Rust では入れ子の `/* /* */ */` を数え、一番外側が閉じるまでをコメントとする
+    fn tokenize_block_comment(&mut self, start: usize, open_len: usize) -> Token {
+        let language = self.bracket_state.language;
+        let (open, close) = language.block_comment().unwrap_or(("/*", "*/"));
+        let mut depth = if open_len > 0 { 1 } else { self.bracket_state.block_comment_depth };
+        let mut position = start + open_len;
+        let end = loop {
+            let rest = &self.content[position..];
+            let next_open = rest.find(open).filter(|_| language == Language::Rust);
+            match (next_open, rest.find(close)) {
+                (Some(o), Some(c)) if o < c => {
+                    depth += 1;
+                    position += o + open.len();
+                }
+                (Some(o), None) => {
+                    depth += 1;
+                    position += o + open.len();
+                }
+                (_, Some(c)) => {
+                    depth -= 1;
+                    position += c + close.len();
+                    if depth == 0 {
+                        break position;
+                    }
+                }
+                (None, None) => break self.content.len(),
+            }
+        };
+        self.bracket_state.block_comment_depth = depth;
+        while self.peek_char_and_index().is_some_and(|(i, _)| i < end) {
+            self.advance();
+        }
         Token {
             content: self.content[start..end].to_string(),
             token_type: TokenType::Comment,
@@ -179,8 +402,38 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    /// 生文字列を `"` と同じ数の `#` が来るまで（閉じなければ行末まで）読み進める
+    fn tokenize_raw_string(&mut self, start: usize, body_start: usize, hashes: usize) -> Token {
+        let close = format!("\"{}", "#".repeat(hashes));
+        let end = match self.content[body_start..].find(&close) {
+            Some(i) => {
+                self.bracket_state.raw_string_hashes = None;
+                body_start + i + close.len()
+            }
+            None => {
+                self.bracket_state.raw_string_hashes = Some(hashes);
+                self.content.len()
+            }
+        };
+        while self.peek_char_and_index().is_some_and(|(i, _)| i < end) {
+            self.advance();
+        }
+        Token {
+            content: self.content[start..end].to_string(),
+            token_type: TokenType::String,
+            start,
+            end,
+        }
+    }
+
     fn tokenize_quoted_string(&mut self, start: usize, quote_char: char) -> Token {
         self.advance(); // Consume opening quote
+        self.tokenize_string_body(start, quote_char)
+    }
+
+    /// 閉じる引用符まで読み進める。行末までに閉じなければ、複数行にできる文字列か
+    /// 行末が `\` のときに、次の行へ文字列が続くことを状態に残す
+    fn tokenize_string_body(&mut self, start: usize, quote_char: char) -> Token {
         let mut escaped = false;
         let _end = self.take_while(|c| {
             if escaped {
@@ -193,7 +446,9 @@ impl<'a> Tokenizer<'a> {
             }
             c != quote_char
         });
-        self.advance(); // Consume closing quote
+        let closed = self.advance().is_some(); // Consume closing quote
+        let continues = !closed && (escaped || self.bracket_state.language.multiline_quotes().contains(&quote_char));
+        self.bracket_state.string_quote = continues.then_some(quote_char);
         let final_end = self.peek_char_and_index().map_or(self.content.len(), |(i, _)| i);
         Token {
             content: self.content[start..final_end].to_string(),
@@ -210,7 +465,7 @@ impl<'a> Tokenizer<'a> {
         if let Some(&(_, c1)) = iter.peek() {
             if c1.is_alphanumeric() || c1 == '_' {
                 iter.next(); // consume c1
-                if iter.peek().map_or(true, |&(_, c2)| c2 != '\'') {
+                if iter.peek().is_none_or(|&(_, c2)| c2 != '\'') {
                     // It's a lifetime
                     self.advance(); // consume '\''
                     let end = self.take_while(|c| c.is_alphanumeric() || c == '_');
@@ -265,8 +520,49 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    /// 数値リテラルを読み進める（0x / 0o / 0b の接頭辞、`_` 区切り、小数点、指数、Rust の型の接尾辞を含む）
     fn tokenize_number(&mut self, start: usize) -> Token {
-        let end = self.take_while(|c| c.is_ascii_digit());
+        let bytes = self.content.as_bytes();
+        // `from` から `is_digit` に当たる文字と `_` が続く終わりの位置
+        let digits_end = |from: usize, is_digit: fn(&u8) -> bool| {
+            from + bytes[from..].iter().take_while(|&b| is_digit(b) || *b == b'_').count()
+        };
+        let radix_digit: Option<fn(&u8) -> bool> = match self.content[start..].get(..2) {
+            Some("0x" | "0X") => Some(u8::is_ascii_hexdigit),
+            Some("0o" | "0O") => Some(|b| (b'0'..=b'7').contains(b)),
+            Some("0b" | "0B") => Some(|b| matches!(b, b'0' | b'1')),
+            _ => None,
+        };
+        let end = match radix_digit {
+            Some(is_digit) => digits_end(start + 2, is_digit),
+            None => {
+                let mut end = digits_end(start, u8::is_ascii_digit);
+                // 小数点の後に数字が続くときだけ小数とする（1..2 や 1.max() の . は含めない）
+                if bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+                    end = digits_end(end + 1, u8::is_ascii_digit);
+                }
+                if matches!(bytes.get(end), Some(b'e' | b'E')) {
+                    let exponent = if matches!(bytes.get(end + 1), Some(b'+' | b'-')) { end + 2 } else { end + 1 };
+                    if bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+                        end = digits_end(exponent, u8::is_ascii_digit);
+                    }
+                }
+                end
+            }
+        };
+        // 1u32 / 2.0f64 のような型の接尾辞（後ろに識別子が続くものは除く）
+        let rest = &self.content[end..];
+        let suffix = NUMBER_SUFFIXES
+            .iter()
+            .filter(|_| self.bracket_state.language == Language::Rust)
+            .find(|suffix| {
+                rest.strip_prefix(*suffix)
+                    .is_some_and(|after| !after.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+            });
+        let end = end + suffix.map_or(0, |suffix| suffix.len());
+        while self.peek_char_and_index().is_some_and(|(i, _)| i < end) {
+            self.advance();
+        }
         Token {
             content: self.content[start..end].to_string(),
             token_type: TokenType::Number,
@@ -278,9 +574,10 @@ impl<'a> Tokenizer<'a> {
     fn tokenize_identifier(&mut self, start: usize) -> Token {
         let end = self.take_while(|c| c.is_alphanumeric() || c == '_');
         let mut content = self.content[start..end].to_string();
-        let mut token_type = classify_word(&content, self.peek_char_and_index().map(|(_, c)| c));
+        let language = self.bracket_state.language;
+        let mut token_type = classify_word(&content, self.peek_char_and_index().map(|(_, c)| c), language);
 
-        if self.peek_char_and_index().map(|(_, c)| c) == Some('!') {
+        if language == Language::Rust && self.peek_char_and_index().map(|(_, c)| c) == Some('!') {
             self.advance();
             content.push('!');
             token_type = TokenType::Macro;
@@ -337,11 +634,26 @@ pub fn tokenize_with_state(
     tokenizer.run()
 }
 
+/// Rust の数値リテラルに付けられる型の接尾辞
+const NUMBER_SUFFIXES: [&str; 14] = [
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+];
+
+/// `r"` / `r#"` のように生文字列が始まっていれば、`#` の数を返す
+fn raw_string_hashes(rest: &str) -> Option<usize> {
+    let after_r = rest.strip_prefix('r')?;
+    let hashes = after_r.bytes().take_while(|&b| b == b'#').count();
+    (after_r.as_bytes().get(hashes) == Some(&b'"')).then_some(hashes)
+}
+
 /// 単語の種類を分類する関数
-fn classify_word(word: &str, next_char: Option<char>) -> TokenType {
-    if RUST_KEYWORDS.contains(word) {
+fn classify_word(word: &str, next_char: Option<char>, language: Language) -> TokenType {
+    if language.keywords().contains(word) {
         return TokenType::Keyword;
     }
+    if !language.is_code() {
+        return TokenType::Identifier;
+    }
     if next_char == Some('(') {
         return TokenType::Function;
     }
@@ -359,6 +671,8 @@ pub fn token_to_span(token: &Token, theme: &SyntaxTheme) -> Span<'static> {
         TokenType::String => Style::default().fg(theme.string.clone().into()),
         TokenType::Number => Style::default().fg(theme.number.clone().into()),
         TokenType::Comment => Style::default().fg(theme.comment.clone().into()),
+        TokenType::DocComment => Style::default().fg(theme.doc_comment.clone().into()),
+        TokenType::Attribute => Style::default().fg(theme.attribute.clone().into()),
         TokenType::Function => Style::default().fg(theme.function.clone().into()),
         TokenType::Macro => Style::default().fg(theme.r#macro.clone().into()),
         TokenType::Type => Style::default().fg(theme.r#type.clone().into()),
@@ -508,11 +822,83 @@ mod tests {
         assert_eq!(number_token.unwrap().content, "42");
     }
 
+    #[test]
+    fn test_doc_comments() {
+        for (line, token_type) in [
+            ("/// docs", TokenType::DocComment),
+            ("//! crate docs", TokenType::DocComment),
+            ("//// not docs", TokenType::Comment),
+            ("// plain", TokenType::Comment),
+        ] {
+            let tokens = tokenize_with_state(line, 0, 0, &mut BracketState::new());
+            assert_eq!(tokens[0].token_type, token_type, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_attribute_keeps_bracket_depth() {
+        let mut bracket_state = BracketState::new();
+        let tokens = tokenize_with_state("#[derive(Debug)] struct A { x: [u8; 2] }", 0, 0, &mut bracket_state);
+        let kinds: Vec<(&str, &TokenType)> = tokens.iter().map(|t| (t.content.as_str(), &t.token_type)).collect();
+        assert_eq!(kinds[0], ("#", &TokenType::Attribute));
+        assert_eq!(kinds[1], ("[", &TokenType::Bracket { level: 0, is_matched: true }));
+        assert_eq!(kinds[2], ("derive", &TokenType::Attribute));
+        assert_eq!(kinds[3], ("(", &TokenType::Bracket { level: 1, is_matched: true }));
+        assert_eq!(kinds[4], ("Debug", &TokenType::Attribute));
+        assert_eq!(kinds[6], ("]", &TokenType::Bracket { level: 0, is_matched: true }));
+        // 属性の後は通常のトークンに戻り、括弧の深さも元に戻る
+        assert!(tokens.iter().any(|t| t.content == "struct" && t.token_type == TokenType::Keyword));
+        let brace = tokens.iter().find(|t| t.content == "{").unwrap();
+        assert_eq!(brace.token_type, TokenType::Bracket { level: 0, is_matched: true });
+        assert!(bracket_state.stack.is_empty());
+        assert_eq!(bracket_state.attribute_depth, None);
+
+        // 複数行にまたがる属性
+        let mut bracket_state = BracketState::new();
+        let tokens = tokenize_with_state("#![cfg(any(", 0, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "#!");
+        let tokens = tokenize_with_state("unix, windows))]", 1, 0, &mut bracket_state);
+        assert_eq!(tokens[0].token_type, TokenType::Attribute);
+        assert_eq!(bracket_state.attribute_depth, None);
+        let tokens = tokenize_with_state("fn main() {}", 2, 0, &mut bracket_state);
+        assert_eq!(tokens[0].token_type, TokenType::Keyword);
+    }
+
+    #[test]
+    fn test_tokenize_number_literals() {
+        for literal in ["0xFF", "3.14", "1_000_000", "2.5e-3", "0b1010", "0o777", "1E10"] {
+            let tokens = tokenize_with_state(literal, 0, 0, &mut BracketState::new());
+            assert_eq!(tokens.len(), 1, "{}", literal);
+            assert_eq!(tokens[0].content, literal);
+            assert_eq!(tokens[0].token_type, TokenType::Number);
+        }
+
+        // 型の接尾辞も数値に含める（接尾辞に見えるだけの識別子は除く）
+        for literal in ["1u32", "2.0f64", "0xFFu8", "1_000usize", "1e-9f32"] {
+            let tokens = tokenize_with_state(literal, 0, 0, &mut BracketState::new());
+            assert_eq!(tokens.len(), 1, "{}", literal);
+            assert_eq!(tokens[0].token_type, TokenType::Number);
+        }
+        let tokens = tokenize_with_state("1u32x", 0, 0, &mut BracketState::new());
+        assert_eq!(tokens[0].content, "1");
+        let tokens = tokenize_with_state("1u32", 0, 0, &mut BracketState::with_language(Language::Python));
+        assert_eq!(tokens[0].content, "1");
+
+        // 範囲の .. は演算子、メソッド呼び出しの . は数値に含めない
+        let tokens = tokenize_with_state("0..10", 0, 0, &mut BracketState::new());
+        let contents: Vec<&str> = tokens.iter().map(|t| t.content.as_str()).collect();
+        assert_eq!(contents, vec!["0", "..", "10"]);
+        assert_eq!(tokens[1].token_type, TokenType::Operator);
+        let tokens = tokenize_with_state("1.max(2)", 0, 0, &mut BracketState::new());
+        assert_eq!(tokens[0].content, "1");
+    }
+
     #[test]
     fn test_classify_word() {
-        assert_eq!(classify_word("fn", Some(' ')), TokenType::Keyword);
-        assert_eq!(classify_word("main", Some('(')), TokenType::Function);
-        assert_eq!(classify_word("String", Some(':')), TokenType::Type);
+        assert_eq!(classify_word("fn", Some(' '), Language::Rust), TokenType::Keyword);
+        assert_eq!(classify_word("main", Some('('), Language::Rust), TokenType::Function);
+        assert_eq!(classify_word("String", Some(':'), Language::Rust), TokenType::Type);
+        assert_eq!(classify_word("fn", Some(' '), Language::Python), TokenType::Identifier);
     }
 
     #[test]
@@ -721,7 +1107,7 @@ mod tests {
 
     #[test]
     fn test_unmatched_bracket_highlight_multiline() {
-        let lines = vec!["fn main() {", "    let x = 1;"];
+        let lines = ["fn main() {", "    let x = 1;"];
         let theme = Theme::default();
         
         // 1パス目: ファイル全体をスキャンして未対応の括弧を特定
@@ -755,4 +1141,181 @@ mod tests {
         assert_eq!(bracket_span.style.fg, Some(theme.syntax.unmatched_bracket_fg.clone().into()));
         assert_eq!(bracket_span.style.bg, Some(theme.syntax.unmatched_bracket_bg.clone().into()));
     }
+
+    #[test]
+    fn test_language_from_filename() {
+        assert_eq!(Language::from_filename(Some("src/main.rs")), Language::Rust);
+        assert_eq!(Language::from_filename(Some("script.py")), Language::Python);
+        assert_eq!(Language::from_filename(Some("app.js")), Language::JavaScript);
+        assert_eq!(Language::from_filename(Some("app.tsx")), Language::JavaScript);
+        assert_eq!(Language::from_filename(Some("package.json")), Language::Json);
+        assert_eq!(Language::from_filename(Some("Cargo.toml")), Language::Toml);
+        assert_eq!(Language::from_filename(Some("README.md")), Language::Markdown);
+        assert_eq!(Language::from_filename(Some("notes.txt")), Language::PlainText);
+        assert_eq!(Language::from_filename(Some("Makefile")), Language::PlainText);
+        assert_eq!(Language::from_filename(None), Language::Rust);
+    }
+
+    #[test]
+    fn test_tokenize_data_and_text_languages() {
+        let mut bracket_state = BracketState::with_language(Language::Toml);
+        let tokens = tokenize_with_state("name = 'vim' # comment", 0, 0, &mut bracket_state);
+        assert!(tokens.iter().any(|t| t.content == "'vim'" && t.token_type == TokenType::String));
+        assert_eq!(tokens.last().unwrap().token_type, TokenType::Comment);
+
+        let mut bracket_state = BracketState::with_language(Language::Json);
+        let tokens = tokenize_with_state("{\"a\": true} // no", 0, 0, &mut bracket_state);
+        assert!(tokens.iter().any(|t| t.content == "true" && t.token_type == TokenType::Keyword));
+        assert!(tokens.iter().all(|t| t.token_type != TokenType::Comment));
+
+        // 文章の ' や大文字の単語は文字列・型として扱わない
+        let mut bracket_state = BracketState::with_language(Language::PlainText);
+        let tokens = tokenize_with_state("Don't panic // ok", 0, 0, &mut bracket_state);
+        assert!(tokens.iter().all(|t| !matches!(t.token_type, TokenType::String | TokenType::Comment | TokenType::Type)));
+
+        let mut bracket_state = BracketState::with_language(Language::Markdown);
+        let tokens = tokenize_with_state("Run `cargo test` <!-- todo -->", 0, 0, &mut bracket_state);
+        assert!(tokens.iter().any(|t| t.content == "`cargo test`" && t.token_type == TokenType::String));
+        assert_eq!(tokens.last().unwrap().token_type, TokenType::Comment);
+    }
+
+    #[test]
+    fn test_tokenize_python_def() {
+        let mut bracket_state = BracketState::with_language(Language::Python);
+        let tokens = tokenize_with_state("def foo():  # comment", 0, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "def");
+        assert_eq!(tokens[0].token_type, TokenType::Keyword);
+        assert_eq!(tokens[2].content, "foo");
+        assert_eq!(tokens[2].token_type, TokenType::Function);
+        let comment = tokens.last().unwrap();
+        assert_eq!(comment.content, "# comment");
+        assert_eq!(comment.token_type, TokenType::Comment);
+    }
+
+    #[test]
+    fn test_unterminated_string_continues_on_next_lines() {
+        // Rust の文字列は改行を含められるので、閉じるまで次の行も文字列になる
+        let mut bracket_state = BracketState::new();
+        let tokens = tokenize_with_state("let s = \"open (", 0, 0, &mut bracket_state);
+        assert_eq!(tokens.last().unwrap().content, "\"open (");
+        assert_eq!(bracket_state.string_quote, Some('"'));
+        let tokens = tokenize_with_state("fn { \\\" still", 1, 0, &mut bracket_state);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        let tokens = tokenize_with_state("end\"; let x = 1;", 2, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "end\"");
+        assert_eq!(bracket_state.string_quote, None);
+        assert!(tokens.iter().any(|t| t.content == "let" && t.token_type == TokenType::Keyword));
+        assert!(bracket_state.stack.is_empty());
+
+        // Python の文字列は行末の \ があるときだけ続く
+        let mut bracket_state = BracketState::with_language(Language::Python);
+        tokenize_with_state("s = 'open", 0, 0, &mut bracket_state);
+        assert_eq!(bracket_state.string_quote, None);
+        tokenize_with_state("s = 'open \\", 0, 0, &mut bracket_state);
+        assert_eq!(bracket_state.string_quote, Some('\''));
+        let tokens = tokenize_with_state("close' + x", 1, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "close'");
+        assert_eq!(bracket_state.string_quote, None);
+    }
+
+    #[test]
+    fn test_tokenize_raw_strings() {
+        let tokens = tokenize_with_state("let s = r\"plain\";", 0, 0, &mut BracketState::new());
+        let string = tokens.iter().find(|t| t.token_type == TokenType::String).unwrap();
+        assert_eq!(string.content, "r\"plain\"");
+
+        let line = "r#\"with \"quotes\"\"# + x";
+        let tokens = tokenize_with_state(line, 0, 0, &mut BracketState::new());
+        assert_eq!(tokens[0].content, "r#\"with \"quotes\"\"#");
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(tokens.last().unwrap().content, "x");
+
+        // # 付きの生文字列では \" で閉じない
+        let tokens = tokenize_with_state("r#\"a\\\"b\"#;", 0, 0, &mut BracketState::new());
+        assert_eq!(tokens[0].content, "r#\"a\\\"b\"#");
+
+        // r だけの識別子や r# の後に " が無いものは生文字列ではない
+        let tokens = tokenize_with_state("r + br", 0, 0, &mut BracketState::new());
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+
+        // 閉じていない生文字列は次の行へ続く
+        let mut bracket_state = BracketState::new();
+        let tokens = tokenize_with_state("let s = r##\"open \"# (", 0, 0, &mut bracket_state);
+        assert_eq!(tokens.last().unwrap().content, "r##\"open \"# (");
+        assert_eq!(bracket_state.raw_string_hashes, Some(2));
+        let tokens = tokenize_with_state("still ) \"##; ok", 1, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "still ) \"##");
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(bracket_state.raw_string_hashes, None);
+        assert!(bracket_state.stack.is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_javascript_block_comment_across_lines() {
+        let mut bracket_state = BracketState::with_language(Language::JavaScript);
+        let tokens = tokenize_with_state("const x = 1; /* start", 0, 0, &mut bracket_state);
+        assert_eq!(tokens[0].token_type, TokenType::Keyword);
+        assert_eq!(tokens.last().unwrap().token_type, TokenType::Comment);
+        assert!(bracket_state.in_block_comment());
+
+        let tokens = tokenize_with_state("end */ function f() {}", 1, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "end */");
+        assert_eq!(tokens[0].token_type, TokenType::Comment);
+        assert!(!bracket_state.in_block_comment());
+        assert!(tokens.iter().any(|t| t.content == "function" && t.token_type == TokenType::Keyword));
+    }
+
+    #[test]
+    fn test_block_comment_spanning_three_lines() {
+        let mut bracket_state = BracketState::new();
+        let lines = ["let a = 1; /* open", "still { comment", "close */ let b = 2;"];
+
+        let tokens = tokenize_with_state(lines[0], 0, 0, &mut bracket_state);
+        assert_eq!(tokens.last().unwrap().content, "/* open");
+        assert!(bracket_state.in_block_comment());
+
+        // コメント中の括弧は対応付けの対象にしない
+        let tokens = tokenize_with_state(lines[1], 1, 0, &mut bracket_state);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, TokenType::Comment);
+        assert!(bracket_state.stack.is_empty());
+
+        let tokens = tokenize_with_state(lines[2], 2, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "close */");
+        assert_eq!(tokens[0].token_type, TokenType::Comment);
+        assert!(!bracket_state.in_block_comment());
+        assert!(tokens.iter().any(|t| t.content == "let" && t.token_type == TokenType::Keyword));
+    }
+
+    #[test]
+    fn test_nested_block_comment_across_lines() {
+        let mut bracket_state = BracketState::new();
+        let tokens = tokenize_with_state("x /* outer /* inner */ still", 0, 0, &mut bracket_state);
+        assert_eq!(tokens.last().unwrap().content, "/* outer /* inner */ still");
+        assert_eq!(bracket_state.block_comment_depth, 1);
+
+        let tokens = tokenize_with_state("/* again */ */ fn f() {}", 1, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "/* again */ */");
+        assert_eq!(tokens[0].token_type, TokenType::Comment);
+        assert!(!bracket_state.in_block_comment());
+        assert!(tokens.iter().any(|t| t.content == "fn" && t.token_type == TokenType::Keyword));
+
+        // JavaScript のブロックコメントは入れ子にならない
+        let mut bracket_state = BracketState::with_language(Language::JavaScript);
+        let tokens = tokenize_with_state("/* a /* b */ c */", 0, 0, &mut bracket_state);
+        assert_eq!(tokens[0].content, "/* a /* b */");
+        assert!(!bracket_state.in_block_comment());
+    }
+
+    #[test]
+    fn test_unterminated_block_comment_runs_to_end_of_file() {
+        let mut bracket_state = BracketState::new();
+        tokenize_with_state("/* never closed", 0, 0, &mut bracket_state);
+        for (i, line) in ["fn main() {", "", "}"].iter().enumerate() {
+            let tokens = tokenize_with_state(line, i + 1, 0, &mut bracket_state);
+            assert!(tokens.iter().all(|t| t.token_type == TokenType::Comment));
+            assert!(bracket_state.in_block_comment());
+        }
+    }
 }
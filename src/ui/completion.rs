@@ -1,5 +1,5 @@
 use crate::app::App;
-use crate::constants::editor;
+use crate::ui::editor::gutter_width;
 use ratatui::{
     layout::Rect,
     style::Style,
@@ -7,76 +7,48 @@ use ratatui::{
     widgets::{Block, Borders, Clear, Paragraph},
     Frame,
 };
-use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub fn draw_completion_popup(f: &mut Frame, app: &mut App, editor_rect: Rect) {
     let current_window = app.current_window();
-    let show_line_numbers = app.config.editor.show_line_numbers;
     let horizontal_margin = app.config.ui.editor_margins.horizontal;
-    let line_number_width = if show_line_numbers { editor::DEFAULT_LINE_NUMBER_WIDTH } else { 0 };
-    let separator_width = if show_line_numbers { editor::LINE_NUMBER_SEPARATOR_WIDTH } else { 0 };
     
     // カーソル位置を計算
-    let cursor_width = current_window.buffer()[current_window.cursor_y()]
-        .graphemes(true)
-        .take(current_window.cursor_x())
-        .map(|g| g.width())
-        .sum::<usize>();
+    let cursor_width = current_window.cursor_display_x();
     
-    let text_start_x_offset = horizontal_margin as usize + line_number_width + separator_width;
-    let cursor_x = editor_rect.x + text_start_x_offset as u16 + (cursor_width - current_window.scroll_x()) as u16;
-    let cursor_y = editor_rect.y + 1 + (current_window.cursor_y() - current_window.scroll_y()) as u16;
-    
-    // 予測変換ポップアップのサイズを計算
-    let max_items = 10;
-    let visible_items = app.completions.len().min(max_items);
-    let popup_height = visible_items as u16 + 2; // ボーダー分を追加
-    
-    // 最大幅を計算（最長の補完候補に基づく）
-    let max_width = app.completions.iter()
-        .map(|s| s.width())
-        .max()
-        .unwrap_or(10)
-        .max(10) as u16 + 4; // パディング分を追加
-    
-    // ポップアップの位置を計算（カーソルの下）
-    let popup_x = cursor_x.min(f.size().width.saturating_sub(max_width));
-    let popup_y = if cursor_y + 1 + popup_height <= f.size().height {
-        cursor_y + 1 // カーソルの下
-    } else {
-        cursor_y.saturating_sub(popup_height) // カーソルの上
-    };
-    
-    let popup_rect = Rect {
-        x: popup_x,
-        y: popup_y,
-        width: max_width,
-        height: popup_height,
+    let text_start_x_offset = horizontal_margin as usize + gutter_width(&app.config.editor, current_window.buffer().len());
+    let cursor_x = editor_rect.x + text_start_x_offset as u16 + cursor_width.saturating_sub(current_window.scroll_x()) as u16;
+    let cursor_y = editor_rect.y + 1 + current_window.cursor_y().saturating_sub(current_window.scroll_y()) as u16;
+    draw_completion_list(f, app, cursor_x, cursor_y);
+}
+
+/// 補完候補を (cursor_x, cursor_y) の下（入りきらなければ上）に描画する
+pub fn draw_completion_list(f: &mut Frame, app: &App, cursor_x: u16, cursor_y: u16) {
+    let longest = app.completions.iter().map(|s| s.width()).max().unwrap_or(0);
+    let Some(popup_rect) = completion_popup_rect(f.size(), cursor_x, cursor_y, app.completions.len(), longest, app.config.ui.completion_max_width) else {
+        return;
     };
+    let visible_items = popup_rect.height as usize - 2;
+    let item_width = popup_rect.width as usize - 4;
     
     // 背景をクリア
     f.render_widget(Clear, popup_rect);
     
     // スクロール位置を計算
-    let scroll_offset = if app.selected_completion >= max_items {
-        app.selected_completion - max_items + 1
-    } else {
-        0
-    };
+    let scroll_offset = app.selected_completion.map_or(0, |i| (i + 1).saturating_sub(visible_items));
     
-    // 表示する補完候補を準備
+    // 表示する補完候補を準備（入りきらない候補は省略記号で切り詰める）
     let completion_lines: Vec<Line> = app.completions
         .iter()
         .enumerate()
         .skip(scroll_offset)
-        .take(max_items)
+        .take(visible_items)
         .map(|(i, completion)| {
-            let actual_index = i + scroll_offset;
-            if actual_index == app.selected_completion {
+            let text = truncate_with_ellipsis(completion, item_width);
+            if Some(i) == app.selected_completion {
                 // 選択されている項目
                 Line::from(Span::styled(
-                    completion.clone(),
+                    text,
                     Style::default()
                         .bg(app.config.theme.ui.completion_selection_background.clone().into())
                         .fg(app.config.theme.ui.completion_foreground.clone().into())
@@ -84,7 +56,7 @@ pub fn draw_completion_popup(f: &mut Frame, app: &mut App, editor_rect: Rect) {
             } else {
                 // 通常の項目
                 Line::from(Span::styled(
-                    completion.clone(),
+                    text,
                     Style::default()
                         .fg(app.config.theme.ui.completion_foreground.clone().into())
                 ))
@@ -101,4 +73,77 @@ pub fn draw_completion_popup(f: &mut Frame, app: &mut App, editor_rect: Rect) {
         .block(popup_block);
     
     f.render_widget(popup_paragraph, popup_rect);
-}
\ No newline at end of file
+}
+
+/// 補完ポップアップの位置と大きさ。幅は最長の候補（最大 `max_width`）、高さは候補数（最大 10 件）に合わせ、
+/// カーソルの下に入りきらなければ広い方（上か下）に収まる分だけ表示する。表示できる場所が無ければ None
+fn completion_popup_rect(screen: Rect, cursor_x: u16, cursor_y: u16, items: usize, longest: usize, max_width: u16) -> Option<Rect> {
+    let max_items = 10;
+    // ボーダーと左右の余白の分を加える
+    let width = (longest.max(10) as u16).saturating_add(4).min(max_width).min(screen.width);
+    let wanted_height = items.min(max_items) as u16 + 2;
+    let below = screen.bottom().saturating_sub(cursor_y + 1);
+    let above = cursor_y.saturating_sub(screen.y);
+    let (y, height) = if wanted_height <= below {
+        (cursor_y + 1, wanted_height)
+    } else if below >= above {
+        (cursor_y + 1, below)
+    } else {
+        let height = wanted_height.min(above);
+        (cursor_y - height, height)
+    };
+    if height < 3 || width < 5 {
+        return None;
+    }
+    let x = cursor_x.min(screen.right() - width).max(screen.x);
+    Some(Rect { x, y, width, height })
+}
+
+/// 表示幅が `width` を超える文字列を、末尾を `…` にして切り詰める
+fn truncate_with_ellipsis(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if used + char_width + 1 > width {
+            break;
+        }
+        truncated.push(c);
+        used += char_width;
+    }
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_with_ellipsis() {
+        assert_eq!(truncate_with_ellipsis("short", 10), "short");
+        assert_eq!(truncate_with_ellipsis("very_long_identifier", 8), "very_lo…");
+        // 全角文字は幅2として数える
+        assert_eq!(truncate_with_ellipsis("日本語の候補", 6), "日本…");
+    }
+
+    #[test]
+    fn test_completion_popup_fits_on_screen() {
+        let screen = Rect::new(0, 0, 30, 12);
+        // 下に入りきる
+        assert_eq!(completion_popup_rect(screen, 2, 1, 3, 8, 40), Some(Rect::new(2, 2, 14, 5)));
+        // 幅は最大幅と画面幅に収め、右端ではカーソルより左に寄せる
+        assert_eq!(completion_popup_rect(screen, 25, 1, 3, 60, 20), Some(Rect::new(10, 2, 20, 5)));
+        assert_eq!(completion_popup_rect(screen, 25, 1, 3, 60, 80), Some(Rect::new(0, 2, 30, 5)));
+        // 下に入りきらなければ上に出す
+        assert_eq!(completion_popup_rect(screen, 0, 10, 3, 8, 40), Some(Rect::new(0, 5, 14, 5)));
+        // 上下どちらにも入りきらなければ広い方に収まる分だけ表示する
+        assert_eq!(completion_popup_rect(screen, 0, 4, 20, 8, 40), Some(Rect::new(0, 5, 14, 7)));
+        assert_eq!(completion_popup_rect(screen, 0, 8, 20, 8, 40), Some(Rect::new(0, 0, 14, 8)));
+        // 表示できる場所が無い
+        assert_eq!(completion_popup_rect(Rect::new(0, 0, 30, 2), 0, 0, 3, 8, 40), None);
+    }
+}
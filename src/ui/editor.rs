@@ -1,36 +1,56 @@
 use crate::app::App;
+use crate::config::EditorConfig;
 use crate::window::Mode;
-use crate::syntax::{highlight_syntax_with_state, BracketState};
+use crate::syntax::{highlight_syntax_with_state, BracketState, Language};
 use crate::constants::{editor, ui as ui_constants, file};
+use crate::utils::grapheme_width;
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Margin},
-    style::{Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
     Frame,
 };
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// 行番号の欄と区切りを合わせた幅（行番号を表示しなければ 0）。行数の桁数が設定の幅を超えれば広げる
+pub fn gutter_width(config: &EditorConfig, line_count: usize) -> usize {
+    if !config.show_line_numbers {
+        return 0;
+    }
+    config.line_number_width.max(line_count.to_string().len()) + editor::LINE_NUMBER_SEPARATOR_WIDTH
+}
 
 pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rect, window_index: usize, is_active: bool) {
+    let misspellings = app.misspellings(window_index);
+    let jump_labels = if is_active { app.jump_labels.clone() } else { Vec::new() };
     let window = &mut app.windows[window_index];
     let app_mode = app.mode;
     let config = &app.config;
+    let tab_size = window.tab_size();
+    let tab_indicator: Option<Color> = config.theme.ui.tab_indicator.clone().map(Into::into);
     
     // シンタックスハイライトの更新完了をマーク
     window.mark_syntax_updated();
 
     let border_style = if is_active { Style::default().fg(config.theme.ui.active_pane_border.clone().into()) } else { Style::default() };
-    let editor_block = Block::default().borders(Borders::ALL).title(window.filename().unwrap_or(file::DEFAULT_FILENAME)).border_style(border_style);
+    let title = window.filename().unwrap_or(file::DEFAULT_FILENAME);
+    let title = if window.is_modified() { format!("{} [+]", title) } else { title.to_string() };
+    let editor_block = Block::default().borders(Borders::ALL).title(title).border_style(border_style);
     f.render_widget(editor_block, area);
     let editor_area = area.inner(&Margin { 
         vertical: config.ui.editor_margins.vertical, 
         horizontal: config.ui.editor_margins.horizontal 
     });
 
-    window.scroll_to_cursor(editor_area.height as usize, editor_area.width as usize, config.editor.show_line_numbers);
+    let gutter_width = gutter_width(&config.editor, window.buffer().len());
+    window.scroll_to_cursor(editor_area.height as usize, editor_area.width as usize, gutter_width);
+    let view_rows = window.view_rows();
 
-    let line_number_width = if config.editor.show_line_numbers { config.editor.line_number_width } else { 0 };
     let separator_width = if config.editor.show_line_numbers { editor::LINE_NUMBER_SEPARATOR_WIDTH } else { 0 };
+    let line_number_width = gutter_width - separator_width;
+    let cursor_y = window.cursor_y();
 
     let editor_chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -42,19 +62,18 @@ pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rec
         .split(editor_area);
 
     if config.editor.show_line_numbers {
-        let line_numbers: Vec<Line> = (window.scroll_y()..window.scroll_y() + editor_area.height as usize)
-            .map(|i| {
-                if i < window.buffer().len() {
-                    Line::from(Span::styled(
-                        format!("{:>width$}", i + 1, width = line_number_width), 
-                        Style::default().fg(config.theme.ui.line_number.clone().into())
-                    ))
-                } else {
-                    Line::from(Span::styled(
-                        format!("{:>width$}", ui_constants::EMPTY_LINE_MARKER, width = line_number_width), 
-                        Style::default().fg(config.theme.ui.line_number.clone().into())
-                    ))
-                }
+        let line_numbers: Vec<Line> = (0..editor_area.height as usize)
+            .map(|row| match view_rows.get(row) {
+                Some(&(i, 0, _)) => Line::from(Span::styled(
+                    format!("{:>width$}", config.editor.line_number_mode.label(i, cursor_y), width = line_number_width), 
+                    Style::default().fg(config.theme.ui.line_number.clone().into())
+                )),
+                // 折り返した行の続きには行番号を表示しない
+                Some(_) => Line::from(""),
+                None => Line::from(Span::styled(
+                    format!("{:>width$}", ui_constants::EMPTY_LINE_MARKER, width = line_number_width), 
+                    Style::default().fg(config.theme.ui.line_number.clone().into())
+                )),
             })
             .collect();
         let line_numbers_paragraph = Paragraph::new(line_numbers).alignment(Alignment::Right);
@@ -67,8 +86,9 @@ pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rec
     // 1パス目: ファイル全体をスキャンし、未対応の括弧を特定し、
     //          同時に各行の開始時点での BracketState をキャッシュする
     let mut states_by_line = Vec::with_capacity(window.buffer().len() + 1);
-    states_by_line.push(BracketState::new());
-    let mut current_state = BracketState::new();
+    let language = Language::from_filename(window.filename());
+    states_by_line.push(BracketState::with_language(language));
+    let mut current_state = BracketState::with_language(language);
     let mut all_unmatched_brackets: std::collections::HashSet<(usize, usize)> = std::collections::HashSet::new();
     
     for (i, line_str) in window.buffer().iter().enumerate() {
@@ -96,7 +116,7 @@ pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rec
     let unmatched_brackets = all_unmatched_brackets; // 名前を合わせる
 
     // 2. 表示範囲の行をレンダリングする
-    let text: Vec<Line> = window
+    let lines: Vec<Line> = window
         .buffer()
         .iter()
         .enumerate()
@@ -106,6 +126,49 @@ pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rec
             // キャッシュした状態を使ってハイライト
             let mut bracket_state = states_by_line[i].clone();
 
+            if let (Mode::VisualLine, Some((first, last))) = (&app_mode, window.line_selection()) {
+                if is_active && (first..=last).contains(&i) {
+                    // 行単位の選択は行の表示幅全体をハイライトする
+                    let selection_bg = config.theme.ui.visual_selection_background.clone().into();
+                    let mut spans: Vec<Span<'static>> = highlight_syntax_with_state(line_str, i, config.editor.indent_width, &mut bracket_state, &config.theme, &unmatched_brackets)
+                        .into_iter()
+                        .map(|mut span| {
+                            span.style = span.style.bg(selection_bg);
+                            span
+                        })
+                        .collect();
+                    let line_width = window.display_x(line_str.graphemes(true).count(), i);
+                    let padding = (window.scroll_x() + editor_chunks[2].width as usize).saturating_sub(line_width);
+                    spans.push(Span::styled(" ".repeat(padding), Style::default().bg(selection_bg)));
+                    return Line::from(expand_tabs(spans, tab_size, tab_indicator));
+                }
+            }
+
+            if let (Mode::VisualBlock, Some((top, bottom, left, right))) = (&app_mode, window.block_selection()) {
+                if is_active && (top..=bottom).contains(&i) {
+                    // 矩形選択は各行の同じ表示カラム範囲をハイライトする（短い行は行末まで）
+                    let graphemes: Vec<&str> = line_str.graphemes(true).collect();
+                    let (highlight_start, highlight_end) = window.block_range_in_line(i, left, right);
+                    let selection_bg: ratatui::style::Color = config.theme.ui.visual_selection_background.clone().into();
+                    let mut spans = Vec::new();
+                    for (range, selected) in [(0..highlight_start, false), (highlight_start..highlight_end, true), (highlight_end..graphemes.len(), false)] {
+                        if range.is_empty() {
+                            continue;
+                        }
+                        let s = graphemes[range].concat();
+                        spans.extend(highlight_syntax_with_state(&s, i, config.editor.indent_width, &mut bracket_state, &config.theme, &unmatched_brackets)
+                            .into_iter()
+                            .map(|mut span| {
+                                if selected {
+                                    span.style = span.style.bg(selection_bg);
+                                }
+                                span
+                            }));
+                    }
+                    return Line::from(expand_tabs(spans, tab_size, tab_indicator));
+                }
+            }
+
             if let (Mode::Visual, Some(start)) = (&app_mode, window.visual_start()) {
                 if is_active {
                     let (start_x, start_y) = start;
@@ -148,28 +211,314 @@ pub fn draw_editor_pane(f: &mut Frame, app: &mut App, area: ratatui::layout::Rec
                             let s = graphemes[highlight_end..line_len].join("");
                             spans.extend(highlight_syntax_with_state(&s, i, config.editor.indent_width, &mut bracket_state, &config.theme, &unmatched_brackets));
                         }
-                        return Line::from(spans);
+                        return Line::from(expand_tabs(spans, tab_size, tab_indicator));
                     }
                 }
             }
 
-            let mut spans = highlight_syntax_with_state(line_str, i, config.editor.indent_width, &mut bracket_state, &config.theme, &unmatched_brackets);
-            if let Some((bx, by)) = window.matching_bracket() {
-                if by == i {
-                    let mut current_width = 0;
-                    for span in &mut spans {
-                        let span_width = span.width();
-                        if current_width <= bx && bx < current_width + span_width {
-                            span.style = span.style.add_modifier(ratatui::style::Modifier::UNDERLINED);
-                            break;
-                        }
-                        current_width += span_width;
-                    }
-                }
+            let spans = highlight_syntax_with_state(line_str, i, config.editor.indent_width, &mut bracket_state, &config.theme, &unmatched_brackets);
+            // 辞書にない単語に下線を引く
+            let misspelled: Vec<(usize, usize)> = misspellings.iter().filter(|&&(y, _, _)| y == i).map(|&(_, start, end)| (start, end)).collect();
+            let spans = style_byte_ranges(spans, &misspelled, Style::default().add_modifier(Modifier::UNDERLINED));
+            // カーソル下の括弧と対応する括弧の両方を専用のスタイルで表示する（位置は書記素から行頭のバイト位置に直す）
+            let brackets: Vec<(usize, usize)> = window
+                .matching_bracket_pair()
+                .filter(|_| is_active)
+                .into_iter()
+                .flatten()
+                .filter(|&(_, by)| by == i)
+                .filter_map(|(bx, _)| line_str.grapheme_indices(true).nth(bx).map(|(start, g)| (start, start + g.len())))
+                .collect();
+            let spans = style_byte_ranges(spans, &brackets, config.theme.ui.matching_bracket_style());
+            // 行末の空白（入力中の行は除く）
+            let editing = is_active && app_mode == Mode::Insert && i == cursor_y;
+            let trailing: Vec<(usize, usize)> = trailing_whitespace_range(line_str)
+                .filter(|_| config.editor.show_trailing_whitespace && !editing)
+                .into_iter()
+                .collect();
+            let spans = style_byte_ranges(spans, &trailing, Style::default().bg(config.theme.ui.trailing_whitespace_background.clone().into()));
+            let mut spans = expand_tabs(spans, tab_size, tab_indicator);
+            if is_active && config.editor.cursorline && i == cursor_y {
+                // 折り返し表示では最後の表示行の右端まで届くよう多めに埋める（はみ出した分は描かれない）
+                let text_width = editor_chunks[2].width as usize;
+                let fill_to = if window.word_wrap() {
+                    window.display_x(line_str.graphemes(true).count(), i) + text_width
+                } else {
+                    window.scroll_x() + text_width
+                };
+                spans = highlight_cursor_line(spans, config.theme.ui.cursor_line_background.clone().into(), fill_to);
             }
             Line::from(spans)
         })
         .collect();
+    // 折り返し表示では、描画した行を表示行ごとのカラム範囲に切り分ける
+    let text: Vec<Line> = if window.word_wrap() {
+        view_rows
+            .iter()
+            .map(|&(y, start, end)| {
+                let from = window.display_x(start, y);
+                let to = if end < window.buffer()[y].graphemes(true).count() { window.display_x(end, y) } else { usize::MAX };
+                Line::from(slice_columns(&lines[y - window.scroll_y()].spans, from, to))
+            })
+            .collect()
+    } else {
+        lines
+    };
     let editor_paragraph = Paragraph::new(text).scroll((0, window.scroll_x() as u16));
     f.render_widget(editor_paragraph, editor_chunks[2]);
-}
\ No newline at end of file
+
+    // 表示範囲からはみ出した行の端にマーカーを描画する
+    if config.editor.show_overflow_markers && !window.word_wrap() {
+        let text_area = editor_chunks[2];
+        let marker_style = Style::default().fg(config.theme.ui.line_number.clone().into());
+        let right_edge = text_area.x + text_area.width.saturating_sub(1);
+        for (row, line_str) in window.buffer().iter().skip(window.scroll_y()).take(text_area.height as usize).enumerate() {
+            let line_width = window.display_x(line_str.graphemes(true).count(), window.scroll_y() + row);
+            let y = text_area.y + row as u16;
+            if text_area.width > 0 && line_width > window.scroll_x() + text_area.width as usize {
+                f.buffer_mut().get_mut(right_edge, y).set_symbol(ui_constants::OVERFLOW_RIGHT_MARKER).set_style(marker_style);
+            }
+            if window.scroll_x() > 0 && line_width > 0 {
+                f.buffer_mut().get_mut(text_area.x, y).set_symbol(ui_constants::OVERFLOW_LEFT_MARKER).set_style(marker_style);
+            }
+        }
+    }
+
+    // 追加カーソルは反転表示で描画する
+    if is_active {
+        let text_area = editor_chunks[2];
+        for &(x, y) in window.extra_cursors() {
+            let Some((row, col)) = window.view_position(x, y) else {
+                continue;
+            };
+            if row < text_area.height as usize && col < text_area.width as usize {
+                f.buffer_mut()
+                    .get_mut(text_area.x + col as u16, text_area.y + row as u16)
+                    .set_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+        }
+    }
+
+    // 文字ジャンプのラベルを移動先の文字に重ねて描画する
+    let text_area = editor_chunks[2];
+    let label_background: Color = config.theme.ui.jump_label_background.clone().unwrap_or_else(|| config.theme.ui.selection_background.clone()).into();
+    let label_style = Style::default().fg(Color::Black).bg(label_background).add_modifier(Modifier::BOLD);
+    for &(label, x, y) in &jump_labels {
+        let Some((row, col)) = window.view_position(x, y) else {
+            continue;
+        };
+        if row < text_area.height as usize && col < text_area.width as usize {
+            f.buffer_mut()
+                .get_mut(text_area.x + col as u16, text_area.y + row as u16)
+                .set_char(label)
+                .set_style(label_style);
+        }
+    }
+}
+
+/// スパン中のタブを次のタブストップまでの空白に展開する（カラムはスパンをまたいで数える）。
+/// `indicator` があればタブの先頭に → をその色で表示する
+fn expand_tabs(spans: Vec<Span<'static>>, tab_size: usize, indicator: Option<Color>) -> Vec<Span<'static>> {
+    let mut column = 0;
+    let mut result = Vec::with_capacity(spans.len());
+    for span in spans {
+        if !span.content.contains('\t') {
+            column += span.content.width();
+            result.push(span);
+            continue;
+        }
+        let mut expanded = String::with_capacity(span.content.len());
+        for g in span.content.graphemes(true) {
+            let width = grapheme_width(g, column, tab_size);
+            column += width;
+            if g != "\t" {
+                expanded.push_str(g);
+                continue;
+            }
+            match indicator {
+                Some(color) if width > 0 => {
+                    if !expanded.is_empty() {
+                        result.push(Span::styled(std::mem::take(&mut expanded), span.style));
+                    }
+                    result.push(Span::styled("→", span.style.fg(color)));
+                    expanded.push_str(&" ".repeat(width - 1));
+                }
+                _ => expanded.push_str(&" ".repeat(width)),
+            }
+        }
+        if !expanded.is_empty() {
+            result.push(Span::styled(expanded, span.style));
+        }
+    }
+    result
+}
+
+/// 行末の空白・タブのバイト範囲
+fn trailing_whitespace_range(line: &str) -> Option<(usize, usize)> {
+    let content_end = line.trim_end_matches([' ', '\t']).len();
+    (content_end < line.len()).then_some((content_end, line.len()))
+}
+
+/// カーソル行のスパンのうち背景色の無い部分に `background` を付け、表示カラム `fill_to` まで同じ背景の空白で埋める
+fn highlight_cursor_line(spans: Vec<Span<'static>>, background: Color, fill_to: usize) -> Vec<Span<'static>> {
+    let width: usize = spans.iter().map(Span::width).sum();
+    let mut spans: Vec<Span<'static>> = spans
+        .into_iter()
+        .map(|mut span| {
+            if span.style.bg.is_none() {
+                span.style = span.style.bg(background);
+            }
+            span
+        })
+        .collect();
+    if fill_to > width {
+        spans.push(Span::styled(" ".repeat(fill_to - width), Style::default().bg(background)));
+    }
+    spans
+}
+
+/// 表示カラム `from` 以上 `to` 未満に描かれる部分だけをスパンから切り出す
+fn slice_columns(spans: &[Span<'static>], from: usize, to: usize) -> Vec<Span<'static>> {
+    let mut column = 0;
+    let mut result = Vec::new();
+    for span in spans {
+        let mut content = String::new();
+        for g in span.content.graphemes(true) {
+            if (from..to).contains(&column) {
+                content.push_str(g);
+            }
+            column += g.width();
+        }
+        if !content.is_empty() {
+            result.push(Span::styled(content, span.style));
+        }
+    }
+    result
+}
+
+/// 行頭からのバイト範囲 `ranges` に当たる部分でスパンを分割し、`patch` のスタイルを重ねる
+fn style_byte_ranges(spans: Vec<Span<'static>>, ranges: &[(usize, usize)], patch: Style) -> Vec<Span<'static>> {
+    if ranges.is_empty() {
+        return spans;
+    }
+    let mut result = Vec::with_capacity(spans.len());
+    let mut offset = 0;
+    for span in spans {
+        let len = span.content.len();
+        let mut cuts = vec![0, len];
+        for &(start, end) in ranges {
+            for cut in [start, end] {
+                if cut > offset && cut < offset + len && span.content.is_char_boundary(cut - offset) {
+                    cuts.push(cut - offset);
+                }
+            }
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for pair in cuts.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let mut style = span.style;
+            if ranges.iter().any(|&(start, end)| start <= offset + from && offset + to <= end) {
+                style = style.patch(patch);
+            }
+            result.push(Span::styled(span.content[from..to].to_string(), style));
+        }
+        offset += len;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LineNumberMode;
+    use crate::window::Window;
+
+    #[test]
+    fn test_line_number_modes_and_gutter_width() {
+        assert_eq!(LineNumberMode::Absolute.label(4, 2), 5);
+        assert_eq!(LineNumberMode::Relative.label(4, 2), 2);
+        assert_eq!(LineNumberMode::Relative.label(2, 2), 0);
+        assert_eq!(LineNumberMode::Hybrid.label(2, 2), 3);
+        assert_eq!(LineNumberMode::Hybrid.label(0, 2), 2);
+
+        // 9999 行を超えたら全桁が入るように広げる
+        let mut config = EditorConfig::default();
+        assert_eq!(gutter_width(&config, 9999), 5);
+        assert_eq!(gutter_width(&config, 123_456), 7);
+        config.show_line_numbers = false;
+        assert_eq!(gutter_width(&config, 123_456), 0);
+    }
+
+    #[test]
+    fn test_cursor_line_keeps_existing_backgrounds_and_fills_to_edge() {
+        let indent = Style::default().bg(Color::Red);
+        let keyword = Style::default().fg(Color::Blue);
+        let spans = vec![Span::styled("  ", indent), Span::styled("fn", keyword)];
+        let highlighted = highlight_cursor_line(spans, Color::DarkGray, 8);
+        assert_eq!(drawn(&highlighted), "  fn    ");
+        // インデントの背景色はそのまま、構文の文字色には背景だけを重ねる
+        assert_eq!(highlighted[0].style, indent);
+        assert_eq!(highlighted[1].style, keyword.bg(Color::DarkGray));
+        assert_eq!(highlighted[2].style, Style::default().bg(Color::DarkGray));
+
+        // 行が表示幅より長ければ埋めない
+        let highlighted = highlight_cursor_line(vec![Span::raw("long line")], Color::DarkGray, 4);
+        assert_eq!(highlighted.len(), 1);
+    }
+
+    #[test]
+    fn test_trailing_whitespace_range() {
+        assert_eq!(trailing_whitespace_range("let x = 1;  \t"), Some((10, 13)));
+        assert_eq!(trailing_whitespace_range("日本 "), Some((6, 7)));
+        assert_eq!(trailing_whitespace_range("   "), Some((0, 3)));
+        assert_eq!(trailing_whitespace_range("  indented"), None);
+        assert_eq!(trailing_whitespace_range(""), None);
+    }
+
+    #[test]
+    fn test_style_byte_ranges_marks_bracket_after_wide_characters() {
+        let bracket = Style::default().fg(Color::Yellow);
+        let spans = vec![Span::raw("日本"), Span::styled("(x)", Style::default().fg(Color::Blue))];
+        let styled = style_byte_ranges(spans, &[(6, 7), (8, 9)], bracket);
+        assert_eq!(drawn(&styled), "日本(x)");
+        let marked: Vec<&str> = styled.iter().filter(|span| span.style.fg == Some(Color::Yellow)).map(|span| span.content.as_ref()).collect();
+        assert_eq!(marked, vec!["(", ")"]);
+        assert_eq!(styled[2].content, "x");
+        assert_eq!(styled[2].style.fg, Some(Color::Blue));
+    }
+
+    fn drawn(spans: &[Span<'static>]) -> String {
+        spans.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_expand_tabs_matches_cursor_column() {
+        let line = "a\t日本\tx\t";
+        let spans = vec![Span::raw("a\t日"), Span::raw("本\tx"), Span::raw("\t")];
+        let expanded = expand_tabs(spans.clone(), 4, None);
+        assert_eq!(drawn(&expanded), "a   日本    x   ");
+
+        // 描画上の各グラフェムの位置と、カーソル計算の表示カラムが一致する
+        let mut window = Window::new(None);
+        *window.buffer_mut() = vec![line.to_string()];
+        let mut column = 0;
+        let mut drawn_at = std::collections::HashMap::new();
+        for g in drawn(&expanded).graphemes(true) {
+            drawn_at.insert(column, g.to_string());
+            column += g.width();
+        }
+        for (x, expected) in [(0, "a"), (2, "日"), (3, "本"), (5, "x")] {
+            *window.cursor_x_mut() = x;
+            assert_eq!(drawn_at[&window.cursor_display_x()], expected);
+        }
+
+        // 表示記号を使っても幅は変わらず、スタイルは元のスパンを引き継ぐ
+        let styled = vec![Span::styled("\tb\t", Style::default().bg(Color::Blue))];
+        let with_indicator = expand_tabs(styled, 4, Some(Color::DarkGray));
+        assert_eq!(drawn(&with_indicator), "→   b→  ");
+        assert_eq!(with_indicator[0].style, Style::default().bg(Color::Blue).fg(Color::DarkGray));
+        assert_eq!(with_indicator[1].style, Style::default().bg(Color::Blue));
+        assert_eq!(drawn(&expand_tabs(spans, 4, Some(Color::DarkGray))).width(), 16);
+    }
+}
@@ -1,19 +1,25 @@
 use crate::app::{App, FocusedPanel};
 use crate::utils::get_display_cursor_x;
-use crate::window::Mode;
+use crate::constants::file::DEFAULT_FILENAME;
+use crate::constants::ui::SHOWCMD_WIDTH;
+use crate::constants::editor::READING_WORDS_PER_MINUTE;
+use crate::spell;
+use crate::window::{LineEnding, Mode};
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
-    style::Style,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::Paragraph,
     Frame,
 };
+use unicode_width::UnicodeWidthStr;
 
 pub mod completion;
 pub mod editor;
 pub mod panels;
 
 pub use editor::draw_editor_pane;
-pub use completion::draw_completion_popup;
+pub use completion::{draw_completion_list, draw_completion_popup};
 pub use panels::{draw_directory_panel, draw_chat_panel, ChatPanelData};
 
 pub fn ui(f: &mut Frame, app: &mut App) {
@@ -61,8 +67,22 @@ pub fn ui(f: &mut Frame, app: &mut App) {
             .collect()
     };
     
-    for (_, window_index, rect, is_active) in pane_info {
-        draw_editor_pane(f, app, rect, window_index, is_active);
+    for (pane_id, window_index, rect, is_active) in pane_info {
+        // アクティブでないペインは、そのペインが保存している表示位置で描画する
+        let saved_view = if is_active { None } else { app.pane_manager.get_pane(pane_id).and_then(|pane| pane.view) };
+        match saved_view {
+            Some(view) => {
+                let live_view = app.windows[window_index].view();
+                app.windows[window_index].set_view(view);
+                draw_editor_pane(f, app, rect, window_index, is_active);
+                let drawn_view = app.windows[window_index].view();
+                app.windows[window_index].set_view(live_view);
+                if let Some(pane) = app.pane_manager.get_pane_mut(pane_id) {
+                    pane.view = Some(drawn_view);
+                }
+            }
+            None => draw_editor_pane(f, app, rect, window_index, is_active),
+        }
     }
 
     if app.show_directory {
@@ -86,29 +106,17 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         );
     }
 
-    let status_bar_text = match app.mode {
-        Mode::Normal => {
-            let w = app.current_window_mut();
-            format!(
-                "NORMAL | {}:{} | {}",
-                w.cursor_y() + 1,
-                w.cursor_x() + 1,
-                app.status_message
-            )
-        },
-        Mode::Insert => "INSERT".to_string(),
-        Mode::Visual => "VISUAL".to_string(),
-        Mode::Command => format!(":{}", app.command_buffer),
-        Mode::RightPanelInput => "RIGHT PANEL INPUT".to_string(),
-    };
     let status_bar_chunk = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(0), Constraint::Length(app.config.ui.status_bar_height)].as_ref())
         .split(f.size())[1];
-    let status_bar = Paragraph::new(status_bar_text).style(Style::default().bg(app.config.theme.ui.status_bar_background.clone().into()));
-    f.render_widget(status_bar, status_bar_chunk);
+    let message_x = draw_status_bar(f, app, status_bar_chunk);
 
-    if app.show_completion && !app.completions.is_empty() && !app.show_directory {
+    if app.show_completion && !app.completions.is_empty() && app.mode == Mode::Command {
+        // パスの候補はコマンドラインで補完している位置の上に出す
+        let prefix = app.command_completion_prefix.as_deref().unwrap_or_default();
+        draw_completion_list(f, app, message_x + " :".width() as u16 + prefix.width() as u16, status_bar_chunk.y);
+    } else if app.show_completion && !app.completions.is_empty() && !app.show_directory {
         if let Some(active_pane) = app.pane_manager.get_active_pane() {
             if let Some(rect) = active_pane.rect {
                 draw_completion_popup(f, app, rect);
@@ -117,25 +125,23 @@ pub fn ui(f: &mut Frame, app: &mut App) {
     }
 
     match app.focused_panel {
-        FocusedPanel::RightPanel if app.show_right_panel && !is_floating => {
-            if app.mode == Mode::RightPanelInput {
-                let right_panel_index = if app.show_directory { 2 } else { 1 };
-                let right_panel_area = main_chunks[right_panel_index];
-                let right_panel_chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Min(0),
-                        Constraint::Length(3),
-                    ])
-                    .split(right_panel_area);
-                
-                let input_area = right_panel_chunks[1].inner(&ratatui::layout::Margin { vertical: 1, horizontal: 1 });
-                let cursor_x = get_display_cursor_x(&app.right_panel_input, app.right_panel_input_cursor);
-                f.set_cursor(
-                    input_area.x + cursor_x,
-                    input_area.y,
-                );
-            }
+        FocusedPanel::RightPanel if app.show_right_panel && !is_floating && app.mode == Mode::RightPanelInput => {
+            let right_panel_index = if app.show_directory { 2 } else { 1 };
+            let right_panel_area = main_chunks[right_panel_index];
+            let right_panel_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Min(0),
+                    Constraint::Length(3),
+                ])
+                .split(right_panel_area);
+            
+            let input_area = right_panel_chunks[1].inner(&ratatui::layout::Margin { vertical: 1, horizontal: 1 });
+            let cursor_x = get_display_cursor_x(&app.right_panel_input, app.right_panel_input_cursor);
+            f.set_cursor(
+                input_area.x + cursor_x,
+                input_area.y,
+            );
         }
         FocusedPanel::Directory if app.show_directory => {
             if is_floating {
@@ -151,34 +157,15 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         FocusedPanel::Editor => {
             if let Some(active_pane) = app.pane_manager.get_active_pane() {
                 if let Some(rect) = active_pane.rect {
-                    let show_line_numbers = app.config.editor.show_line_numbers;
                     let horizontal_margin = app.config.ui.editor_margins.horizontal;
-                    let line_number_width = if show_line_numbers { app.config.editor.line_number_width } else { 0 };
-                    let separator_width = if show_line_numbers { 1 } else { 0 };
-                    let text_start_x_offset = horizontal_margin as usize + line_number_width + separator_width;
+                    let current_window = app.current_window();
+                    let text_start_x_offset = horizontal_margin as usize + editor::gutter_width(&app.config.editor, current_window.buffer().len());
                     
-                    let (cursor_x, cursor_y, scroll_x, scroll_y) = {
-                        let current_window = app.current_window();
-                        (current_window.cursor_x(), current_window.cursor_y(), current_window.scroll_x(), current_window.scroll_y())
-                    };
-
-                    let cursor_width = if app.current_window().buffer().is_empty() || cursor_y >= app.current_window().buffer().len() {
-                        0
-                    } else {
-                        use unicode_segmentation::UnicodeSegmentation;
-                        use unicode_width::UnicodeWidthStr;
-                        app.current_window().buffer()[cursor_y]
-                            .graphemes(true)
-                            .take(cursor_x)
-                            .map(|g| g.width())
-                            .sum::<usize>()
-                    };
-
-                    if cursor_y >= scroll_y &&
-                       cursor_y < scroll_y + rect.height.saturating_sub(2) as usize {
+                    // 折り返し表示も含め、カーソルが描かれる表示行とカラムに置く
+                    if let Some((row, col)) = current_window.view_position(current_window.cursor_x(), current_window.cursor_y()) {
                         f.set_cursor(
-                            rect.x + text_start_x_offset as u16 + (cursor_width - scroll_x) as u16,
-                            rect.y + 1 + (cursor_y - scroll_y) as u16,
+                            rect.x + text_start_x_offset as u16 + col as u16,
+                            rect.y + 1 + row as u16,
                         )
                     }
                 }
@@ -187,3 +174,71 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         _ => {}
     }
 }
+
+/// ステータスバーに表示するモード名
+fn mode_label(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Normal => "NORMAL",
+        Mode::Insert => "INSERT",
+        Mode::Visual => "VISUAL",
+        Mode::VisualLine => "VISUAL LINE",
+        Mode::VisualBlock => "VISUAL BLOCK",
+        Mode::Command => "COMMAND",
+        Mode::Search => "SEARCH",
+        Mode::RightPanelInput => "RIGHT PANEL INPUT",
+    }
+}
+
+/// ステータスバーを「モード | ファイル名 | メッセージ | showcmd | 位置」の区画に分けて描画し、メッセージの開始位置を返す
+fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) -> u16 {
+    let theme = &app.config.theme.ui;
+    let bar_style = Style::default().bg(theme.status_bar_background.clone().into());
+    let mode_style = Style::default()
+        .fg(Color::Black)
+        .bg(theme.status_bar_background_for(app.mode))
+        .add_modifier(Modifier::BOLD);
+
+    let window = app.current_window();
+    let mode = format!(" {} ", mode_label(app.mode));
+    let modified = if window.is_modified() { " [+]" } else { "" };
+    let filename = match window.line_ending() {
+        LineEnding::Unix => format!(" {}{} ", window.filename().unwrap_or(DEFAULT_FILENAME), modified),
+        LineEnding::Dos => format!(" {}{} [dos] ", window.filename().unwrap_or(DEFAULT_FILENAME), modified),
+    };
+    // コマンドラインと検索の入力中はメッセージの代わりに入力内容を表示する
+    let message = match app.mode {
+        Mode::Command => format!(" :{}", app.command_buffer),
+        Mode::Search => format!(" {}{}", if app.search_forward { '/' } else { '?' }, app.command_buffer),
+        _ => format!(" {}", app.status_message),
+    };
+    let pending_command = app.pending_command_text();
+    let showcmd = if app.config.editor.show_cmd && !pending_command.is_empty() {
+        format!("{:>width$} ", pending_command, width = SHOWCMD_WIDTH as usize)
+    } else {
+        String::new()
+    };
+    // 文章ファイルでは単語数と読了時間を表示する
+    let word_count = if app.config.editor.word_count && spell::is_prose_file(window.filename()) {
+        let words = window.word_count();
+        format!(" {} words, {} min read ", words, words.div_ceil(READING_WORDS_PER_MINUTE))
+    } else {
+        String::new()
+    };
+    let position = format!(" {}:{} ", window.cursor_y() + 1, window.cursor_x() + 1);
+
+    // 右側の区画を右端に寄せ、間を空白で埋める
+    let used_width = [&mode, &filename, &message, &showcmd, &word_count, &position].iter().map(|s| s.width()).sum::<usize>();
+    let padding = (area.width as usize).saturating_sub(used_width);
+    let message_x = area.x + (mode.width() + filename.width()) as u16;
+    let line = Line::from(vec![
+        Span::styled(mode, mode_style),
+        Span::styled(filename, bar_style.add_modifier(Modifier::BOLD)),
+        Span::styled(message, bar_style),
+        Span::styled(" ".repeat(padding), bar_style),
+        Span::styled(showcmd, bar_style),
+        Span::styled(word_count, bar_style),
+        Span::styled(position, mode_style),
+    ]);
+    f.render_widget(Paragraph::new(line).style(bar_style), area);
+    message_x
+}
@@ -1,6 +1,6 @@
 use reqwest::header::CONTENT_TYPE;
 use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use std::{fs, path::{Path, PathBuf}};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
@@ -54,6 +54,34 @@ pub async fn send_gemini_greeting_with_input(
     Ok(reply)
 }
 
+/// 単語移動で使う文字の分類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+/// 書記素の先頭文字から分類を判定する
+pub fn char_class(grapheme: &str) -> CharClass {
+    match grapheme.chars().next() {
+        Some(c) if c.is_whitespace() => CharClass::Whitespace,
+        Some(c) if c.is_alphanumeric() || c == '_' => CharClass::Word,
+        Some(_) => CharClass::Punctuation,
+        None => CharClass::Whitespace,
+    }
+}
+
+/// 表示カラム `column` に置いたときの書記素の表示幅（タブは次のタブストップまでの幅）
+pub fn grapheme_width(grapheme: &str, column: usize, tab_size: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_size = tab_size.max(1);
+        tab_size - column % tab_size
+    } else {
+        grapheme.width()
+    }
+}
+
 pub fn get_display_cursor_x(input: &str, cursor_grapheme: usize) -> u16 {
     input
         .graphemes(true)
@@ -62,6 +90,43 @@ pub fn get_display_cursor_x(input: &str, cursor_grapheme: usize) -> u16 {
         .sum::<usize>() as u16
 }
 
+/// `parts` を改行でつないだ内容の FNV-1a ハッシュ。Rust のバージョンによらず同じ値になるのでファイルに書き出してよい
+pub fn stable_hash<'a>(parts: impl IntoIterator<Item = &'a str>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    parts.into_iter().fold(OFFSET_BASIS, |hash, part| {
+        part.bytes().chain([b'\n']).fold(hash, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+    })
+}
+
+/// エディタのキャッシュを置くディレクトリ（`~/.cache/vim-clone`）
+pub fn cache_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".cache").join("vim-clone"))
+}
+
+/// コマンドラインのパス補完。`base` からの相対パス（または絶対パス）`partial` に続く候補を整列して返す（ディレクトリは `/` 付き）
+pub fn complete_path(base: &Path, partial: &str) -> Vec<String> {
+    let (dir, prefix) = partial.split_at(partial.rfind('/').map_or(0, |i| i + 1));
+    let Ok(read_dir) = fs::read_dir(base.join(dir)) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = read_dir
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // 隠しファイルは `.` から入力したときだけ候補にする
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
 pub fn list_directory(path: &PathBuf) -> Result<Vec<String>, std::io::Error> {
     let mut entries = Vec::new();
     if path.is_dir() {
@@ -1,8 +1,27 @@
 use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
     fs,
     io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use crate::config::EditorConfig;
+use crate::dictionary::Dictionary;
+use crate::line_buffer::{LineBuffer, LineChange};
+use crate::constants::editor::INDENT_DETECTION_LINES;
+use crate::positions;
+use crate::spell;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use crate::substitute::{Substitute, SubstituteRange};
+use crate::syntax::{count_leading_spaces, tokenize_with_state, BracketState, Language, TokenType};
+use crate::utils::{char_class, grapheme_width, CharClass};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
 // Define the editor modes
 #[derive(Copy, Clone, PartialEq)]
 pub enum Mode {
@@ -10,18 +29,189 @@ pub enum Mode {
     Insert,
     Command,
     Visual,
+    VisualLine,
+    VisualBlock,
     RightPanelInput,
+    Search,
+}
+
+/// zt / zz / zb でカーソル行を画面のどこに置くか
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ScrollAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// f / F / t / T で探す文字と向き（`;` と `,` で繰り返す）
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CharSearch {
+    pub target: char,
+    pub forward: bool,
+    /// t / T のように文字の手前（後ろ向きなら直後）で止まるか
+    pub till: bool,
+}
+
+/// オペレータと組み合わせるモーション（dw / de / d$）
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Motion {
+    WordForward,
+    WordEnd,
+    LineEnd,
+}
+
+/// ヤンクしたテキストの種類（行単位か文字単位か）
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum YankKind {
+    #[default]
+    CharWise,
+    LineWise,
+    /// 矩形選択（各行の切り出しを改行で連結したもの）
+    Block,
+}
+
+/// ファイルの内容から推定したインデントの種類
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum IndentStyle {
+    Tabs,
+    /// 1段あたりの空白数
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// 先頭の `INDENT_DETECTION_LINES` 行のインデントからタブか空白か（空白なら幅）を推定する
+    pub fn detect(lines: &[String]) -> Option<Self> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        // 連続する行のインデント幅の差分ごとの出現回数
+        let mut widths = [0usize; 9];
+        let mut previous = 0;
+        for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+            if tab_lines + space_lines >= INDENT_DETECTION_LINES {
+                break;
+            }
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let spaces = line.chars().take_while(|&c| c == ' ').count();
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            if let Some(count) = widths.get_mut(spaces.abs_diff(previous)) {
+                *count += 1;
+            }
+            previous = spaces;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            None
+        } else if tab_lines > space_lines {
+            Some(IndentStyle::Tabs)
+        } else {
+            // 最も多く現れた差分を1段の幅とみなす（同数なら小さい方）。1〜8 の差分が無ければ推定しない
+            (1..widths.len())
+                .rev()
+                .filter(|&w| widths[w] > 0)
+                .max_by_key(|&w| widths[w])
+                .map(IndentStyle::Spaces)
+        }
+    }
+}
+
+/// ファイルの改行コード（保存時に使う）
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum LineEnding {
+    #[default]
+    Unix,
+    Dos,
+}
+
+impl LineEnding {
+    /// CRLF を含むファイルは DOS 形式とみなす
+    pub fn detect(content: &str) -> Self {
+        if content.contains("\r\n") {
+            LineEnding::Dos
+        } else {
+            LineEnding::Unix
+        }
+    }
+
+    /// `:set fileformat=` で指定する名前から変換する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Unix),
+            "dos" => Some(LineEnding::Dos),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "unix",
+            LineEnding::Dos => "dos",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Dos => "\r\n",
+        }
+    }
 }
 
-#[derive(Clone)]
-pub struct WindowState {
-    pub buffer: Vec<String>,
+/// ペインごとに持つ表示位置（カーソルとスクロール）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ViewState {
     pub cursor_x: usize,
     pub cursor_y: usize,
+    pub scroll_x: usize,
+    pub scroll_y: usize,
+}
+
+/// 矩形選択からの I / A で、挿入モード終了時に残りの行へ反映する挿入
+#[derive(Clone, Copy, Debug)]
+struct BlockInsert {
+    /// 先頭行で挿入を始めた書記素インデックス
+    start_x: usize,
+    /// 先頭行以外の対象行
+    first: usize,
+    last: usize,
+    /// 挿入する表示カラム
+    column: usize,
+    /// 短い行を空白で埋めて挿入するか（A）、飛ばすか（I）
+    pad: bool,
+}
+
+/// undo / redo の1単位。変更のあった行の範囲だけを変更前後の内容で持つ
+#[derive(Clone, Serialize, Deserialize)]
+struct UndoEntry {
+    /// 変更のあった最初の行
+    start: usize,
+    before: Vec<String>,
+    after: Vec<String>,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+}
+
+/// undo 履歴ファイルの中身。`hash` は書き出したときに保存されていたファイル内容のハッシュ
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    hash: u64,
+    undo: VecDeque<UndoEntry>,
+    redo: Vec<UndoEntry>,
+}
+
+impl UndoEntry {
+    fn new(change: LineChange, cursor_before: (usize, usize), cursor_after: (usize, usize)) -> Self {
+        let LineChange { start, before, after } = change;
+        Self { start, before, after, cursor_before, cursor_after }
+    }
 }
 
 pub struct Window {
-    buffer: Vec<String>,
+    buffer: LineBuffer,
     cursor_x: usize,
     cursor_y: usize,
     scroll_y: usize,
@@ -29,20 +219,68 @@ pub struct Window {
     filename: Option<String>,
     visual_start: Option<(usize, usize)>,
     pub yanked_text: String,
-    undo_stack: Vec<WindowState>,
-    redo_stack: Vec<WindowState>,
-    insert_mode_start_state: Option<WindowState>,
+    pub yank_kind: YankKind,
+    undo_stack: VecDeque<UndoEntry>,
+    redo_stack: Vec<UndoEntry>,
+    /// save_state で変更の記録を始めたときのカーソル位置（まだ undo 履歴にしていない変更がある）
+    pending_undo: Option<(usize, usize)>,
+    /// 挿入モードを始めたときのカーソル位置（挿入モード中の変更は1回の undo にまとめる）
+    insert_mode_start: Option<(usize, usize)>,
+    /// 保存する undo 履歴の最大数
+    undo_levels: usize,
     needs_syntax_update: bool,
     last_modified_line: Option<usize>,
     matching_bracket: Option<(usize, usize)>,
+    swap_dirty: bool,
+    /// 最後に保存（または読み込み）してからバッファを変更したか
+    modified: bool,
+    /// 最後に保存（または読み込み）したときの内容のハッシュ。undo / redo で保存時の内容に戻ったかを判定する
+    saved_hash: u64,
+    /// バッファを変更するたびに増える番号（表示用の計算結果のキャッシュに使う）
+    version: u64,
+    /// 単語数と、それを数えたときの version
+    word_count_cache: Cell<Option<(u64, usize)>>,
+    /// 綴りの誤りの位置と、それを調べたときの version
+    misspellings_cache: RefCell<Option<(u64, Vec<spell::Misspelling>)>>,
+    recovery_pending: bool,
+    /// 主カーソル以外の追加カーソル (x, y)
+    extra_cursors: Vec<(usize, usize)>,
+    /// 読み込んだファイルから推定したインデント
+    indent_style: Option<IndentStyle>,
+    /// 読み込んだファイルの改行コード
+    line_ending: LineEnding,
+    /// 読み込んだファイルが改行で終わっていたか（保存時に末尾の改行を足したり消したりしない）
+    trailing_newline: bool,
+    /// 最後に読み込み・保存したときのファイルの更新日時とサイズ（ほかのプログラムによる変更の検出に使う）
+    disk_stamp: Option<(SystemTime, u64)>,
+    block_insert: Option<BlockInsert>,
+    /// タブ文字の表示幅（描画前に設定から反映する）
+    tab_size: usize,
+    /// m{a-z} で記録した位置 (x, y)
+    marks: HashMap<char, (usize, usize)>,
+    /// 長い行を折り返して表示するか（描画前に設定から反映する）
+    word_wrap: bool,
+    /// 折り返し表示で、先頭の行 (scroll_y) のうち画面より上に隠れている表示行の数
+    scroll_row: usize,
+    /// 直近の描画でのテキスト領域の大きさ (高さ, 幅)
+    view_size: (usize, usize),
+    /// カーソル位置を記録するキャッシュファイル（None なら記録も復元もしない）
+    position_cache: Option<PathBuf>,
 }
 
 impl Window {
     pub fn buffer(&self) -> &Vec<String> {
         &self.buffer
     }
+    /// `y` 行目だけを書き換える（undo にはその行だけを記録する）
+    pub fn line_mut(&mut self, y: usize) -> &mut String {
+        self.version += 1;
+        &mut self.buffer[y]
+    }
     pub fn buffer_mut(&mut self) -> &mut Vec<String> {
-        &mut self.buffer
+        // 呼び出し側で書き換えられるかもしれないので、キャッシュした計算結果は使わない
+        self.version += 1;
+        self.buffer.lines_mut()
     }
     pub fn cursor_x(&self) -> usize {
         self.cursor_x
@@ -71,21 +309,116 @@ impl Window {
     pub fn visual_start_mut(&mut self) -> &mut Option<(usize, usize)> {
         &mut self.visual_start
     }
+    pub fn indent_style(&self) -> Option<IndentStyle> {
+        self.indent_style
+    }
+
+    pub fn tab_size(&self) -> usize {
+        self.tab_size
+    }
+
+    pub fn set_tab_size(&mut self, tab_size: usize) {
+        self.tab_size = tab_size.max(1);
+    }
+
+    pub fn word_wrap(&self) -> bool {
+        self.word_wrap
+    }
+
+    pub fn set_word_wrap(&mut self, word_wrap: bool) {
+        self.word_wrap = word_wrap;
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// undo 履歴ファイルにも書き出すので、Rust のバージョンで変わらないハッシュを使う
+    fn content_hash(&self) -> u64 {
+        utils::stable_hash(self.buffer.iter().map(String::as_str).chain([self.line_ending.as_str()]))
+    }
+
+    /// 保存した状態として記録する
+    fn mark_saved(&mut self) {
+        self.modified = false;
+        self.saved_hash = self.content_hash();
+    }
+
+    /// 空白で区切った単語の数（バッファが変わるまでは前回の結果を使う）
+    pub fn word_count(&self) -> usize {
+        if let Some((_, count)) = self.word_count_cache.get().filter(|&(version, _)| version == self.version) {
+            return count;
+        }
+        let count = self.buffer.iter().map(|line| line.split_whitespace().count()).sum();
+        self.word_count_cache.set(Some((self.version, count)));
+        count
+    }
+
+    /// 辞書にない単語の位置（バッファが変わるまでは前回の結果を使う）
+    pub fn misspellings(&self, dictionary: &Dictionary) -> Vec<spell::Misspelling> {
+        if let Some((_, found)) = self.misspellings_cache.borrow().as_ref().filter(|(version, _)| *version == self.version) {
+            return found.clone();
+        }
+        let found = spell::find_misspellings(&self.buffer, self.filename(), dictionary);
+        *self.misspellings_cache.borrow_mut() = Some((self.version, found.clone()));
+        found
+    }
+
+    /// 辞書を読み直したときに、前の辞書で調べた綴りの誤りを捨てる
+    pub fn clear_misspellings_cache(&self) {
+        self.misspellings_cache.take();
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// 保存時の改行コードを切り替え、変更があればバッファを変更済みにする
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) -> bool {
+        if self.line_ending == line_ending {
+            return false;
+        }
+        self.line_ending = line_ending;
+        self.swap_dirty = true;
+        self.modified = true;
+        self.version += 1;
+        true
+    }
     pub fn matching_bracket(&self) -> Option<(usize, usize)> {
         self.matching_bracket
     }
 
+    /// 現在の表示位置
+    pub fn view(&self) -> ViewState {
+        ViewState {
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            scroll_x: self.scroll_x,
+            scroll_y: self.scroll_y,
+        }
+    }
+
+    /// 別のペインの表示位置に切り替える。他のペインでの編集で行が減っていてもバッファ内に収める
+    pub fn set_view(&mut self, view: ViewState) {
+        let last_line = self.buffer.len().saturating_sub(1);
+        self.cursor_y = view.cursor_y.min(last_line);
+        self.cursor_x = view.cursor_x.min(self.line_graphemes(self.cursor_y).len());
+        self.scroll_x = view.scroll_x;
+        self.scroll_y = view.scroll_y.min(last_line);
+        self.scroll_row = 0;
+    }
+
     pub fn new(filename: Option<String>) -> Self {
-        let buffer = if let Some(path) = &filename {
-            fs::read_to_string(path)
-                .map(|content| content.lines().map(String::from).collect())
-                .unwrap_or_else(|_| vec![String::new()])
-        } else {
-            vec![String::new()]
-        };
+        let content = filename.as_ref().and_then(|path| fs::read_to_string(path).ok());
+        let line_ending = content.as_deref().map_or(LineEnding::default(), LineEnding::detect);
+        let trailing_newline = content.as_deref().is_none_or(|content| content.ends_with('\n'));
+        let buffer = content
+            .filter(|content| !content.is_empty())
+            .map(|content| content.lines().map(String::from).collect())
+            .unwrap_or_else(|| vec![String::new()]);
         
-        Self {
-            buffer,
+        let mut window = Self {
+            buffer: LineBuffer::from(buffer),
             cursor_x: 0,
             cursor_y: 0,
             scroll_y: 0,
@@ -93,20 +426,96 @@ impl Window {
             filename,
             visual_start: None,
             yanked_text: String::new(),
-            undo_stack: Vec::new(),
+            yank_kind: YankKind::CharWise,
+            undo_stack: VecDeque::new(),
             redo_stack: Vec::new(),
-            insert_mode_start_state: None,
+            pending_undo: None,
+            insert_mode_start: None,
+            undo_levels: 100,
             needs_syntax_update: true,
             last_modified_line: None,
             matching_bracket: None,
+            swap_dirty: false,
+            modified: false,
+            saved_hash: 0,
+            version: 0,
+            word_count_cache: Cell::new(None),
+            misspellings_cache: RefCell::new(None),
+            recovery_pending: false,
+            extra_cursors: Vec::new(),
+            indent_style: None,
+            line_ending,
+            trailing_newline,
+            disk_stamp: None,
+            block_insert: None,
+            tab_size: 4,
+            word_wrap: false,
+            scroll_row: 0,
+            view_size: (0, 0),
+            marks: HashMap::new(),
+            position_cache: None,
+        };
+        window.indent_style = IndentStyle::detect(&window.buffer);
+        window.saved_hash = window.content_hash();
+        window.disk_stamp = window.filename.as_deref().and_then(Self::read_disk_stamp);
+        window
+    }
+
+    /// ファイルを開き、`position_cache` に前回保存・再読み込みした時のカーソル位置があればそこに戻す
+    pub fn open(filename: Option<String>, position_cache: Option<PathBuf>) -> Self {
+        let mut window = Self::new(filename);
+        window.position_cache = position_cache;
+        window.restore_position();
+        window
+    }
+
+    /// 記録しておいたカーソル位置に戻す（今の内容に合わない位置は丸める）
+    fn restore_position(&mut self) {
+        let position = self
+            .filename
+            .as_deref()
+            .zip(self.position_cache.as_deref())
+            .and_then(|(filename, cache)| positions::lookup(cache, filename));
+        if let Some((cursor_x, cursor_y, scroll_y)) = position {
+            self.cursor_x = cursor_x;
+            self.cursor_y = cursor_y;
+            self.clamp_restored_cursor();
+            if !self.buffer[self.cursor_y].is_char_boundary(self.cursor_x) {
+                self.cursor_x = 0;
+            }
+            self.scroll_y = scroll_y.min(self.cursor_y);
+        }
+    }
+
+    /// 現在のカーソル位置をキャッシュへ書き出す（書けなくても編集には影響しないので無視する）
+    fn store_position(&self) {
+        if let (Some(filename), Some(cache)) = (self.filename.as_deref(), self.position_cache.as_deref()) {
+            let _ = positions::store(cache, filename, (self.cursor_x, self.cursor_y, self.scroll_y));
+        }
+    }
+
+    /// 保存する。開いた後にほかのプログラムがファイルを変更していれば上書きしない
+    pub fn save_file(&mut self, config: &EditorConfig) -> io::Result<()> {
+        if self.changed_on_disk() {
+            return Err(io::Error::other("File changed on disk (use :w! to overwrite or :e! to reload)"));
         }
+        self.overwrite_file(config)
     }
 
-    pub fn save_file(&mut self) -> io::Result<()> {
+    /// :w!: ディスク上のファイルが変更されていても保存する
+    pub fn overwrite_file(&mut self, config: &EditorConfig) -> io::Result<()> {
         if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
-            for line in &self.buffer {
-                writeln!(file, "{}", line)?;
+            self.write_buffer_to(Path::new(filename), config)?;
+            self.disk_stamp = Self::read_disk_stamp(filename);
+            self.mark_saved();
+            self.store_position();
+            if config.undo_file {
+                if let Some(path) = self.undo_file_path() {
+                    let _ = self.write_undo_file(&path);
+                }
+            }
+            if !self.recovery_pending {
+                self.remove_swap_file();
             }
             Ok(())
         } else {
@@ -114,15 +523,184 @@ impl Window {
         }
     }
 
+    fn read_disk_stamp(path: &str) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// 最後に読み込み・保存した後に、ディスク上のファイルが作られたり変更されたりしたか（削除された場合は含めない）
+    pub fn changed_on_disk(&self) -> bool {
+        match self.filename.as_deref().and_then(Self::read_disk_stamp) {
+            Some(stamp) => self.disk_stamp != Some(stamp),
+            None => false,
+        }
+    }
+
+    /// バッファの内容を `path` に書き出す（設定されていれば既存ファイルのバックアップを先に作る）。
+    /// 途中で失敗しても元のファイルが壊れないよう、同じディレクトリの一時ファイルに書いてから置き換える
+    fn write_buffer_to(&self, path: &Path, config: &EditorConfig) -> io::Result<()> {
+        if config.backup {
+            Self::write_backup(path, &config.backup_suffix, config.backup_dir.as_deref())?;
+        }
+        let ending = self.line_ending.as_str();
+        let mut content = self.buffer.join(ending);
+        if self.trailing_newline {
+            content.push_str(ending);
+        }
+
+        // シンボリックリンクはリンク先のファイルを置き換える
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let name = target.file_name().ok_or_else(|| io::Error::other("Invalid file name"))?.to_string_lossy();
+        let temp = target.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        let result = (|| {
+            let mut file = fs::File::create(&temp)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            if let Ok(metadata) = fs::metadata(&target) {
+                fs::set_permissions(&temp, metadata.permissions())?;
+            }
+            fs::rename(&temp, &target)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// 書き出し先のディレクトリが無ければ分かりやすいエラーにする
+    fn check_parent_dir(path: &str) -> io::Result<()> {
+        match Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+            Some(parent) if !parent.is_dir() => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Directory does not exist: {}", parent.display()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// :w path: ファイル名が無ければ `path` として保存し、あれば今のファイル名のまま内容だけを `path` に書き出す
+    pub fn write_file(&mut self, path: &str, config: &EditorConfig) -> io::Result<()> {
+        if self.filename.is_none() {
+            return self.save_file_as(path, config);
+        }
+        Self::check_parent_dir(path)?;
+        self.write_buffer_to(Path::new(path), config)
+    }
+
+    /// :saveas path: 別名で保存する。成功したらこのウィンドウのファイル名を新しいパスに切り替える
+    pub fn save_file_as(&mut self, path: &str, config: &EditorConfig) -> io::Result<()> {
+        Self::check_parent_dir(path)?;
+        let previous_swap = self.swap_path();
+        let previous = self.filename.replace(path.to_string());
+        if let Err(e) = self.overwrite_file(config) {
+            self.filename = previous;
+            return Err(e);
+        }
+        // 拡張子が変わると綴りを確認する範囲も変わる
+        self.clear_misspellings_cache();
+        // 元のファイル名のスワップファイルは不要になる
+        if let Some(previous_swap) = previous_swap.filter(|p| Some(p) != self.swap_path().as_ref()) {
+            let _ = fs::remove_file(previous_swap);
+        }
+        Ok(())
+    }
+
+    /// 既存ファイルを `<name><suffix>` としてコピーする（ファイルが無ければ何もしない）
+    fn write_backup(path: &Path, suffix: &str, backup_dir: Option<&str>) -> io::Result<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::other("Invalid file name"))?
+            .to_string_lossy();
+        let backup_name = format!("{}{}", file_name, suffix);
+        let backup_path = match backup_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)?;
+                PathBuf::from(dir).join(backup_name)
+            }
+            None => path.with_file_name(backup_name),
+        };
+        fs::copy(path, backup_path)?;
+        Ok(())
+    }
+
+    /// スワップファイルのパス（`.<name>.swp`）
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        let path = Path::new(self.filename.as_ref()?);
+        let name = path.file_name()?.to_string_lossy();
+        Some(path.with_file_name(format!(".{}.swp", name)))
+    }
+
+    /// 前回から変更があればスワップファイルへバッファを書き出す
+    pub fn write_swap_file(&mut self) -> io::Result<()> {
+        if !self.swap_dirty || self.recovery_pending {
+            return Ok(());
+        }
+        if let Some(path) = self.swap_path() {
+            fs::write(path, self.buffer.join("\n"))?;
+        }
+        self.swap_dirty = false;
+        Ok(())
+    }
+
+    pub fn remove_swap_file(&mut self) {
+        if let Some(path) = self.swap_path() {
+            let _ = fs::remove_file(path);
+        }
+        self.swap_dirty = false;
+    }
+
+    /// 前回のスワップファイルが残っていれば復元待ちにし、残っていたかを返す
+    pub fn detect_swap(&mut self) -> bool {
+        self.recovery_pending = self.swap_path().is_some_and(|path| path.is_file());
+        self.recovery_pending
+    }
+
+    /// 前回のスワップファイルが残っていて、復元するか決まっていないか
+    pub fn has_recoverable_swap(&self) -> bool {
+        self.recovery_pending
+    }
+
+    /// スワップファイルの内容でバッファを復元する
+    pub fn recover_from_swap(&mut self) -> io::Result<()> {
+        let path = self.swap_path().ok_or_else(|| io::Error::other("No file name"))?;
+        let content = fs::read_to_string(path)?;
+        self.save_state();
+        if content.is_empty() {
+            self.buffer.replace_all([String::new()]);
+        } else {
+            self.buffer.replace_all(content.lines().map(String::from));
+        }
+        self.cursor_y = self.cursor_y.min(self.buffer.len() - 1);
+        self.clamp_cursor_x();
+        self.recovery_pending = false;
+        self.mark_line_modified(0);
+        Ok(())
+    }
+
+    /// 復元せずに古いスワップファイルを破棄する
+    pub fn discard_swap(&mut self) {
+        self.recovery_pending = false;
+        self.remove_swap_file();
+    }
+
     pub fn reload_file(&mut self) -> io::Result<()> {
         if let Some(filename) = &self.filename {
             match fs::read_to_string(filename) {
                 Ok(content) => {
-                    self.buffer = if content.is_empty() {
-                        vec![String::new()]
+                    if content.is_empty() {
+                        self.buffer.replace_all([String::new()]);
                     } else {
-                        content.lines().map(String::from).collect()
-                    };
+                        self.buffer.replace_all(content.lines().map(String::from));
+                    }
+                    self.indent_style = IndentStyle::detect(&self.buffer);
+                    self.line_ending = LineEnding::detect(&content);
+                    self.trailing_newline = content.ends_with('\n');
+                    self.disk_stamp = Self::read_disk_stamp(filename);
+                    self.mark_saved();
+                    self.version += 1;
                     
                     if self.cursor_y >= self.buffer.len() {
                         self.cursor_y = self.buffer.len().saturating_sub(1);
@@ -136,6 +714,7 @@ impl Window {
                     if self.scroll_y >= self.buffer.len() {
                         self.scroll_y = self.buffer.len().saturating_sub(1);
                     }
+                    self.store_position();
                     
                     Ok(())
                 }
@@ -149,6 +728,9 @@ impl Window {
     pub fn mark_line_modified(&mut self, line_index: usize) {
         self.last_modified_line = Some(line_index);
         self.needs_syntax_update = true;
+        self.swap_dirty = true;
+        self.modified = true;
+        self.version += 1;
     }
 
     pub fn on_char_inserted(&mut self, line_index: usize, _char_index: usize, _ch: char) {
@@ -160,11 +742,25 @@ impl Window {
     }
 
     pub fn on_line_inserted(&mut self, line_index: usize) {
+        // 挿入した行より下のマークを1行ずらす
+        for (_, y) in self.marks.values_mut() {
+            if *y >= line_index {
+                *y += 1;
+            }
+        }
         self.mark_line_modified(line_index);
     }
 
-    pub fn on_line_deleted(&mut self, line_index: usize) {
-        self.mark_line_modified(line_index);
+    /// `line_index` から `count` 行を取り除いた後に呼ぶ。取り除いた行のマークは消し、それより下のマークは詰める
+    pub fn on_line_deleted(&mut self, line_index: usize, count: usize) {
+        let end = line_index + count;
+        self.marks.retain(|_, &mut (_, y)| !(line_index..end).contains(&y));
+        for (_, y) in self.marks.values_mut() {
+            if *y >= end {
+                *y -= count;
+            }
+        }
+        self.mark_line_modified(line_index.min(self.buffer.len().saturating_sub(1)));
     }
 
     pub fn mark_syntax_updated(&mut self) {
@@ -172,121 +768,215 @@ impl Window {
         self.last_modified_line = None;
     }
 
+    /// カーソル下の括弧に対応する括弧を探す（位置は書記素単位）。文字列・コメント中の括弧は数えない
     pub fn find_matching_bracket(&mut self) {
         self.matching_bracket = None;
-        if self.cursor_y >= self.buffer.len() || self.cursor_x >= self.buffer[self.cursor_y].len() {
+        let Some(&cursor_grapheme) = self.line_graphemes(self.cursor_y).get(self.cursor_x) else {
             return;
-        }
-
-        let ch = self.buffer[self.cursor_y].chars().nth(self.cursor_x).unwrap();
-        let (open_bracket, close_bracket) = match ch {
-            '(' => ('(', ')'),
-            ')' => ('(', ')'),
-            '[' => ('[', ']'),
-            ']' => ('[', ']'),
-            '{' => ('{', '}'),
-            '}' => ('{', '}'),
+        };
+        let (open_bracket, close_bracket) = match cursor_grapheme {
+            "(" | ")" => ("(", ")"),
+            "[" | "]" => ("[", "]"),
+            "{" | "}" => ("{", "}"),
             _ => return,
         };
 
-        let is_forward = ch == open_bracket;
-        let mut stack = Vec::new();
-        let current_y = self.cursor_y;
-
-        if is_forward {
-            for y in current_y..self.buffer.len() {
-                let line = &self.buffer[y];
-                let start_x = if y == current_y { self.cursor_x } else { 0 };
-                for (x, c) in line.chars().enumerate().skip(start_x) {
-                    if c == open_bracket {
-                        stack.push(c);
-                    } else if c == close_bracket {
-                        stack.pop();
-                        if stack.is_empty() {
-                            self.matching_bracket = Some((x, y));
-                            return;
-                        }
+        // カーソル下の括弧自体が文字列・コメント中なら対応を探さない。
+        // 前方へはカーソル位置から深さを数えて 0 になったところで、後方へはカーソルまでに閉じていない開き括弧を積んで探す
+        let is_forward = cursor_grapheme == open_bracket;
+        let (cursor_x, cursor_y) = (self.cursor_x, self.cursor_y);
+        let mut depth = None;
+        let mut openings = Vec::new();
+        let mut matching = None;
+        self.visit_code_brackets(|y, brackets| {
+            if y > cursor_y && depth.is_none() {
+                return false;
+            }
+            for (x, bracket) in brackets {
+                if (x, y) == (cursor_x, cursor_y) {
+                    if !is_forward {
+                        matching = openings.last().copied();
+                        return false;
                     }
+                    depth = Some(0);
                 }
-            }
-        } else {
-            for y in (0..=current_y).rev() {
-                let line = &self.buffer[y];
-                let end_x = if y == current_y { self.cursor_x + 1 } else { line.len() };
-                let line_chars: Vec<(usize, char)> = line.chars().enumerate().take(end_x).collect();
-                for (x, c) in line_chars.into_iter().rev() {
-                    if c == close_bracket {
-                        stack.push(c);
-                    } else if c == open_bracket {
-                        stack.pop();
-                        if stack.is_empty() {
-                            self.matching_bracket = Some((x, y));
-                            return;
+                match depth.as_mut() {
+                    Some(depth) if bracket == open_bracket => *depth += 1,
+                    Some(depth) if bracket == close_bracket => {
+                        *depth -= 1;
+                        if *depth == 0 {
+                            matching = Some((x, y));
+                            return false;
                         }
                     }
+                    Some(_) => {}
+                    None if bracket == open_bracket => openings.push((x, y)),
+                    None if bracket == close_bracket => {
+                        openings.pop();
+                    }
+                    None => {}
                 }
             }
+            y < cursor_y || depth.is_some()
+        });
+        self.matching_bracket = matching;
+    }
+
+    /// %: カーソル下の括弧（括弧の上でなければ、行内でカーソルより後ろにある最初の括弧）に対応する括弧へ移動する。
+    /// 対応する括弧が無ければ動かない
+    pub fn jump_to_matching_bracket(&mut self) -> bool {
+        let (cx, cy) = (self.cursor_x, self.cursor_y);
+        let mut first = None;
+        self.visit_code_brackets(|y, brackets| {
+            if y == cy {
+                first = brackets.into_iter().map(|(x, _)| x).find(|&x| x >= cx);
+            }
+            y < cy
+        });
+        let Some(x) = first else {
+            return false;
+        };
+        self.cursor_x = x;
+        self.find_matching_bracket();
+        let Some((match_x, match_y)) = self.matching_bracket() else {
+            self.cursor_x = cx;
+            return false;
+        };
+        self.cursor_x = match_x;
+        self.cursor_y = match_y;
+        self.find_matching_bracket();
+        true
+    }
+
+    /// 先頭の行から1行ずつトークン化し、行番号とその行の文字列・コメントの外にある括弧 (x, 括弧) を `visit` に渡す。
+    /// `visit` が false を返したらそれ以降の行はトークン化しない
+    fn visit_code_brackets(&self, mut visit: impl FnMut(usize, Vec<(usize, String)>) -> bool) {
+        let mut state = BracketState::with_language(Language::from_filename(self.filename()));
+        for (y, line) in self.buffer.iter().enumerate() {
+            let space_count = count_leading_spaces(line);
+            let brackets = tokenize_with_state(&line[space_count..], y, space_count, &mut state)
+                .into_iter()
+                .filter(|token| matches!(token.token_type, TokenType::Bracket { .. }))
+                .map(|token| (line[..space_count + token.start].graphemes(true).count(), token.content))
+                .collect();
+            if !visit(y, brackets) {
+                return;
+            }
         }
     }
 
+    /// カーソル下の括弧と対応する括弧の両方の位置を返す
+    pub fn matching_bracket_pair(&self) -> Option<[(usize, usize); 2]> {
+        self.matching_bracket.map(|partner| [(self.cursor_x, self.cursor_y), partner])
+    }
+
+    /// これからの変更を undo 履歴の1単位として記録し始める。変更のあった行だけを控え、次に履歴を操作するときに積む
+    /// （挿入モード中の変更は挿入モードを始めたときの記録にまとめる）
     pub fn save_state(&mut self) {
-        let state = WindowState {
-            buffer: self.buffer.clone(),
-            cursor_x: self.cursor_x,
-            cursor_y: self.cursor_y,
-        };
-        self.undo_stack.push(state);
-        
-        if self.undo_stack.len() > 100 {
-            self.undo_stack.remove(0);
+        if self.insert_mode_start.is_none() {
+            self.commit_pending_undo();
+            self.pending_undo = Some((self.cursor_x, self.cursor_y));
+            self.buffer.start_recording();
         }
-        
         self.redo_stack.clear();
+        self.swap_dirty = true;
+        self.modified = true;
+        self.version += 1;
+    }
+
+    /// 記録しておいた変更を undo 履歴に積む
+    fn commit_pending_undo(&mut self) {
+        if let Some(cursor_before) = self.pending_undo.take() {
+            if let Some(change) = self.buffer.take_recording() {
+                self.push_undo(UndoEntry::new(change, cursor_before, (self.cursor_x, self.cursor_y)));
+            }
+        }
+    }
+
+    fn push_undo(&mut self, entry: UndoEntry) {
+        self.undo_stack.push_back(entry);
+        while self.undo_stack.len() > self.undo_levels {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    pub fn set_undo_levels(&mut self, undo_levels: usize) {
+        self.undo_levels = undo_levels;
+        while self.undo_stack.len() > self.undo_levels {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// undo 履歴ファイルのパス（`~/.cache/vim-clone/undo/` に、ファイルのパスの `/` を `%` にした名前で置く）
+    pub fn undo_file_path(&self) -> Option<PathBuf> {
+        let key = positions::key(self.filename.as_deref()?);
+        Some(utils::cache_dir()?.join("undo").join(key.replace('/', "%")))
+    }
+
+    /// undo / redo 履歴を、保存されている内容のハッシュと一緒に書き出す
+    pub fn write_undo_file(&mut self, path: &Path) -> io::Result<()> {
+        self.commit_pending_undo();
+        let undo_file = UndoFile {
+            hash: self.saved_hash,
+            undo: self.undo_stack.clone(),
+            redo: self.redo_stack.clone(),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(&undo_file)?)
+    }
+
+    /// undo 履歴ファイルを読み込む。書き出した後にファイルが変わっていたり、履歴が壊れていたりすれば読み込まない
+    pub fn read_undo_file(&mut self, path: &Path) -> bool {
+        let undo_file = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<UndoFile>(&content).ok());
+        match undo_file {
+            Some(undo_file) if !self.modified && undo_file.hash == self.saved_hash => {
+                self.undo_stack = undo_file.undo;
+                self.redo_stack = undo_file.redo;
+                self.set_undo_levels(self.undo_levels);
+                true
+            }
+            _ => false,
+        }
     }
 
     pub fn start_insert_mode(&mut self) {
-        self.insert_mode_start_state = Some(WindowState {
-            buffer: self.buffer.clone(),
-            cursor_x: self.cursor_x,
-            cursor_y: self.cursor_y,
-        });
+        self.commit_pending_undo();
+        self.insert_mode_start = Some((self.cursor_x, self.cursor_y));
+        self.buffer.start_recording();
     }
 
     pub fn end_insert_mode(&mut self) {
-        if let Some(start_state) = self.insert_mode_start_state.take() {
-            self.undo_stack.push(start_state);
-            
-            if self.undo_stack.len() > 100 {
-                self.undo_stack.remove(0);
+        if let Some(block) = self.block_insert.take() {
+            self.apply_block_insert(block);
+        }
+        if let Some(cursor_before) = self.insert_mode_start.take() {
+            if let Some(change) = self.buffer.take_recording() {
+                self.push_undo(UndoEntry::new(change, cursor_before, (self.cursor_x, self.cursor_y)));
             }
-            
             self.redo_stack.clear();
         }
     }
 
     pub fn undo(&mut self) -> bool {
-        if let Some(state) = self.undo_stack.pop() {
-            let current_state = WindowState {
-                buffer: self.buffer.clone(),
-                cursor_x: self.cursor_x,
-                cursor_y: self.cursor_y,
-            };
-            self.redo_stack.push(current_state);
-            
-            self.buffer = state.buffer;
-            self.cursor_x = state.cursor_x;
-            self.cursor_y = state.cursor_y;
-            
-            if self.cursor_y >= self.buffer.len() {
-                self.cursor_y = self.buffer.len().saturating_sub(1);
-            }
-            if self.cursor_y < self.buffer.len() {
-                let line_len = self.buffer[self.cursor_y].len();
-                if self.cursor_x > line_len {
-                    self.cursor_x = line_len;
-                }
-            }
-            
+        self.commit_pending_undo();
+        if let Some(mut entry) = self.undo_stack.pop_back() {
+            self.extra_cursors.clear();
+            self.swap_dirty = true;
+            self.modified = true;
+            self.version += 1;
+
+            // redo したときは undo する直前のカーソル位置に戻す
+            entry.cursor_after = (self.cursor_x, self.cursor_y);
+            self.buffer.splice(entry.start..entry.start + entry.after.len(), entry.before.iter().cloned());
+            (self.cursor_x, self.cursor_y) = entry.cursor_before;
+            self.clamp_restored_cursor();
+            // 保存したときの内容まで戻れば未変更に戻す
+            self.modified = self.content_hash() != self.saved_hash;
+            self.redo_stack.push(entry);
             true
         } else {
             false
@@ -294,78 +984,2978 @@ impl Window {
     }
 
     pub fn redo(&mut self) -> bool {
-        if let Some(state) = self.redo_stack.pop() {
-            let current_state = WindowState {
-                buffer: self.buffer.clone(),
-                cursor_x: self.cursor_x,
-                cursor_y: self.cursor_y,
-            };
-            self.undo_stack.push(current_state);
-            
-            self.buffer = state.buffer;
-            self.cursor_x = state.cursor_x;
-            self.cursor_y = state.cursor_y;
-            
-            if self.cursor_y >= self.buffer.len() {
-                self.cursor_y = self.buffer.len().saturating_sub(1);
-            }
-            if self.cursor_y < self.buffer.len() {
-                let line_len = self.buffer[self.cursor_y].len();
-                if self.cursor_x > line_len {
-                    self.cursor_x = line_len;
-                }
-            }
-            
+        if let Some(mut entry) = self.redo_stack.pop() {
+            self.extra_cursors.clear();
+            self.swap_dirty = true;
+            self.modified = true;
+            self.version += 1;
+
+            entry.cursor_before = (self.cursor_x, self.cursor_y);
+            self.buffer.splice(entry.start..entry.start + entry.before.len(), entry.after.iter().cloned());
+            (self.cursor_x, self.cursor_y) = entry.cursor_after;
+            self.clamp_restored_cursor();
+            self.modified = self.content_hash() != self.saved_hash;
+            self.push_undo(entry);
             true
         } else {
             false
         }
     }
 
-    pub fn scroll_to_cursor(&mut self, height: usize, width: usize, show_line_numbers: bool) {
+    /// undo / redo で戻したカーソルをバッファの範囲内に収める
+    fn clamp_restored_cursor(&mut self) {
+        if self.cursor_y >= self.buffer.len() {
+            self.cursor_y = self.buffer.len().saturating_sub(1);
+        }
+        if self.cursor_y < self.buffer.len() {
+            let line_len = self.buffer[self.cursor_y].len();
+            if self.cursor_x > line_len {
+                self.cursor_x = line_len;
+            }
+        }
+    }
+
+    /// `gutter_width` は行番号の欄と区切りを合わせた幅
+    pub fn scroll_to_cursor(&mut self, height: usize, width: usize, gutter_width: usize) {
+        let available_width = width.saturating_sub(gutter_width);
+        self.view_size = (height, available_width);
+        if self.word_wrap {
+            self.scroll_to_cursor_wrapped(height.max(1));
+            return;
+        }
+        self.scroll_row = 0;
+
         if self.cursor_y < self.scroll_y {
             self.scroll_y = self.cursor_y;
         } else if self.cursor_y >= self.scroll_y + height {
             self.scroll_y = self.cursor_y - height + 1;
         }
 
-        let line_number_width = if show_line_numbers { 4 } else { 0 };
-        let separator_width = if show_line_numbers { 1 } else { 0 };
-        let available_width = width.saturating_sub(line_number_width + separator_width);
-
-        if self.cursor_x < self.scroll_x {
-            self.scroll_x = self.cursor_x;
-        } else if self.cursor_x >= self.scroll_x + available_width {
-            self.scroll_x = self.cursor_x - available_width + 1;
+        // scroll_x は表示幅（カラム）単位なので、全角文字も考慮して比較する
+        let cursor_col = self.cursor_display_x();
+        let cursor_cell_width = self
+            .line_graphemes(self.cursor_y)
+            .get(self.cursor_x)
+            .map_or(1, |g| grapheme_width(g, cursor_col, self.tab_size).max(1));
+        if cursor_col < self.scroll_x {
+            self.scroll_x = cursor_col;
+        } else if cursor_col + cursor_cell_width > self.scroll_x + available_width {
+            self.scroll_x = (cursor_col + cursor_cell_width).saturating_sub(available_width);
         }
     }
 
-    pub fn open_new_line(&mut self) {
-        self.save_state();
-        let new_line_y = self.cursor_y + 1;
-        self.buffer.insert(new_line_y, String::new());
-        self.cursor_y = new_line_y;
-        self.cursor_x = 0;
+    /// zt / zz / zb: 最後に描画した画面の高さで、カーソルのある表示行が上端・中央・下端に来るようスクロールする
+    pub fn scroll_cursor_line(&mut self, align: ScrollAlign) {
+        let height = self.view_size.0.max(1);
+        let mut rows_above = match align {
+            ScrollAlign::Top => 0,
+            ScrollAlign::Center => (height - 1) / 2,
+            ScrollAlign::Bottom => height - 1,
+        };
+        // 折り返していなければ各行は1表示行なので、行単位で数えるのと同じになる
+        let (mut y, mut row) = (self.cursor_y, self.wrap_position(self.cursor_x, self.cursor_y).0);
+        while rows_above > 0 {
+            if row > 0 {
+                row -= 1;
+            } else if y > 0 {
+                y -= 1;
+                row = self.wrap_starts(y).len() - 1;
+            } else {
+                break;
+            }
+            rows_above -= 1;
+        }
+        self.scroll_y = y;
+        self.scroll_row = row;
     }
-    pub fn move_to_screen_top(&mut self) {
-        self.cursor_y = self.scroll_y;
-        if self.cursor_y < self.buffer.len() {
-            let line_len = self.buffer[self.cursor_y].len();
-            if self.cursor_x > line_len {
-                self.cursor_x = line_len;
+
+    /// 折り返し表示で、カーソルのある表示行が画面内に入るよう表示行単位でスクロールする
+    fn scroll_to_cursor_wrapped(&mut self, height: usize) {
+        self.scroll_x = 0;
+        let (cursor_row, _) = self.wrap_position(self.cursor_x, self.cursor_y);
+        if self.cursor_y < self.scroll_y || (self.cursor_y == self.scroll_y && cursor_row < self.scroll_row) {
+            self.scroll_y = self.cursor_y;
+            self.scroll_row = cursor_row;
+            return;
+        }
+        // どの行も1表示行以上を占めるので、height 行より上から数え始める必要はない
+        if self.cursor_y >= self.scroll_y + height {
+            self.scroll_y = self.cursor_y + 1 - height;
+            self.scroll_row = 0;
+        }
+        self.scroll_row = self.scroll_row.min(self.wrap_starts(self.scroll_y).len() - 1);
+        let rows_before: usize = (self.scroll_y..self.cursor_y).map(|y| self.wrap_starts(y).len()).sum();
+        let mut rows_above = (rows_before + cursor_row).saturating_sub(self.scroll_row);
+        while rows_above >= height {
+            self.scroll_row += 1;
+            if self.scroll_row >= self.wrap_starts(self.scroll_y).len() {
+                self.scroll_y += 1;
+                self.scroll_row = 0;
             }
+            rows_above -= 1;
         }
     }
 
-    pub fn move_to_screen_bottom(&mut self, visible_height: usize) {
-        let last_visible_line = (self.scroll_y + visible_height.saturating_sub(1))
-            .min(self.buffer.len().saturating_sub(1));
-        self.cursor_y = last_visible_line;
-        if self.cursor_y < self.buffer.len() {
-            let line_len = self.buffer[self.cursor_y].len();
-            if self.cursor_x > line_len {
-                self.cursor_x = line_len;
+    /// 折り返し表示で各表示行が始まる書記素の位置（先頭は常に 0）。
+    /// 空白の直後で折り返し、1行に収まる空白がなければ幅いっぱいで折り返す
+    pub fn wrap_starts(&self, y: usize) -> Vec<usize> {
+        let width = self.view_size.1;
+        let mut starts = vec![0];
+        if !self.word_wrap || width == 0 {
+            return starts;
+        }
+        let mut columns = Vec::new();
+        let mut column = 0;
+        let mut row_start_column = 0;
+        let mut break_at = None;
+        for (i, g) in self.line_graphemes(y).into_iter().enumerate() {
+            let w = grapheme_width(g, column, self.tab_size);
+            columns.push(column);
+            if column + w > row_start_column + width && i > starts[starts.len() - 1] {
+                let start = break_at.take().unwrap_or(i);
+                starts.push(start);
+                row_start_column = columns[start];
             }
+            if g.chars().all(char::is_whitespace) {
+                break_at = Some(i + 1);
+            }
+            column += w;
+        }
+        starts
+    }
+
+    /// 書記素位置 (x, y) が行内の何番目の表示行の何カラム目に描かれるか（画面の幅を越える分は右端に収める）。
+    /// カーソルの描画と gj / gk で共通に使う
+    pub fn wrap_position(&self, x: usize, y: usize) -> (usize, usize) {
+        let starts = self.wrap_starts(y);
+        let row = starts.partition_point(|&start| start <= x) - 1;
+        let column = self.display_x(x, y) - self.display_x(starts[row], y);
+        let width = self.view_size.1;
+        if self.word_wrap && width > 0 {
+            (row, column.min(width - 1))
+        } else {
+            (row, column)
         }
     }
+
+    /// `wrap_position` の逆変換: y 行の `row` 番目の表示行で、表示カラム `column` に描かれている書記素の位置
+    pub fn position_in_wrap_row(&self, y: usize, row: usize, column: usize) -> usize {
+        let starts = self.wrap_starts(y);
+        let row = row.min(starts.len() - 1);
+        let x = self.grapheme_index_at_column(y, self.display_x(starts[row], y) + column);
+        // 表示行の外へ出ないよう、次の表示行の手前（最後の表示行なら行末の文字）に収める
+        let row_end = starts.get(row + 1).copied().unwrap_or(self.line_graphemes(y).len());
+        x.min(row_end.saturating_sub(1)).max(starts[row])
+    }
+
+    /// gj / gk: 表示行単位で `count` 行移動する（表示カラムは保つ）
+    pub fn move_display_rows(&mut self, down: bool, count: usize) {
+        let (mut row, column) = self.wrap_position(self.cursor_x, self.cursor_y);
+        let mut y = self.cursor_y;
+        for _ in 0..count {
+            if down {
+                if row + 1 < self.wrap_starts(y).len() {
+                    row += 1;
+                } else if y + 1 < self.buffer.len() {
+                    y += 1;
+                    row = 0;
+                } else {
+                    break;
+                }
+            } else if row > 0 {
+                row -= 1;
+            } else if y > 0 {
+                y -= 1;
+                row = self.wrap_starts(y).len() - 1;
+            } else {
+                break;
+            }
+        }
+        self.cursor_y = y;
+        self.cursor_x = self.position_in_wrap_row(y, row, column);
+    }
+
+    /// 画面に表示する行の一覧 (行, 開始書記素, 終了書記素)。折り返し表示では1行が複数になる
+    pub fn view_rows(&self) -> Vec<(usize, usize, usize)> {
+        let height = self.view_size.0;
+        let mut rows = Vec::with_capacity(height);
+        for y in self.scroll_y..self.buffer.len() {
+            let len = self.line_graphemes(y).len();
+            let starts = self.wrap_starts(y);
+            let skip = if y == self.scroll_y { self.scroll_row } else { 0 };
+            for (i, &start) in starts.iter().enumerate().skip(skip) {
+                if rows.len() >= height {
+                    return rows;
+                }
+                rows.push((y, start, starts.get(i + 1).copied().unwrap_or(len)));
+            }
+        }
+        rows
+    }
+
+    /// 書記素位置 (x, y) を描く画面上の位置（テキスト領域内の行, カラム）。画面外なら None
+    pub fn view_position(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (height, width) = self.view_size;
+        if y < self.scroll_y || y >= self.scroll_y + height {
+            return None;
+        }
+        let (row, column) = if self.word_wrap {
+            let (row_in_line, column) = self.wrap_position(x, y);
+            let rows_before: usize = (self.scroll_y..y).map(|line| self.wrap_starts(line).len()).sum();
+            ((rows_before + row_in_line).checked_sub(self.scroll_row)?, column)
+        } else {
+            (y - self.scroll_y, self.display_x(x, y).checked_sub(self.scroll_x)?)
+        };
+        (row < height && column < width).then_some((row, column))
+    }
+
+    /// カーソル位置の表示カラム（行頭からの表示幅）を返す
+    pub fn cursor_display_x(&self) -> usize {
+        self.display_x(self.cursor_x, self.cursor_y)
+    }
+
+    /// 指定位置（書記素単位）の表示カラムを返す
+    pub fn display_x(&self, x: usize, y: usize) -> usize {
+        let graphemes = self.line_graphemes(y);
+        let line_width = graphemes.iter().take(x).fold(0, |col, g| col + grapheme_width(g, col, self.tab_size));
+        // virtualedit で行末より先にいる場合は仮想空間の幅を加算
+        line_width + x.saturating_sub(graphemes.len())
+    }
+
+    /// カーソルを現在行の範囲内に収める（ノーマルモード用）
+    fn clamp_cursor_x(&mut self) {
+        let line_len = self.buffer.get(self.cursor_y).map_or(0, |line| line.graphemes(true).count());
+        self.cursor_x = self.cursor_x.min(line_len.saturating_sub(1));
+    }
+
+    /// カーソル位置にテキストをそのまま挿入し、カーソルを挿入したテキストの末尾に置く（自動インデントはしない）
+    pub fn insert_text(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        if self.buffer.is_empty() {
+            self.buffer.push(String::new());
+        }
+        self.pad_line_to_cursor();
+        let y = self.cursor_y;
+        let line = &self.buffer[y];
+        let byte_index = line.grapheme_indices(true).nth(self.cursor_x).map_or(line.len(), |(i, _)| i);
+        let tail = self.buffer[y].split_off(byte_index);
+
+        let mut lines = text.split('\n');
+        self.buffer[y].push_str(lines.next().unwrap_or(""));
+        self.mark_line_modified(y);
+        let mut last_y = y;
+        for line in lines {
+            last_y += 1;
+            self.buffer.insert(last_y, line.to_string());
+            self.on_line_inserted(last_y);
+        }
+        self.cursor_y = last_y;
+        self.cursor_x = self.buffer[last_y].graphemes(true).count();
+        self.buffer[last_y].push_str(&tail);
+    }
+
+    /// 表示中の行 (`scroll_y` から `height` 行) にある `target` の位置 (x, y) を、カーソルに近い順に返す（カーソル位置は除く）
+    pub fn jump_targets(&self, target: char, height: usize) -> Vec<(usize, usize)> {
+        let mut buf = [0; 4];
+        let target: &str = target.encode_utf8(&mut buf);
+        // 折り返し表示では画面に収まった表示行の範囲だけを対象にする
+        let rows = if self.word_wrap {
+            self.view_rows()
+        } else {
+            (self.scroll_y..(self.scroll_y + height).min(self.buffer.len())).map(|y| (y, 0, usize::MAX)).collect()
+        };
+        let mut targets: Vec<(usize, usize)> = rows
+            .into_iter()
+            .flat_map(|(y, start, end)| {
+                self.buffer[y]
+                    .graphemes(true)
+                    .enumerate()
+                    .filter(move |&(x, g)| (start..end).contains(&x) && g == target)
+                    .map(move |(x, _)| (x, y))
+            })
+            .filter(|&pos| pos != (self.cursor_x, self.cursor_y))
+            .collect();
+        targets.sort_by_key(|&(x, y)| (y.abs_diff(self.cursor_y), x.abs_diff(self.cursor_x)));
+        targets
+    }
+
+    /// x: カーソル位置から最大 `count` 個の書記素を削除して、削除したテキストを返す
+    pub fn delete_chars(&mut self, count: usize) -> String {
+        let y = self.cursor_y;
+        let graphemes = self.line_graphemes(y);
+        if self.cursor_x >= graphemes.len() {
+            return String::new();
+        }
+        let end = (self.cursor_x + count).min(graphemes.len());
+        let deleted = graphemes[self.cursor_x..end].concat();
+        let remaining = graphemes.len() - (end - self.cursor_x);
+        let line = format!("{}{}", graphemes[..self.cursor_x].concat(), graphemes[end..].concat());
+        self.save_state();
+        self.buffer[y] = line;
+        self.cursor_x = self.cursor_x.min(remaining.saturating_sub(1));
+        self.on_char_deleted(y, self.cursor_x, deleted.chars().next().unwrap_or(' '));
+        deleted
+    }
+
+    /// r: カーソル下の書記素を置き換える。置き換える文字がなければ何もせず false を返す
+    /// 改行で置き換えた場合は vim と同様に行を分割し、カーソルを次の行の先頭に置く
+    pub fn replace_char(&mut self, replacement: char) -> bool {
+        let y = self.cursor_y;
+        let graphemes = self.line_graphemes(y);
+        if self.cursor_x >= graphemes.len() {
+            return false;
+        }
+        let before = graphemes[..self.cursor_x].concat();
+        let after = graphemes[self.cursor_x + 1..].concat();
+        self.save_state();
+        if replacement == '\n' {
+            self.buffer[y] = before;
+            self.buffer.insert(y + 1, after);
+            self.mark_line_modified(y);
+            self.on_line_inserted(y + 1);
+            self.cursor_y = y + 1;
+            self.cursor_x = 0;
+        } else {
+            self.buffer[y] = format!("{}{}{}", before, replacement, after);
+            self.mark_line_modified(y);
+        }
+        true
+    }
+
+    /// カーソルが行末より先にある場合、カーソル位置まで空白で埋める
+    pub fn pad_line_to_cursor(&mut self) {
+        if let Some(line) = self.buffer.get_mut(self.cursor_y) {
+            let line_len = line.graphemes(true).count();
+            if self.cursor_x > line_len {
+                line.push_str(&" ".repeat(self.cursor_x - line_len));
+            }
+        }
+    }
+
+    /// テキストを貼り付ける。`before` なら `P`（カーソルの前／現在行の上）として扱う
+    pub fn paste(&mut self, text: &str, kind: YankKind, before: bool) {
+        if text.is_empty() {
+            return;
+        }
+        self.save_state(); // 変更前の状態を保存
+        let cy = self.cursor_y;
+        match kind {
+            YankKind::LineWise => {
+                // 行単位のテキストはカーソル行の下（P なら上）に挿入する
+                let insert_at = if before { cy } else { cy + 1 };
+                for (i, line) in text.lines().enumerate() {
+                    self.buffer.insert(insert_at + i, line.to_string());
+                    self.on_line_inserted(insert_at + i);
+                }
+                self.cursor_y = insert_at;
+                self.cursor_x = self.first_non_blank_x(insert_at);
+            }
+            YankKind::Block => {
+                // 各行の切り出しを、後続の行の同じ表示カラムに挿入する
+                let col = if before || self.buffer[cy].is_empty() {
+                    self.display_x(self.cursor_x, cy)
+                } else {
+                    let col = self.display_x(self.cursor_x, cy);
+                    col + self.line_graphemes(cy).get(self.cursor_x).map_or(1, |g| grapheme_width(g, col, self.tab_size))
+                };
+                let segments: Vec<&str> = text.split('\n').collect();
+                let block_width = segments.iter().map(|s| s.width()).max().unwrap_or(0);
+                for (i, segment) in segments.iter().enumerate() {
+                    let y = cy + i;
+                    if y >= self.buffer.len() {
+                        self.buffer.push(String::new());
+                        self.on_line_inserted(y);
+                    }
+                    let line = &mut self.buffer[y];
+                    let (byte, line_col) = byte_index_at_column(line, col, self.tab_size);
+                    let mut insertion = " ".repeat(col.saturating_sub(line_col));
+                    insertion.push_str(segment);
+                    // 後ろに文字が続く場合は矩形の幅まで空白で埋める
+                    if byte < line.len() {
+                        insertion.push_str(&" ".repeat(block_width - segment.width()));
+                    }
+                    line.insert_str(byte, &insertion);
+                    self.mark_line_modified(y);
+                }
+                self.cursor_x = self.grapheme_index_at_column(cy, col);
+            }
+            YankKind::CharWise => {
+                let mut cx = self.cursor_x;
+                if !before && !self.buffer[cy].is_empty() {
+                    cx += 1;
+                }
+                let line = &mut self.buffer[cy];
+                let byte_index = line.grapheme_indices(true).nth(cx).map(|(i, _)| i).unwrap_or(line.len());
+                if text.contains('\n') {
+                    let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+                    let rest_of_current_line = line.split_off(byte_index);
+                    line.push_str(&lines[0]);
+                    let last_line_index = lines.len() - 1;
+                    lines[last_line_index].push_str(&rest_of_current_line);
+                    for (i, line) in lines.into_iter().skip(1).enumerate() {
+                        self.buffer.insert(cy + 1 + i, line);
+                        self.on_line_inserted(cy + 1 + i);
+                    }
+                    self.cursor_x = cx;
+                } else {
+                    line.insert_str(byte_index, text);
+                    // カーソルは貼り付けたテキストの最後の文字に置く
+                    self.cursor_x = cx + text.graphemes(true).count().saturating_sub(1);
+                }
+                self.mark_line_modified(cy);
+            }
+        }
+    }
+
+    /// 行単位の選択範囲 (先頭行, 末尾行) を返す（カラムは無視する）
+    pub fn line_selection(&self) -> Option<(usize, usize)> {
+        let (_, start_y) = self.visual_start?;
+        Some((start_y.min(self.cursor_y), start_y.max(self.cursor_y)))
+    }
+
+    /// V での d: 選択行を削除して選択を終了し、削除した内容を行単位のテキストとして返す
+    pub fn delete_line_selection(&mut self) -> Option<String> {
+        let (first, last) = self.line_selection()?;
+        self.save_state();
+        self.visual_start = None;
+        Some(self.delete_lines(first, last - first + 1))
+    }
+
+    /// 矩形選択の範囲 (上端行, 下端行, 左端カラム, 右端カラム) を返す（カラムは表示幅、右端は含まない）
+    pub fn block_selection(&self) -> Option<(usize, usize, usize, usize)> {
+        let (anchor_x, anchor_y) = self.visual_start?;
+        let corner_col = |x: usize, y: usize| {
+            let col = self.display_x(x, y);
+            let width = self.line_graphemes(y).get(x).map_or(1, |g| grapheme_width(g, col, self.tab_size).max(1));
+            (col, col + width)
+        };
+        let (anchor_left, anchor_right) = corner_col(anchor_x, anchor_y);
+        let (cursor_left, cursor_right) = corner_col(self.cursor_x, self.cursor_y);
+        Some((
+            anchor_y.min(self.cursor_y),
+            anchor_y.max(self.cursor_y),
+            anchor_left.min(cursor_left),
+            anchor_right.max(cursor_right),
+        ))
+    }
+
+    /// 指定行で、表示カラム `left`〜`right` に始まる書記素の範囲（書記素インデックス）を返す
+    pub fn block_range_in_line(&self, y: usize, left: usize, right: usize) -> (usize, usize) {
+        let mut col = 0;
+        let mut start = None;
+        let mut end = 0;
+        for (i, g) in self.line_graphemes(y).iter().enumerate() {
+            if col >= right {
+                break;
+            }
+            if col >= left {
+                start.get_or_insert(i);
+                end = i + 1;
+            }
+            col += grapheme_width(g, col, self.tab_size);
+        }
+        match start {
+            Some(start) => (start, end),
+            None => (0, 0),
+        }
+    }
+
+    /// 矩形選択の内容を行ごとに取り出し、改行で連結して返す
+    pub fn yank_block(&self, top: usize, bottom: usize, left: usize, right: usize) -> String {
+        (top..=bottom.min(self.buffer.len().saturating_sub(1)))
+            .map(|y| {
+                let (start, end) = self.block_range_in_line(y, left, right);
+                self.line_graphemes(y)[start..end].concat()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// 矩形選択の範囲を削除し、削除した内容を返す
+    pub fn delete_block(&mut self, top: usize, bottom: usize, left: usize, right: usize) -> String {
+        let yanked = self.yank_block(top, bottom, left, right);
+        self.save_state();
+        for y in top..=bottom.min(self.buffer.len().saturating_sub(1)) {
+            let (start, end) = self.block_range_in_line(y, left, right);
+            if start < end {
+                let graphemes = self.line_graphemes(y);
+                let line = [graphemes[..start].concat(), graphemes[end..].concat()].concat();
+                self.buffer[y] = line;
+                self.mark_line_modified(y);
+            }
+        }
+        self.cursor_y = top;
+        self.cursor_x = self.grapheme_index_at_column(top, left);
+        self.clamp_cursor_x();
+        yanked
+    }
+
+    /// 矩形選択の I / A: 先頭行の表示カラム `column` から挿入モードを始め、
+    /// 挿入モードを終えたときに同じテキストを `bottom` 行まで挿入する（`append` なら短い行を空白で埋める）
+    pub fn start_block_insert(&mut self, top: usize, bottom: usize, column: usize, append: bool) {
+        self.start_insert_mode();
+        self.cursor_y = top;
+        let width = self.display_x(self.line_graphemes(top).len(), top);
+        if append && width < column {
+            self.buffer[top].push_str(&" ".repeat(column - width));
+            self.mark_line_modified(top);
+        }
+        self.cursor_x = self.grapheme_index_at_column(top, column);
+        self.block_insert = Some(BlockInsert { start_x: self.cursor_x, first: top + 1, last: bottom, column, pad: append });
+    }
+
+    /// 先頭行に入力したテキストを残りの行の同じ表示カラムに挿入する（改行を含む場合は何もしない）
+    fn apply_block_insert(&mut self, block: BlockInsert) {
+        let top = block.first - 1;
+        if self.cursor_y != top || self.cursor_x <= block.start_x {
+            return;
+        }
+        let text = self.line_graphemes(top)[block.start_x..self.cursor_x].concat();
+        for y in block.first..=block.last.min(self.buffer.len().saturating_sub(1)) {
+            let width = self.display_x(self.line_graphemes(y).len(), y);
+            if width < block.column {
+                if !block.pad {
+                    continue;
+                }
+                self.buffer[y].push_str(&" ".repeat(block.column - width));
+            }
+            let (byte, _) = byte_index_at_column(&self.buffer[y], block.column, self.tab_size);
+            self.buffer[y].insert_str(byte, &text);
+            self.mark_line_modified(y);
+        }
+    }
+
+    /// 表示カラム `col` にある書記素のインデックスを返す
+    fn grapheme_index_at_column(&self, y: usize, col: usize) -> usize {
+        let mut current = 0;
+        for (i, g) in self.line_graphemes(y).iter().enumerate() {
+            let width = grapheme_width(g, current, self.tab_size);
+            if current + width > col {
+                return i;
+            }
+            current += width;
+        }
+        self.line_graphemes(y).len()
+    }
+
+    /// `start` 行から `count` 行を行単位のテキストとして取得する
+    pub fn yank_lines(&self, start: usize, count: usize) -> String {
+        self.buffer
+            .iter()
+            .skip(start)
+            .take(count)
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// カーソルから `motion` を `count` 回適用した位置までを削除し、削除したテキストを返す
+    /// （行をまたぐのは `count` が2以上の d$ だけ）
+    pub fn delete_to_motion(&mut self, motion: Motion, count: usize) -> String {
+        if motion == Motion::LineEnd && count > 1 && self.cursor_y + 1 < self.buffer.len() {
+            self.save_state();
+            let deleted = self.remove_to_line_end(count);
+            self.clamp_cursor_x();
+            return deleted;
+        }
+        let end = self.motion_end(motion, count);
+        if self.cursor_x >= end {
+            return String::new();
+        }
+        self.save_state();
+        let deleted = self.remove_graphemes(self.cursor_y, self.cursor_x, end);
+        self.clamp_cursor_x();
+        deleted
+    }
+
+    /// cw / ce / c$: 削除した範囲を返す。undo 用の状態は呼び出し側で `start_insert_mode` により保存する
+    pub fn change_to_motion(&mut self, motion: Motion, count: usize) -> String {
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        let end = if motion == Motion::WordForward {
+            // cw は ce と同じく単語の末尾までを対象にする（空白上でも ce と同じ）
+            // ただしカーソルが単語の最後の文字にあればその1文字だけを変更する
+            let (line_len, word_end) = {
+                let graphemes = self.line_graphemes(y);
+                let word_end = graphemes.get(x).map(|g| char_class(g)).filter(|&c| c != CharClass::Whitespace).map(|class| {
+                    let mut word_end = x;
+                    while word_end + 1 < graphemes.len() && char_class(graphemes[word_end + 1]) == class {
+                        word_end += 1;
+                    }
+                    word_end
+                });
+                (graphemes.len(), word_end)
+            };
+            let mut remaining = count.max(1);
+            if let Some(word_end) = word_end {
+                self.cursor_x = word_end;
+                remaining -= 1;
+            }
+            for _ in 0..remaining {
+                self.move_word_end();
+            }
+            let end = if self.cursor_y == y { self.cursor_x + 1 } else { line_len };
+            self.cursor_x = x;
+            self.cursor_y = y;
+            end.min(line_len)
+        } else if motion == Motion::LineEnd && count > 1 {
+            return self.remove_to_line_end(count);
+        } else {
+            self.motion_end(motion, count)
+        };
+        if x >= end {
+            return String::new();
+        }
+        self.remove_graphemes(y, x, end)
+    }
+
+    /// cc: `count` 行をインデントだけ残して空にし、変更前の内容を行単位のテキストとして返す
+    pub fn change_lines(&mut self, count: usize) -> String {
+        let y = self.cursor_y;
+        let last = (y + count.max(1) - 1).min(self.buffer.len().saturating_sub(1));
+        let changed = self.yank_lines(y, last - y + 1);
+        let indent: String = self.buffer[y].chars().take_while(|c| c.is_whitespace()).collect();
+        self.buffer.drain(y + 1..=last);
+        self.on_line_deleted(y + 1, last - y);
+        self.cursor_x = indent.graphemes(true).count();
+        self.buffer[y] = indent;
+        self.mark_line_modified(y);
+        changed
+    }
+
+    /// カーソルから `motion` を `count` 回適用した移動先（現在行内の終端、排他的）を返す。カーソルは動かさない
+    fn motion_end(&mut self, motion: Motion, count: usize) -> usize {
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        let line_len = self.line_graphemes(y).len();
+        let end = match motion {
+            Motion::WordForward | Motion::WordEnd => {
+                for _ in 0..count.max(1) {
+                    if motion == Motion::WordForward {
+                        self.move_word_forward();
+                    } else {
+                        self.move_word_end();
+                    }
+                }
+                // 移動先が次の行なら行末までにとどめる
+                match (self.cursor_y == y, motion) {
+                    (true, Motion::WordEnd) => self.cursor_x + 1,
+                    (true, _) => self.cursor_x,
+                    (false, _) => line_len,
+                }
+            }
+            Motion::LineEnd => line_len,
+        };
+        self.cursor_x = x;
+        self.cursor_y = y;
+        end.min(line_len)
+    }
+
+    /// 2D / 2C: カーソルから行末までと、続く `count - 1` 行を取り除いてその内容を返す
+    fn remove_to_line_end(&mut self, count: usize) -> String {
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        let last = (y + count - 1).min(self.buffer.len() - 1);
+        let line_len = self.line_graphemes(y).len();
+        let mut removed = self.remove_graphemes(y, x.min(line_len), line_len);
+        for line in self.buffer.drain(y + 1..=last) {
+            removed.push('\n');
+            removed.push_str(&line);
+        }
+        self.on_line_deleted(y + 1, last - y);
+        removed
+    }
+
+    /// `y` 行の書記素 `start`〜`end`（排他的）を取り除き、その内容を返す
+    fn remove_graphemes(&mut self, y: usize, start: usize, end: usize) -> String {
+        let graphemes = self.line_graphemes(y);
+        let removed = graphemes[start..end].concat();
+        let line = [graphemes[..start].concat(), graphemes[end..].concat()].concat();
+        self.buffer[y] = line;
+        self.mark_line_modified(y);
+        removed
+    }
+
+    /// `start` 行から `count` 行を削除し、削除した内容を行単位のテキストとして返す
+    pub fn delete_lines(&mut self, start: usize, count: usize) -> String {
+        if start >= self.buffer.len() || count == 0 {
+            return String::new();
+        }
+        let end = (start + count).min(self.buffer.len());
+        let removed = self.buffer.drain(start..end);
+        if self.buffer.is_empty() {
+            self.buffer.push(String::new());
+        }
+        self.on_line_deleted(start, end - start);
+        self.cursor_y = start.min(self.buffer.len() - 1);
+        self.cursor_x = self.first_non_blank_x(self.cursor_y);
+
+        removed.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// J / gJ: カーソル行と続く行を `count` 行（最低2行）結合する。`with_space` なら続く行の先頭の空白を除いて
+    /// 空白1つでつなぎ（J）、そうでなければそのままつなぐ（gJ）。カーソルは最後につないだ位置に置く
+    pub fn join_lines(&mut self, count: usize, with_space: bool) -> bool {
+        let y = self.cursor_y;
+        let joins = (count.max(2) - 1).min(self.buffer.len() - 1 - y);
+        if joins == 0 {
+            return false;
+        }
+        self.save_state();
+        for _ in 0..joins {
+            let next = self.buffer.remove(y + 1);
+            let line = &self.buffer[y];
+            let line_len = line.graphemes(true).count();
+            let text = if with_space { next.trim_start() } else { next.as_str() };
+            let separator = if with_space && !text.is_empty() && !line.is_empty() && !line.ends_with(' ') && !text.starts_with(')') {
+                " "
+            } else {
+                ""
+            };
+            // つないだ行のマークは結合後の同じ文字の位置へ移す
+            let removed_indent = next[..next.len() - text.len()].graphemes(true).count();
+            for (mark_x, mark_y) in self.marks.values_mut() {
+                if *mark_y == y + 1 {
+                    *mark_x = line_len + separator.len() + mark_x.saturating_sub(removed_indent);
+                    *mark_y = y;
+                }
+            }
+            self.buffer[y] = format!("{}{}{}", self.buffer[y], separator, text);
+            self.cursor_x = line_len;
+            self.on_line_deleted(y + 1, 1);
+        }
+        true
+    }
+
+    /// バッファ内の `regex` の一致位置 (x, y) をすべて返す（x は書記素単位）
+    pub fn find_matches(&self, regex: &Regex) -> Vec<(usize, usize)> {
+        self.buffer
+            .iter()
+            .enumerate()
+            .flat_map(|(y, line)| {
+                let mut matches: Vec<(usize, usize)> = regex
+                    .find_iter(line)
+                    .map(|m| (line[..m.start()].graphemes(true).count(), y))
+                    .collect();
+                // 書記素の途中で一致した場合に同じ位置が重複しないようにする
+                matches.dedup();
+                matches
+            })
+            .collect()
+    }
+
+    /// カーソル位置から次（`forward` が false なら前）の一致位置を探す。
+    /// `inclusive` ならカーソル位置自体も対象にする。折り返した場合は true も返す
+    pub fn next_match(&self, matches: &[(usize, usize)], forward: bool, inclusive: bool) -> Option<((usize, usize), bool)> {
+        let cursor = (self.cursor_y, self.cursor_x);
+        let key = |&(x, y): &(usize, usize)| (y, x);
+        if forward {
+            matches
+                .iter()
+                .find(|m| if inclusive { key(m) >= cursor } else { key(m) > cursor })
+                .map(|&m| (m, false))
+                .or_else(|| matches.first().map(|&m| (m, true)))
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|m| key(m) < cursor)
+                .map(|&m| (m, false))
+                .or_else(|| matches.last().map(|&m| (m, true)))
+        }
+    }
+
+    /// `start`〜`end` 行の (行数, 単語数, 文字数) を返す。文字数は書記素単位
+    pub fn count_stats(&self, start: usize, end: usize) -> (usize, usize, usize) {
+        let end = end.min(self.buffer.len().saturating_sub(1));
+        if start > end || self.buffer.is_empty() {
+            return (0, 0, 0);
+        }
+        let lines = &self.buffer[start..=end];
+        let words = lines.iter().map(|line| line.split_whitespace().count()).sum();
+        let chars = lines.iter().map(|line| line.graphemes(true).count()).sum();
+        (lines.len(), words, chars)
+    }
+
+    /// `:s` / `:%s` の置換を適用し、(置換数, 変更した行数) を返す
+    pub fn substitute(&mut self, substitute: &Substitute) -> (usize, usize) {
+        let lines = match substitute.range {
+            SubstituteRange::CurrentLine => self.cursor_y..self.cursor_y + 1,
+            SubstituteRange::All => 0..self.buffer.len(),
+        };
+        let replaced: Vec<(usize, String, usize)> = lines
+            .filter_map(|y| {
+                let (line, count) = substitute.apply_to_line(self.buffer.get(y)?);
+                (count > 0).then_some((y, line, count))
+            })
+            .collect();
+        if replaced.is_empty() {
+            return (0, 0);
+        }
+
+        // 1回の u で元に戻せるよう、状態の保存は一度だけ行う
+        self.save_state();
+        let mut total = 0;
+        for (y, line, count) in &replaced {
+            self.buffer[*y] = line.clone();
+            self.mark_line_modified(*y);
+            total += count;
+        }
+        if let Some((y, _, _)) = replaced.last() {
+            self.cursor_y = *y;
+            self.cursor_x = self.first_non_blank_x(*y);
+        }
+        (total, replaced.len())
+    }
+
+    /// `start`〜`end` 行を `delimiter` の最初の出現位置で揃える。揃えた行数を返す
+    pub fn align_lines(&mut self, start: usize, end: usize, delimiter: &str) -> usize {
+        let end = end.min(self.buffer.len().saturating_sub(1));
+        if start > end || delimiter.is_empty() {
+            return 0;
+        }
+        // 区切り文字の前の部分の表示幅を行ごとに求める
+        let columns: Vec<Option<(usize, usize)>> = self.buffer[start..=end]
+            .iter()
+            .map(|line| line.find(delimiter).map(|byte| (byte, line[..byte].width())))
+            .collect();
+        let Some(max_column) = columns.iter().flatten().map(|&(_, col)| col).max() else {
+            return 0;
+        };
+
+        self.save_state();
+        let mut aligned = 0;
+        for (offset, column) in columns.into_iter().enumerate() {
+            if let Some((byte, col)) = column {
+                let y = start + offset;
+                self.buffer[y].insert_str(byte, &" ".repeat(max_column - col));
+                self.mark_line_modified(y);
+                aligned += 1;
+            }
+        }
+        aligned
+    }
+
+    /// 指定行の最初の非空白文字の位置（書記素単位）
+    fn first_non_blank_x(&self, y: usize) -> usize {
+        let graphemes = self.line_graphemes(y);
+        graphemes
+            .iter()
+            .position(|g| char_class(g) != CharClass::Whitespace)
+            .unwrap_or(graphemes.len().saturating_sub(1))
+    }
+
+    /// `0`: 行頭へ移動する
+    pub fn move_to_line_start(&mut self) {
+        self.cursor_x = 0;
+    }
+
+    /// `$`: 行の最後の書記素へ移動する
+    pub fn move_to_line_end(&mut self) {
+        self.cursor_x = self.line_graphemes(self.cursor_y).len().saturating_sub(1);
+    }
+
+    /// `^`: 行の最初の非空白文字へ移動する（タブも空白として扱う）
+    pub fn move_to_first_non_blank(&mut self) {
+        self.cursor_x = self.first_non_blank_x(self.cursor_y);
+    }
+
+    /// `{count}%`: ファイル全体の指定パーセント位置の行へ移動する
+    pub fn jump_to_percentage(&mut self, percent: usize) {
+        let percent = percent.min(100);
+        let total_lines = self.buffer.len();
+        let line_number = (percent * total_lines).div_ceil(100).max(1);
+        self.cursor_y = line_number.min(total_lines) - 1;
+        self.cursor_x = self.first_non_blank_x(self.cursor_y);
+    }
+
+    /// 指定した行（1始まり、バッファ長でクランプ）の先頭へ移動する
+    pub fn goto_line(&mut self, line_number: usize) {
+        let total_lines = self.buffer.len().max(1);
+        self.cursor_y = line_number.clamp(1, total_lines) - 1;
+        self.cursor_x = 0;
+    }
+
+    /// `:42` / `:$`: 行番号（`$` は最終行）で指定した行へ移動する。行番号として読めなければ false
+    pub fn goto_line_address(&mut self, address: &str) -> bool {
+        let line_number = match address {
+            "$" => self.buffer.len(),
+            _ if !address.is_empty() && address.bytes().all(|b| b.is_ascii_digit()) => address.parse().unwrap_or(usize::MAX),
+            _ => return false,
+        };
+        self.goto_line(line_number);
+        true
+    }
+
+    fn line_graphemes(&self, y: usize) -> Vec<&str> {
+        self.buffer.get(y).map_or_else(Vec::new, |line| line.graphemes(true).collect())
+    }
+
+    /// `w`: 次の単語の先頭へ移動する（行をまたぐ）
+    pub fn move_word_forward(&mut self) {
+        let (mut y, mut x) = (self.cursor_y, self.cursor_x);
+        let graphemes = self.line_graphemes(y);
+        if x < graphemes.len() {
+            let class = char_class(graphemes[x]);
+            if class != CharClass::Whitespace {
+                while x < graphemes.len() && char_class(graphemes[x]) == class {
+                    x += 1;
+                }
+            }
+        }
+
+        loop {
+            let graphemes = self.line_graphemes(y);
+            while x < graphemes.len() && char_class(graphemes[x]) == CharClass::Whitespace {
+                x += 1;
+            }
+            if x < graphemes.len() {
+                break;
+            }
+            if y + 1 >= self.buffer.len() {
+                // バッファ末尾では最後の文字に留まる
+                x = graphemes.len().saturating_sub(1);
+                break;
+            }
+            y += 1;
+            x = 0;
+            if self.buffer[y].is_empty() {
+                // 空行も単語として扱う
+                break;
+            }
+        }
+
+        self.cursor_y = y;
+        self.cursor_x = x;
+    }
+
+    /// `b`: 前の単語の先頭へ移動する（行をまたぐ）
+    pub fn move_word_backward(&mut self) {
+        let (mut y, mut x) = (self.cursor_y, self.cursor_x.min(self.line_graphemes(self.cursor_y).len()));
+
+        loop {
+            if x == 0 {
+                if y == 0 {
+                    break;
+                }
+                y -= 1;
+                x = self.line_graphemes(y).len();
+                if x == 0 {
+                    break;
+                }
+                continue;
+            }
+            if char_class(self.line_graphemes(y)[x - 1]) != CharClass::Whitespace {
+                break;
+            }
+            x -= 1;
+        }
+
+        let graphemes = self.line_graphemes(y);
+        if x > 0 {
+            let class = char_class(graphemes[x - 1]);
+            while x > 0 && char_class(graphemes[x - 1]) == class {
+                x -= 1;
+            }
+        }
+
+        self.cursor_y = y;
+        self.cursor_x = x;
+    }
+
+    /// `e`: 現在または次の単語の末尾へ移動する（行をまたぐ）
+    pub fn move_word_end(&mut self) {
+        let (mut y, mut x) = (self.cursor_y, self.cursor_x + 1);
+
+        loop {
+            let graphemes = self.line_graphemes(y);
+            while x < graphemes.len() && char_class(graphemes[x]) == CharClass::Whitespace {
+                x += 1;
+            }
+            if x < graphemes.len() {
+                break;
+            }
+            if y + 1 >= self.buffer.len() {
+                self.cursor_x = graphemes.len().saturating_sub(1);
+                self.cursor_y = y;
+                return;
+            }
+            y += 1;
+            x = 0;
+        }
+
+        let graphemes = self.line_graphemes(y);
+        let class = char_class(graphemes[x]);
+        while x + 1 < graphemes.len() && char_class(graphemes[x + 1]) == class {
+            x += 1;
+        }
+
+        self.cursor_y = y;
+        self.cursor_x = x;
+    }
+
+    /// Tab: `indent_unit` がタブならタブを、空白なら次のタブストップ（`tab_size` の倍数）まで空白を挿入する
+    pub fn insert_tab(&mut self, indent_unit: &str) {
+        self.pad_line_to_cursor();
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        let text = if indent_unit.starts_with('\t') {
+            "\t".to_string()
+        } else {
+            let column = self.display_x(x, y);
+            " ".repeat(self.tab_size - column % self.tab_size)
+        };
+        let line = &mut self.buffer[y];
+        let byte_index = line.grapheme_indices(true).nth(x).map(|(i, _)| i).unwrap_or(line.len());
+        line.insert_str(byte_index, &text);
+        self.cursor_x += text.len();
+        self.mark_line_modified(y);
+    }
+
+    /// 行 `y` のインデントを1段浅くするときに取り除く文字数（先頭のタブ1つか、`indent_unit` の幅までの空白）
+    fn dedent_width(&self, y: usize, indent_unit: &str) -> usize {
+        let line = &self.buffer[y];
+        if line.starts_with('\t') {
+            1
+        } else {
+            let width = if indent_unit.starts_with('\t') { self.tab_size } else { indent_unit.len() };
+            line.chars().take(width).take_while(|&c| c == ' ').count()
+        }
+    }
+
+    /// `>>` / `<<` / ビジュアルモードの `>` `<`: `first` から `last` までの行を1段深く（浅く）する。
+    /// 空行にはインデントを足さず、カーソルは同じ文字の上に残す
+    pub fn shift_lines(&mut self, first: usize, last: usize, indent_unit: &str, right: bool) {
+        let last = last.min(self.buffer.len() - 1);
+        self.save_state();
+        for y in first..=last {
+            let shift = if right {
+                if self.buffer[y].is_empty() {
+                    continue;
+                }
+                self.buffer[y].insert_str(0, indent_unit);
+                indent_unit.graphemes(true).count() as isize
+            } else {
+                let removed = self.dedent_width(y, indent_unit);
+                self.buffer[y].drain(..removed);
+                -(removed as isize)
+            };
+            if y == self.cursor_y {
+                self.cursor_x = self.cursor_x.saturating_add_signed(shift);
+            }
+            self.mark_line_modified(y);
+        }
+    }
+
+    /// f / F / t / T: 現在行で `count` 個目の `search.target` へ移動する。見つからなければ動かない。
+    /// `repeat`（`;` / `,`）の t / T では、すぐ隣の文字で止まり続けないよう1文字飛ばして探す
+    pub fn find_char(&mut self, search: CharSearch, count: usize, repeat: bool) -> bool {
+        let graphemes = self.line_graphemes(self.cursor_y);
+        let target = search.target.to_string();
+        let skip = usize::from(search.till && repeat);
+        let found = if search.forward {
+            (self.cursor_x + 1 + skip..graphemes.len())
+                .filter(|&x| graphemes[x] == target)
+                .nth(count.max(1) - 1)
+                .map(|x| if search.till { x - 1 } else { x })
+        } else {
+            (0..self.cursor_x.saturating_sub(skip))
+                .rev()
+                .filter(|&x| graphemes[x] == target)
+                .nth(count.max(1) - 1)
+                .map(|x| if search.till { x + 1 } else { x })
+        };
+        match found {
+            Some(x) => {
+                self.cursor_x = x;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// gcc / ビジュアルモードの gc: `first` から `last` までの行の行コメントを切り替える。
+    /// コメントでない行が1行でもあれば全行を最も浅いインデントの位置でコメントにし、そうでなければ全行のコメントを外す（空行はそのまま）
+    pub fn toggle_comment(&mut self, first: usize, last: usize) -> bool {
+        let last = last.min(self.buffer.len() - 1);
+        let Some(marker) = Language::from_filename(self.filename()).line_comment() else {
+            return false;
+        };
+        let lines: Vec<usize> = (first..=last).filter(|&y| !self.buffer[y].trim().is_empty()).collect();
+        let indent_of = |line: &str| line.len() - line.trim_start().len();
+        let Some(indent) = lines.iter().map(|&y| indent_of(&self.buffer[y])).min() else {
+            return false;
+        };
+        let comment = lines.iter().any(|&y| !self.buffer[y].trim_start().starts_with(marker));
+        self.save_state();
+        for y in lines {
+            let (start, shift) = if comment {
+                self.buffer[y].insert_str(indent, &format!("{} ", marker));
+                (indent, marker.len() as isize + 1)
+            } else {
+                let start = indent_of(&self.buffer[y]);
+                let mut end = start + marker.len();
+                if self.buffer[y][end..].starts_with(' ') {
+                    end += 1;
+                }
+                self.buffer[y].drain(start..end);
+                (start, -((end - start) as isize))
+            };
+            // カーソルが挿入（削除）位置より後ろなら同じ文字の上に残す
+            let start_x = self.buffer[y][..start].graphemes(true).count();
+            if y == self.cursor_y && self.cursor_x >= start_x {
+                self.cursor_x = self.cursor_x.saturating_add_signed(shift).max(start_x);
+            }
+            self.mark_line_modified(y);
+        }
+        true
+    }
+
+    /// Shift+Tab: 現在行のインデントを1段（先頭のタブ1つか、`indent_unit` の幅までの空白）浅くする
+    pub fn dedent_line(&mut self, indent_unit: &str) {
+        let y = self.cursor_y;
+        let removed = self.dedent_width(y, indent_unit);
+        if removed == 0 {
+            return;
+        }
+        self.buffer[y].drain(..removed);
+        self.cursor_x = self.cursor_x.saturating_sub(removed);
+        self.mark_line_modified(y);
+    }
+
+    /// カーソル位置で行を分割し、新しい行にカーソルを移す
+    /// `auto_indent` なら前の行のインデントを引き継ぎ、括弧に応じて1段深く・浅くする
+    pub fn insert_newline(&mut self, indent_unit: &str, auto_indent: bool) {
+        let y = self.cursor_y;
+        let current_line = &mut self.buffer[y];
+        let byte_index = current_line
+            .grapheme_indices(true)
+            .nth(self.cursor_x)
+            .map(|(i, _)| i)
+            .unwrap_or(current_line.len());
+        let new_line = current_line.split_off(byte_index);
+
+        let mut indent = String::new();
+        if auto_indent {
+            // 前の行の先頭のインデント（空白とタブ）を取得
+            indent = current_line.chars().take_while(|&ch| ch == ' ' || ch == '\t').collect();
+            if current_line.ends_with('{') || current_line.ends_with('[') || current_line.ends_with('(') {
+                // 前の行の末尾が開き括弧の場合、インデントを深くする
+                indent.push_str(indent_unit);
+            } else if new_line.starts_with('}') || new_line.starts_with(')') || new_line.starts_with(']') {
+                // 新しい行の先頭が閉じ括弧の場合、インデントを一段浅くする
+                if let Some(dedented) = indent.strip_suffix(indent_unit) {
+                    indent.truncate(dedented.len());
+                }
+            }
+        }
+
+        self.cursor_x = indent.graphemes(true).count();
+        self.buffer.insert(y + 1, format!("{}{}", indent, new_line));
+        self.cursor_y = y + 1;
+        self.on_line_inserted(self.cursor_y);
+    }
+
+    /// o / O: 現在行の下（`above` なら上）に空行を開き、カーソルを移動する
+    /// `auto_indent` なら現在行のインデントを引き継ぐ
+    pub fn open_new_line(&mut self, above: bool, auto_indent: bool) {
+        self.save_state();
+        let indent: String = if auto_indent {
+            self.buffer.get(self.cursor_y).map_or_else(String::new, |line| line.chars().take_while(|c| c.is_whitespace()).collect())
+        } else {
+            String::new()
+        };
+        let new_line_y = if above { self.cursor_y } else { self.cursor_y + 1 };
+        self.cursor_x = indent.graphemes(true).count();
+        self.buffer.insert(new_line_y, indent);
+        self.cursor_y = new_line_y;
+        self.on_line_inserted(new_line_y);
+    }
+    pub fn move_to_screen_top(&mut self) {
+        self.cursor_y = self.scroll_y;
+        if self.cursor_y < self.buffer.len() {
+            let line_len = self.buffer[self.cursor_y].len();
+            if self.cursor_x > line_len {
+                self.cursor_x = line_len;
+            }
+        }
+    }
+
+    pub fn move_to_screen_bottom(&mut self, visible_height: usize) {
+        let last_visible_line = (self.scroll_y + visible_height.saturating_sub(1))
+            .min(self.buffer.len().saturating_sub(1));
+        self.cursor_y = last_visible_line;
+        if self.cursor_y < self.buffer.len() {
+            let line_len = self.buffer[self.cursor_y].len();
+            if self.cursor_x > line_len {
+                self.cursor_x = line_len;
+            }
+        }
+    }
+
+    /// m{a-z}: 現在のカーソル位置をマークとして記録する
+    pub fn set_mark(&mut self, name: char) {
+        self.marks.insert(name, (self.cursor_x, self.cursor_y));
+    }
+
+    /// `{a-z}: マークの位置へ移動する（バッファの範囲内に収める）。マークがなければ false を返す
+    pub fn jump_to_mark(&mut self, name: char) -> bool {
+        let Some(&(x, y)) = self.marks.get(&name) else {
+            return false;
+        };
+        self.cursor_y = y.min(self.buffer.len().saturating_sub(1));
+        self.cursor_x = x;
+        self.clamp_cursor_x();
+        true
+    }
+
+    pub fn extra_cursors(&self) -> &[(usize, usize)] {
+        &self.extra_cursors
+    }
+
+    pub fn has_extra_cursors(&self) -> bool {
+        !self.extra_cursors.is_empty()
+    }
+
+    pub fn clear_extra_cursors(&mut self) {
+        self.extra_cursors.clear();
+    }
+
+    /// 主カーソルと追加カーソルをすべて返す（主カーソルが先頭）
+    fn all_cursors(&self) -> Vec<(usize, usize)> {
+        let mut cursors = vec![(self.cursor_x, self.cursor_y)];
+        for &cursor in &self.extra_cursors {
+            if !cursors.contains(&cursor) {
+                cursors.push(cursor);
+            }
+        }
+        cursors
+    }
+
+    /// カーソル下の単語の範囲（書記素単位の start..end）を返す
+    fn word_bounds_at_cursor(&self) -> Option<(usize, usize)> {
+        let graphemes = self.line_graphemes(self.cursor_y);
+        let is_word = |g: &&str| char_class(g) == CharClass::Word;
+        if !graphemes.get(self.cursor_x).is_some_and(is_word) {
+            return None;
+        }
+        let start = graphemes[..self.cursor_x].iter().rposition(|g| !is_word(g)).map_or(0, |i| i + 1);
+        let end = graphemes[self.cursor_x..].iter().position(|g| !is_word(g)).map_or(graphemes.len(), |i| self.cursor_x + i);
+        Some((start, end))
+    }
+
+    /// カーソルの直前にある単語の開始位置（書記素単位）を返す。補完対象の接頭辞になる
+    pub fn completion_prefix_start(&self) -> usize {
+        let graphemes = self.line_graphemes(self.cursor_y);
+        let x = self.cursor_x.min(graphemes.len());
+        graphemes[..x].iter().rposition(|g| char_class(g) != CharClass::Word).map_or(0, |i| i + 1)
+    }
+
+    /// カーソルの直前にある単語（補完の接頭辞）を (行, 開始位置, 単語) で返す
+    pub fn completion_prefix(&self) -> (usize, usize, String) {
+        let start = self.completion_prefix_start();
+        let prefix = self.line_graphemes(self.cursor_y)[start..self.cursor_x.max(start)].concat();
+        (self.cursor_y, start, prefix)
+    }
+
+    /// バッファ内の `prefix` で始まる単語を、カーソルに近い行から順に重複なく返す
+    pub fn buffer_words_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut words: Vec<String> = Vec::new();
+        let mut lines: Vec<usize> = (0..self.buffer.len()).collect();
+        lines.sort_by_key(|y| y.abs_diff(self.cursor_y));
+        for y in lines {
+            let graphemes = self.line_graphemes(y);
+            for word in graphemes.chunk_by(|a, b| char_class(a) == char_class(b)) {
+                if char_class(word[0]) != CharClass::Word {
+                    continue;
+                }
+                let word = word.concat();
+                if word.starts_with(prefix) && word != prefix && !words.contains(&word) {
+                    words.push(word);
+                }
+            }
+        }
+        words
+    }
+
+    /// カーソル直前の単語（とカーソル下に続く単語の残り）を `completion` に置き換える
+    pub fn replace_word_with_completion(&mut self, completion: &str) {
+        let start = self.completion_prefix_start();
+        let graphemes = self.line_graphemes(self.cursor_y);
+        let x = self.cursor_x.min(graphemes.len());
+        let end = graphemes[x..].iter().position(|g| char_class(g) != CharClass::Word).map_or(graphemes.len(), |i| x + i);
+        let line = [graphemes[..start].concat(), completion.to_string(), graphemes[end..].concat()].concat();
+        self.buffer[self.cursor_y] = line;
+        self.cursor_x = start + completion.graphemes(true).count();
+        self.mark_line_modified(self.cursor_y);
+    }
+
+    /// カーソル下の単語をバッファ全体で `new_name` に置き換える。置換した数を返す
+    pub fn rename_word_at_cursor(&mut self, new_name: &str) -> usize {
+        let Some((start, end)) = self.word_bounds_at_cursor() else {
+            return 0;
+        };
+        let word: String = self.line_graphemes(self.cursor_y)[start..end].concat();
+        let is_word = |g: &str| char_class(g) == CharClass::Word;
+
+        let mut replaced_lines = Vec::new();
+        let mut count = 0;
+        for line in &self.buffer {
+            let mut result = String::with_capacity(line.len());
+            let mut last = 0;
+            for (byte, _) in line.match_indices(&word) {
+                // 単語の境界でのみ置換する（部分一致は無視）
+                let bounded_before = line[..byte].graphemes(true).next_back().is_none_or(|g| !is_word(g));
+                let bounded_after = line[byte + word.len()..].graphemes(true).next().is_none_or(|g| !is_word(g));
+                if bounded_before && bounded_after {
+                    result.push_str(&line[last..byte]);
+                    result.push_str(new_name);
+                    last = byte + word.len();
+                    count += 1;
+                }
+            }
+            result.push_str(&line[last..]);
+            replaced_lines.push(result);
+        }
+        if count == 0 || word == new_name {
+            return count;
+        }
+
+        self.save_state();
+        for (y, line) in replaced_lines.into_iter().enumerate() {
+            if self.buffer[y] != line {
+                self.buffer[y] = line;
+                self.mark_line_modified(y);
+            }
+        }
+        self.cursor_x = start;
+        count
+    }
+
+    /// 主カーソル下の単語の次の出現位置にカーソルを追加する（Ctrl+D）
+    pub fn add_cursor_at_next_occurrence(&mut self) -> bool {
+        let is_word = |g: &&str| char_class(g) == CharClass::Word;
+        let Some((start, end)) = self.word_bounds_at_cursor() else {
+            return false;
+        };
+        let graphemes = self.line_graphemes(self.cursor_y);
+        let word = graphemes[start..end].to_vec();
+        let offset = self.cursor_x - start;
+
+        // 最後に追加したカーソル（なければ主カーソル）の単語の後ろから検索する
+        let (from_x, from_y) = self.extra_cursors.last().copied().unwrap_or((self.cursor_x, self.cursor_y));
+        let from_x = from_x.saturating_sub(offset) + word.len();
+        let line_count = self.buffer.len();
+        for step in 0..=line_count {
+            let y = (from_y + step) % line_count;
+            let line = self.line_graphemes(y);
+            let begin = if step == 0 { from_x } else { 0 };
+            for x in begin..line.len() {
+                if line.len() - x < word.len() || line[x..x + word.len()] != word[..] {
+                    continue;
+                }
+                // 単語の境界でのみ一致とみなす
+                let bounded_before = x == 0 || !is_word(&line[x - 1]);
+                let bounded_after = line.get(x + word.len()).is_none_or(|g| !is_word(g));
+                let candidate = (x + offset, y);
+                if bounded_before && bounded_after && !self.all_cursors().contains(&candidate) {
+                    self.extra_cursors.push(candidate);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// カーソル位置に文字を挿入する
+    /// `auto_pairs` なら開き括弧・引用符に対応する閉じ文字も挿入し、次の文字と同じ閉じ文字は上書きせず飛び越す
+    pub fn insert_char(&mut self, c: char, auto_pairs: bool) {
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        if self.buffer.len() <= y {
+            self.buffer.resize(y + 1, String::new());
+        }
+        self.pad_line_to_cursor();
+        let graphemes = self.line_graphemes(y);
+        let next = graphemes.get(x).copied();
+        // 英数字の直後の引用符はアポストロフィやライフタイムとみなして対にしない
+        let after_word = x.checked_sub(1).and_then(|i| graphemes.get(i)).is_some_and(|g| g.chars().all(char::is_alphanumeric));
+        let mut buf = [0; 4];
+        let typed = &*c.encode_utf8(&mut buf);
+        if auto_pairs && matches!(c, ')' | ']' | '}' | '"' | '\'') && next == Some(typed) {
+            self.cursor_x += 1;
+            return;
+        }
+        let closing = match c {
+            '(' => Some(')'),
+            '[' => Some(']'),
+            '{' => Some('}'),
+            '"' | '\'' if !after_word => Some(c),
+            _ => None,
+        }
+        .filter(|_| auto_pairs);
+
+        let line = &mut self.buffer[y];
+        let byte_index = line.grapheme_indices(true).nth(x).map(|(i, _)| i).unwrap_or(line.len());
+        line.insert(byte_index, c);
+        if let Some(closing) = closing {
+            line.insert(byte_index + c.len_utf8(), closing);
+        }
+        self.cursor_x += 1;
+        self.on_char_inserted(y, x, c);
+    }
+
+    /// カーソルの直前の文字を削除する。行頭なら前の行と結合する
+    /// `auto_pairs` で空の括弧・引用符の間にいる場合は閉じ文字も削除する
+    pub fn backspace(&mut self, auto_pairs: bool) {
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        if x > 0 {
+            let is_empty_pair = auto_pairs
+                && matches!(
+                    (self.line_graphemes(y).get(x - 1).copied(), self.line_graphemes(y).get(x).copied()),
+                    (Some("("), Some(")")) | (Some("["), Some("]")) | (Some("{"), Some("}")) | (Some("\""), Some("\"")) | (Some("'"), Some("'"))
+                );
+            if is_empty_pair {
+                self.remove_graphemes(y, x, x + 1);
+            }
+            let line = &mut self.buffer[y];
+            let prev_grapheme = line.grapheme_indices(true).nth(x - 1).map(|(i, _)| i).unwrap_or(0);
+            let removed = line[prev_grapheme..].chars().next().unwrap_or('\0');
+            line.drain(prev_grapheme..prev_grapheme + removed.len_utf8());
+            self.cursor_x -= 1;
+            self.on_char_deleted(y, x - 1, removed);
+        } else if y > 0 {
+            // 行頭なら前の行と結合
+            let prev_line_len = self.buffer[y - 1].graphemes(true).count();
+            let current_line = self.buffer.remove(y);
+            self.buffer[y - 1].push_str(&current_line);
+            self.cursor_y -= 1;
+            self.cursor_x = prev_line_len;
+            // 結合した行のマークは前の行の同じ位置へ移す
+            for (mark_x, mark_y) in self.marks.values_mut() {
+                if *mark_y == y {
+                    *mark_x += prev_line_len;
+                    *mark_y = y - 1;
+                }
+            }
+            self.on_line_deleted(y, 1);
+        }
+    }
+
+    /// すべてのカーソル位置に文字を挿入する
+    pub fn insert_char_at_cursors(&mut self, c: char) {
+        let cursors = self.all_cursors();
+        // インデックスがずれないよう、後ろのカーソルから処理する
+        let mut ordered = cursors.clone();
+        ordered.sort_by_key(|&(x, y)| std::cmp::Reverse((y, x)));
+        for &(x, y) in &ordered {
+            if let Some(line) = self.buffer.get_mut(y) {
+                let byte_index = line.grapheme_indices(true).nth(x).map(|(i, _)| i).unwrap_or(line.len());
+                line.insert(byte_index, c);
+                self.mark_line_modified(y);
+            }
+        }
+        let moved: Vec<(usize, usize)> = cursors
+            .iter()
+            .map(|&(x, y)| {
+                let before = cursors.iter().filter(|&&(ox, oy)| oy == y && ox < x).count();
+                (x + before + 1, y)
+            })
+            .collect();
+        self.set_cursors(&moved);
+    }
+
+    /// すべてのカーソル位置の直前の文字を削除する（行頭では何もしない）
+    pub fn backspace_at_cursors(&mut self) {
+        let cursors = self.all_cursors();
+        let mut ordered = cursors.clone();
+        ordered.sort_by_key(|&(x, y)| std::cmp::Reverse((y, x)));
+        for &(x, y) in &ordered {
+            if x == 0 {
+                continue;
+            }
+            if let Some(line) = self.buffer.get_mut(y) {
+                let mut graphemes: Vec<&str> = line.graphemes(true).collect();
+                if x <= graphemes.len() {
+                    graphemes.remove(x - 1);
+                    *line = graphemes.concat();
+                    self.mark_line_modified(y);
+                }
+            }
+        }
+        let moved: Vec<(usize, usize)> = cursors
+            .iter()
+            .map(|&(x, y)| {
+                let before = cursors.iter().filter(|&&(ox, oy)| oy == y && ox > 0 && ox < x).count();
+                (x.saturating_sub(before + usize::from(x > 0)), y)
+            })
+            .collect();
+        self.set_cursors(&moved);
+    }
+
+    fn set_cursors(&mut self, cursors: &[(usize, usize)]) {
+        if let Some((&(x, y), rest)) = cursors.split_first() {
+            self.cursor_x = x;
+            self.cursor_y = y;
+            self.extra_cursors = rest.to_vec();
+        }
+    }
+}
+
+/// 表示カラム `col` 以降で最初の書記素の先頭バイト位置と、その位置の表示カラムを返す（タブは次のタブストップまでの幅）
+fn byte_index_at_column(line: &str, col: usize, tab_size: usize) -> (usize, usize) {
+    let mut current = 0;
+    for (byte, g) in line.grapheme_indices(true) {
+        if current >= col {
+            return (byte, current);
+        }
+        current += grapheme_width(g, current, tab_size);
+    }
+    (line.len(), current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_with(lines: &[&str]) -> Window {
+        let mut window = Window::new(None);
+        *window.buffer_mut() = lines.iter().map(|l| l.to_string()).collect();
+        window
+    }
+
+    #[test]
+    fn test_swap_file_recovery() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("recover.txt");
+        fs::write(&path, "original\n").unwrap();
+
+        let mut window = Window::new(Some(path.to_string_lossy().to_string()));
+        assert!(!window.detect_swap());
+        window.buffer_mut()[0] = "edited".to_string();
+        window.mark_line_modified(0);
+        window.write_swap_file().unwrap();
+
+        // クラッシュ後に開き直した想定
+        let mut reopened = Window::new(Some(path.to_string_lossy().to_string()));
+        assert!(!reopened.has_recoverable_swap());
+        assert!(reopened.detect_swap());
+        assert!(reopened.has_recoverable_swap());
+        assert_eq!(reopened.buffer()[0], "original");
+        reopened.recover_from_swap().unwrap();
+        assert_eq!(reopened.buffer()[0], "edited");
+
+        reopened.save_file(&EditorConfig::default()).unwrap();
+        assert!(!reopened.swap_path().unwrap().exists());
+    }
+
+    #[test]
+    fn test_delete_lines_only_line() {
+        let mut window = window_with(&["hello"]);
+        let deleted = window.delete_lines(0, 1);
+        assert_eq!(deleted, "hello\n");
+        assert_eq!(window.buffer(), &vec![String::new()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 0));
+    }
+
+    #[test]
+    fn test_delete_lines_last_line() {
+        let mut window = window_with(&["one", "  two"]);
+        *window.cursor_y_mut() = 1;
+        let deleted = window.delete_lines(1, 1);
+        assert_eq!(deleted, "  two\n");
+        assert_eq!(window.buffer(), &vec!["one".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 0));
+    }
+
+    #[test]
+    fn test_delete_lines_middle_line() {
+        let mut window = window_with(&["one", "two", "    three"]);
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 2;
+        let deleted = window.delete_lines(1, 1);
+        assert_eq!(deleted, "two\n");
+        assert_eq!(window.buffer(), &vec!["one".to_string(), "    three".to_string()]);
+        // 同じ行番号に残り、最初の非空白文字へ移動する
+        assert_eq!((window.cursor_x(), window.cursor_y()), (4, 1));
+    }
+
+    #[test]
+    fn test_line_motions_with_leading_whitespace() {
+        let mut window = window_with(&["  \t foo bar"]);
+        *window.cursor_x_mut() = 6;
+        window.move_to_line_start();
+        assert_eq!(window.cursor_x(), 0);
+        window.move_to_first_non_blank();
+        assert_eq!(window.cursor_x(), 4);
+        window.move_to_line_end();
+        assert_eq!(window.cursor_x(), 10);
+    }
+
+    #[test]
+    fn test_line_motions_multibyte() {
+        let mut window = window_with(&["  日本語👍"]);
+        window.move_to_line_end();
+        assert_eq!(window.cursor_x(), 5);
+        window.move_to_first_non_blank();
+        assert_eq!(window.cursor_x(), 2);
+
+        let mut empty = window_with(&[""]);
+        empty.move_to_line_end();
+        assert_eq!(empty.cursor_x(), 0);
+        empty.move_to_first_non_blank();
+        assert_eq!(empty.cursor_x(), 0);
+    }
+
+    #[test]
+    fn test_jump_to_percentage() {
+        let lines: Vec<String> = (0..10).map(|i| format!("  line {}", i)).collect();
+        let refs: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let mut window = window_with(&refs);
+        window.jump_to_percentage(50);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 4));
+        window.jump_to_percentage(1);
+        assert_eq!(window.cursor_y(), 0);
+        window.jump_to_percentage(100);
+        assert_eq!(window.cursor_y(), 9);
+        window.jump_to_percentage(250);
+        assert_eq!(window.cursor_y(), 9);
+    }
+
+    #[test]
+    fn test_word_forward_and_backward() {
+        let mut window = window_with(&["let foo = bar(1);", "next"]);
+        window.move_word_forward();
+        assert_eq!((window.cursor_x(), window.cursor_y()), (4, 0)); // foo
+        window.move_word_forward();
+        assert_eq!(window.cursor_x(), 8); // =
+        window.move_word_forward();
+        assert_eq!(window.cursor_x(), 10); // bar
+        window.move_word_forward();
+        assert_eq!(window.cursor_x(), 13); // (
+
+        window.move_word_backward();
+        assert_eq!(window.cursor_x(), 10);
+        window.move_word_backward();
+        assert_eq!(window.cursor_x(), 8);
+    }
+
+    #[test]
+    fn test_word_motions_wrap_lines() {
+        let mut window = window_with(&["foo bar", "  baz"]);
+        *window.cursor_x_mut() = 4;
+        window.move_word_forward();
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 1));
+        window.move_word_backward();
+        assert_eq!((window.cursor_x(), window.cursor_y()), (4, 0));
+    }
+
+    #[test]
+    fn test_word_end() {
+        let mut window = window_with(&["foo bar", "baz"]);
+        window.move_word_end();
+        assert_eq!(window.cursor_x(), 2);
+        window.move_word_end();
+        assert_eq!(window.cursor_x(), 6);
+        window.move_word_end();
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 1));
+    }
+
+    #[test]
+    fn test_word_motions_multibyte() {
+        let mut window = window_with(&["日本語 テスト"]);
+        window.move_word_forward();
+        assert_eq!(window.cursor_x(), 4);
+        window.move_word_end();
+        assert_eq!(window.cursor_x(), 6);
+    }
+
+    #[test]
+    fn test_pad_line_to_cursor() {
+        let mut window = window_with(&["ab"]);
+        *window.cursor_x_mut() = 5;
+        window.pad_line_to_cursor();
+        assert_eq!(window.buffer()[0], "ab   ");
+        // 行内にいる場合は何もしない
+        *window.cursor_x_mut() = 1;
+        window.pad_line_to_cursor();
+        assert_eq!(window.buffer()[0], "ab   ");
+    }
+
+    #[test]
+    fn test_goto_line() {
+        let mut window = window_with(&["a", "b", "c"]);
+        window.goto_line(2);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 1));
+        window.goto_line(42);
+        assert_eq!(window.cursor_y(), 2);
+        window.goto_line(0);
+        assert_eq!(window.cursor_y(), 0);
+    }
+
+    #[test]
+    fn test_goto_line_address() {
+        let mut window = window_with(&["first", "second", "third"]);
+        *window.cursor_x_mut() = 3;
+        assert!(window.goto_line_address("$"));
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 2));
+        assert!(window.goto_line_address("1"));
+        assert_eq!(window.cursor_y(), 0);
+        // 範囲外の行番号は最終行に収める
+        assert!(window.goto_line_address("99999999999999999999999"));
+        assert_eq!(window.cursor_y(), 2);
+        assert!(!window.goto_line_address("1a"));
+        assert!(!window.goto_line_address("+1"));
+    }
+
+    #[test]
+    fn test_scroll_follows_line_end_on_long_line() {
+        let mut window = window_with(&[&"a".repeat(100), "日本語日本語"]);
+        window.move_to_line_end();
+        window.scroll_to_cursor(10, 20, 0);
+        assert_eq!(window.scroll_x(), 80);
+        window.move_to_line_start();
+        window.scroll_to_cursor(10, 20, 0);
+        assert_eq!(window.scroll_x(), 0);
+        // 全角文字は表示幅でスクロールする
+        *window.cursor_y_mut() = 1;
+        window.move_to_line_end();
+        window.scroll_to_cursor(10, 6, 0);
+        assert_eq!(window.scroll_x(), 6);
+    }
+
+    #[test]
+    fn test_scroll_cursor_line_to_top_center_and_bottom() {
+        let lines: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        let mut window = window_with(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+        *window.cursor_y_mut() = 30;
+        window.scroll_to_cursor(10, 20, 0);
+        assert_eq!(window.scroll_y(), 21);
+
+        window.scroll_cursor_line(ScrollAlign::Top);
+        assert_eq!(window.scroll_y(), 30);
+        window.scroll_cursor_line(ScrollAlign::Center);
+        assert_eq!(window.scroll_y(), 26);
+        window.scroll_cursor_line(ScrollAlign::Bottom);
+        assert_eq!(window.scroll_y(), 21);
+        // 描画し直してもカーソルは画面内なので位置は変わらない
+        window.scroll_to_cursor(10, 20, 0);
+        assert_eq!(window.scroll_y(), 21);
+
+        // 先頭付近ではそれ以上上にスクロールしない
+        *window.cursor_y_mut() = 2;
+        window.scroll_cursor_line(ScrollAlign::Bottom);
+        assert_eq!(window.scroll_y(), 0);
+    }
+
+    #[test]
+    fn test_add_cursor_at_next_occurrence() {
+        let mut window = window_with(&["foo bar foo", "foobar foo"]);
+        assert!(window.add_cursor_at_next_occurrence());
+        assert_eq!(window.extra_cursors(), &[(8, 0)]);
+        // 単語の一部（foobar）には一致しない
+        assert!(window.add_cursor_at_next_occurrence());
+        assert_eq!(window.extra_cursors(), &[(8, 0), (7, 1)]);
+        // 一周したらそれ以上は追加しない
+        assert!(!window.add_cursor_at_next_occurrence());
+
+        // 編集で最後のカーソルが単語の先頭より左へずれていても検索を続けられる
+        let mut window = window_with(&["foo", "foo"]);
+        window.set_cursors(&[(2, 0), (0, 1)]);
+        assert!(window.add_cursor_at_next_occurrence());
+        assert_eq!(window.extra_cursors(), &[(0, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn test_insert_and_backspace_at_multiple_cursors() {
+        let mut window = window_with(&["ab ab", "ab"]);
+        window.set_cursors(&[(0, 0), (3, 0), (0, 1)]);
+        window.insert_char_at_cursors('x');
+        assert_eq!(window.buffer(), &vec!["xab xab".to_string(), "xab".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 0));
+        assert_eq!(window.extra_cursors(), &[(5, 0), (1, 1)]);
+
+        window.backspace_at_cursors();
+        assert_eq!(window.buffer(), &vec!["ab ab".to_string(), "ab".to_string()]);
+        assert_eq!(window.extra_cursors(), &[(3, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn test_align_lines_on_delimiter() {
+        let mut window = window_with(&["a = 1", "long_name = 2", "no delimiter", "日本 = 3"]);
+        assert_eq!(window.align_lines(0, 3, "="), 3);
+        assert_eq!(
+            window.buffer(),
+            &vec![
+                "a         = 1".to_string(),
+                "long_name = 2".to_string(),
+                "no delimiter".to_string(),
+                "日本      = 3".to_string(),
+            ]
+        );
+        // 1回の操作で元に戻せる
+        assert!(window.undo());
+        assert_eq!(window.buffer()[0], "a = 1");
+    }
+
+    #[test]
+    fn test_find_matches_with_wide_characters() {
+        let window = window_with(&["日本語 foo", "foo bar foo"]);
+        let regex = Regex::new("foo").unwrap();
+        assert_eq!(window.find_matches(&regex), vec![(4, 0), (0, 1), (8, 1)]);
+        let regex = Regex::new("本.").unwrap();
+        assert_eq!(window.find_matches(&regex), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn test_next_match_wraps_around() {
+        let mut window = window_with(&["foo", "bar foo"]);
+        let matches = window.find_matches(&Regex::new("foo").unwrap());
+        assert_eq!(window.next_match(&matches, true, true), Some(((0, 0), false)));
+        assert_eq!(window.next_match(&matches, true, false), Some(((4, 1), false)));
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 4;
+        assert_eq!(window.next_match(&matches, true, false), Some(((0, 0), true)));
+        assert_eq!(window.next_match(&matches, false, false), Some(((0, 0), false)));
+        *window.cursor_y_mut() = 0;
+        *window.cursor_x_mut() = 0;
+        assert_eq!(window.next_match(&matches, false, false), Some(((4, 1), true)));
+    }
+
+    #[test]
+    fn test_rename_word_at_cursor() {
+        let mut window = window_with(&["let foo = foo_bar + foo;", "foo(foo);"]);
+        *window.cursor_x_mut() = 5;
+        assert_eq!(window.rename_word_at_cursor("baz"), 4);
+        assert_eq!(window.buffer(), &vec!["let baz = foo_bar + baz;".to_string(), "baz(baz);".to_string()]);
+        assert_eq!(window.cursor_x(), 4);
+        // 1回の操作で元に戻せる
+        assert!(window.undo());
+        assert_eq!(window.buffer()[1], "foo(foo);");
+    }
+
+    #[test]
+    fn test_linewise_paste_duplicates_line_below() {
+        let mut window = window_with(&["  first", "second"]);
+        let yanked = window.yank_lines(0, 1);
+        window.paste(&yanked, YankKind::LineWise, false);
+        assert_eq!(window.buffer(), &vec!["  first".to_string(), "  first".to_string(), "second".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 1));
+        // P は現在行の上に貼り付ける
+        *window.cursor_y_mut() = 2;
+        window.paste("above\n", YankKind::LineWise, true);
+        assert_eq!(window.buffer()[2], "above");
+        assert_eq!(window.buffer()[3], "second");
+        assert_eq!(window.cursor_y(), 2);
+    }
+
+    #[test]
+    fn test_charwise_paste_stays_inline() {
+        let mut window = window_with(&["ac"]);
+        window.paste("b", YankKind::CharWise, false);
+        assert_eq!(window.buffer(), &vec!["abc".to_string()]);
+        assert_eq!(window.cursor_x(), 1);
+        window.paste("xy", YankKind::CharWise, true);
+        assert_eq!(window.buffer(), &vec!["axybc".to_string()]);
+        assert_eq!(window.cursor_x(), 2);
+    }
+
+    #[test]
+    fn test_substitute_whole_buffer_single_undo() {
+        let mut window = window_with(&["foo foo", "bar", "foo"]);
+        let sub = Substitute::parse("%s/foo/baz/g").unwrap().unwrap();
+        assert_eq!(window.substitute(&sub), (3, 2));
+        assert_eq!(window.buffer(), &vec!["baz baz".to_string(), "bar".to_string(), "baz".to_string()]);
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &vec!["foo foo".to_string(), "bar".to_string(), "foo".to_string()]);
+
+        // :s は現在行の最初の一致のみ
+        let sub = Substitute::parse("s/foo/baz/").unwrap().unwrap();
+        assert_eq!(window.substitute(&sub), (1, 1));
+        assert_eq!(window.buffer()[0], "baz foo");
+        assert_eq!(window.buffer()[2], "foo");
+    }
+
+    #[test]
+    fn test_count_stats() {
+        let window = window_with(&["hello world", "", "  日本語 テキスト  "]);
+        assert_eq!(window.count_stats(0, 2), (3, 4, 23));
+        assert_eq!(window.count_stats(0, 0), (1, 2, 11));
+        assert_eq!(window.count_stats(2, 10), (1, 2, 12));
+    }
+
+    #[test]
+    fn test_write_file_and_save_file_as() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("new.txt");
+        let copy = dir.join("copy.txt");
+        let config = EditorConfig::default();
+
+        // ファイル名の無いバッファは :w path でそのファイル名になる
+        let mut window = window_with(&["hello"]);
+        window.write_file(path.to_str().unwrap(), &config).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+        assert_eq!(window.filename(), path.to_str());
+
+        // ファイル名があれば内容だけを書き出す
+        window.insert_char('x', false);
+        window.write_file(copy.to_str().unwrap(), &config).unwrap();
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "xhello\n");
+        assert_eq!(window.filename(), path.to_str());
+        assert!(window.is_modified());
+
+        // :saveas は常にファイル名を切り替える
+        window.save_file_as(copy.to_str().unwrap(), &config).unwrap();
+        assert_eq!(window.filename(), copy.to_str());
+        assert!(!window.is_modified());
+
+        // 存在しないディレクトリには書き出さず、ファイル名も変更しない
+        let missing = dir.join("missing").join("child.txt");
+        let error = window.save_file_as(missing.to_str().unwrap(), &config).unwrap_err();
+        assert!(error.to_string().contains("Directory does not exist"));
+        assert!(window.write_file(missing.to_str().unwrap(), &config).is_err());
+        assert_eq!(window.filename(), copy.to_str());
+    }
+
+    #[test]
+    fn test_crlf_is_preserved_and_convertible() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("dos.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+
+        let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+        assert_eq!(window.line_ending(), LineEnding::Dos);
+        assert_eq!(window.buffer(), &vec!["one".to_string(), "two".to_string()]);
+        window.save_file(&EditorConfig::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\ntwo\r\n");
+
+        assert!(window.set_line_ending(LineEnding::Unix));
+        assert!(!window.set_line_ending(LineEnding::Unix));
+        window.save_file(&EditorConfig::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_reopen_restores_position_from_given_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("file.txt");
+        let cache = dir.join("positions.json");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let filename = path.to_str().unwrap().to_string();
+
+        let mut window = Window::open(Some(filename.clone()), Some(cache.clone()));
+        *window.cursor_x_mut() = 2;
+        *window.cursor_y_mut() = 1;
+        window.save_file(&EditorConfig::default()).unwrap();
+        let reopened = Window::open(Some(filename.clone()), Some(cache.clone()));
+        assert_eq!((reopened.cursor_x(), reopened.cursor_y()), (2, 1));
+
+        // キャッシュを渡さなければ記録も復元もしない
+        let window = Window::new(Some(filename));
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 0));
+    }
+
+    #[test]
+    fn test_save_refuses_when_changed_on_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\n").unwrap();
+        let config = EditorConfig::default();
+
+        let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+        assert!(!window.changed_on_disk());
+        window.save_file(&config).unwrap();
+
+        // 内容が同じでも更新日時が変われば変更とみなす
+        let bump = |seconds| {
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() + std::time::Duration::from_secs(seconds)).unwrap();
+        };
+        bump(10);
+        assert!(window.changed_on_disk());
+        let error = window.save_file(&config).unwrap_err();
+        assert!(error.to_string().contains("File changed on disk"));
+
+        // :w! は上書きし、その後の :w は通る
+        window.overwrite_file(&config).unwrap();
+        assert!(!window.changed_on_disk());
+        window.save_file(&config).unwrap();
+
+        // 再読み込みでも記録し直す
+        fs::write(&path, "two\n").unwrap();
+        bump(20);
+        assert!(window.changed_on_disk());
+        window.reload_file().unwrap();
+        assert!(!window.changed_on_disk());
+        assert_eq!(window.buffer()[0], "two");
+    }
+
+    #[test]
+    fn test_save_preserves_trailing_newline() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for content in ["one\ntwo\n", "one\ntwo", "one\r\ntwo\r\n", "one\r\ntwo", "", "\n"] {
+            let path = dir.join("file.txt");
+            fs::write(&path, content).unwrap();
+            let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+            window.save_file(&EditorConfig::default()).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+            // 再読み込みした後も同じ
+            window.reload_file().unwrap();
+            window.save_file(&EditorConfig::default()).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        }
+        // 一時ファイルは残らない
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+
+        // 新しいファイルは改行で終わる
+        let path = dir.join("new.txt");
+        let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+        window.insert_char('a', false);
+        window.save_file(&EditorConfig::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn test_block_yank_and_delete_with_wide_characters() {
+        let mut window = window_with(&["abcdef", "日本語", "ab"]);
+        *window.visual_start_mut() = Some((2, 0));
+        *window.cursor_y_mut() = 2;
+        *window.cursor_x_mut() = 1;
+        // 表示カラム 1〜3（"bc"、"本"、"b"）
+        let (top, bottom, left, right) = window.block_selection().unwrap();
+        assert_eq!((top, bottom, left, right), (0, 2, 1, 3));
+        assert_eq!(window.yank_block(top, bottom, left, right), "bc\n本\nb");
+
+        let deleted = window.delete_block(top, bottom, left, right);
+        assert_eq!(deleted, "bc\n本\nb");
+        assert_eq!(window.buffer(), &vec!["adef".to_string(), "日語".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    fn test_blockwise_paste_pads_short_lines() {
+        let mut window = window_with(&["abcd", "a", "abcd"]);
+        *window.cursor_x_mut() = 1;
+        window.paste("XY\nZ\nW", YankKind::Block, false);
+        assert_eq!(
+            window.buffer(),
+            &vec!["abXYcd".to_string(), "a Z".to_string(), "abW cd".to_string()]
+        );
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 0));
+    }
+
+    #[test]
+    fn test_blockwise_paste_on_tab_indented_lines() {
+        // タブは次のタブストップまでの幅として数える（tab_size 4 なら "\tabc" の b は表示カラム 5）
+        let mut window = window_with(&["\tabc", "\tabc"]);
+        *window.cursor_x_mut() = 2;
+        window.paste("X\nY", YankKind::Block, true);
+        assert_eq!(window.buffer(), &vec!["\taXbc".to_string(), "\taYbc".to_string()]);
+        assert_eq!(window.cursor_x(), 2);
+
+        // カーソル下のタブの後ろに貼り付ける
+        *window.cursor_x_mut() = 0;
+        window.paste("Z\nW", YankKind::Block, false);
+        assert_eq!(window.buffer(), &vec!["\tZaXbc".to_string(), "\tWaYbc".to_string()]);
+    }
+
+    #[test]
+    fn test_find_matching_bracket_after_multibyte_text() {
+        let mut window = window_with(&["あ(x)", "😀[1,", "2]"]);
+        *window.cursor_x_mut() = 1;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((3, 0)));
+
+        *window.cursor_x_mut() = 3;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((1, 0)));
+        assert_eq!(window.matching_bracket_pair(), Some([(3, 0), (1, 0)]));
+
+        // 複数行にまたがる対応
+        *window.cursor_y_mut() = 2;
+        *window.cursor_x_mut() = 1;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_find_matching_bracket_skips_nested_and_unmatched() {
+        let mut window = window_with(&[") (a", "(b)", "\")\" c)", "("]);
+        *window.cursor_x_mut() = 2;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((5, 2)));
+
+        *window.cursor_y_mut() = 2;
+        *window.cursor_x_mut() = 5;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((2, 0)));
+
+        // 閉じていない括弧と、対応する開き括弧がない閉じ括弧
+        *window.cursor_y_mut() = 3;
+        *window.cursor_x_mut() = 0;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), None);
+        *window.cursor_y_mut() = 0;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), None);
+    }
+
+    #[test]
+    fn test_find_matching_bracket_ignores_non_bracket_graphemes() {
+        let mut window = window_with(&["あ(x)"]);
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), None);
+        *window.cursor_x_mut() = 10;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), None);
+    }
+
+    #[test]
+    fn test_jump_to_matching_bracket_across_lines() {
+        let mut window = window_with(&["fn f() {", "    g(\")\");", "}", "x (y"]);
+        *window.cursor_x_mut() = 7;
+        assert!(window.jump_to_matching_bracket());
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 2));
+        assert!(window.jump_to_matching_bracket());
+        assert_eq!((window.cursor_x(), window.cursor_y()), (7, 0));
+
+        // 括弧の上でなければ行内の次の括弧の対応へ移動する（文字列中の括弧は飛ばす）
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 0;
+        assert!(window.jump_to_matching_bracket());
+        assert_eq!((window.cursor_x(), window.cursor_y()), (9, 1));
+
+        // 対応が無ければ動かない
+        *window.cursor_y_mut() = 3;
+        *window.cursor_x_mut() = 0;
+        assert!(!window.jump_to_matching_bracket());
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 3));
+    }
+
+    #[test]
+    fn test_find_matching_bracket_skips_strings_and_comments() {
+        let mut window = window_with(&["f(\")\", x) // )", "/* ( */ g()"]);
+        *window.cursor_x_mut() = 1;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((8, 0)));
+
+        // 文字列中の括弧の上では対応を探さない
+        *window.cursor_x_mut() = 3;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), None);
+
+        // ブロックコメント中の ( は後ろ向きの探索でも数えない
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 10;
+        window.find_matching_bracket();
+        assert_eq!(window.matching_bracket(), Some((9, 1)));
+    }
+
+    #[test]
+    fn test_delete_word_forward_stops_at_end_of_line() {
+        let mut window = window_with(&["foo bar baz", "next"]);
+        assert_eq!(window.delete_to_motion(Motion::WordForward, 1), "foo ");
+        assert_eq!(window.buffer()[0], "bar baz");
+
+        // 行末の単語では次の行に進まず行末まで削除する
+        *window.cursor_x_mut() = 4;
+        assert_eq!(window.delete_to_motion(Motion::WordForward, 1), "baz");
+        assert_eq!(window.buffer(), &vec!["bar ".to_string(), "next".to_string()]);
+        assert_eq!(window.cursor_x(), 3);
+    }
+
+    #[test]
+    fn test_delete_to_word_end_and_line_end() {
+        let mut window = window_with(&["let value = 1;", ""]);
+        *window.cursor_x_mut() = 4;
+        assert_eq!(window.delete_to_motion(Motion::WordEnd, 1), "value");
+        assert_eq!(window.buffer()[0], "let  = 1;");
+
+        assert_eq!(window.delete_to_motion(Motion::LineEnd, 1), " = 1;");
+        assert_eq!(window.buffer()[0], "let ");
+        assert_eq!(window.cursor_x(), 3);
+
+        // 空行での d$ は何もしない
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 0;
+        assert_eq!(window.delete_to_motion(Motion::LineEnd, 1), "");
+        assert_eq!(window.buffer()[1], "");
+        // 何も削除しなかった場合は undo 履歴を積まない
+        assert!(window.undo());
+        assert_eq!(window.buffer()[0], "let  = 1;");
+    }
+
+    #[test]
+    fn test_delete_to_line_end_with_count() {
+        let mut window = window_with(&["ab日本", "next", "last"]);
+        *window.cursor_x_mut() = 2;
+        // 2D は行末までと次の行全体を削除する
+        assert_eq!(window.delete_to_motion(Motion::LineEnd, 2), "日本\nnext");
+        assert_eq!(window.buffer(), &vec!["ab".to_string(), "last".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 0));
+
+        // 行数を超える回数は最終行までにとどめる
+        assert_eq!(window.delete_to_motion(Motion::LineEnd, 5), "b\nlast");
+        assert_eq!(window.buffer(), &vec!["a".to_string()]);
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &vec!["ab".to_string(), "last".to_string()]);
+    }
+
+    #[test]
+    fn test_insert_text_keeps_pasted_indentation() {
+        let mut window = window_with(&["fn main() {}"]);
+        *window.cursor_x_mut() = 11;
+        window.insert_text("\r\n    if x {\r\n        y();\r\n    }\r\n");
+        assert_eq!(
+            window.buffer(),
+            &vec![
+                "fn main() {".to_string(),
+                "    if x {".to_string(),
+                "        y();".to_string(),
+                "    }".to_string(),
+                "}".to_string(),
+            ]
+        );
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 4));
+    }
+
+    #[test]
+    fn test_change_word_stops_at_word_end() {
+        let mut window = window_with(&["foo bar", "    let x = 1;"]);
+        window.start_insert_mode();
+        assert_eq!(window.change_to_motion(Motion::WordForward, 1), "foo");
+        assert_eq!(window.buffer()[0], " bar");
+        assert_eq!(window.cursor_x(), 0);
+        window.end_insert_mode();
+        assert!(window.undo());
+        assert_eq!(window.buffer()[0], "foo bar");
+
+        // 単語の最後の文字では、その1文字だけを変更する
+        *window.cursor_x_mut() = 2;
+        assert_eq!(window.change_to_motion(Motion::WordForward, 1), "o");
+        assert_eq!(window.buffer()[0], "fo bar");
+    }
+
+    #[test]
+    fn test_change_word_on_whitespace_behaves_like_change_to_word_end() {
+        // Vim と同じく、空白上の cw は ce と同様に次の単語の末尾までを変更する
+        let mut window = window_with(&["foo  bar baz"]);
+        *window.cursor_x_mut() = 3;
+        assert_eq!(window.change_to_motion(Motion::WordForward, 1), "  bar");
+        assert_eq!(window.buffer()[0], "foo baz");
+    }
+
+    #[test]
+    fn test_change_to_line_end_and_change_lines() {
+        let mut window = window_with(&["    let x = 1;", "    let y = 2;", "end"]);
+        *window.cursor_x_mut() = 8;
+        assert_eq!(window.change_to_motion(Motion::LineEnd, 1), "x = 1;");
+        assert_eq!(window.buffer()[0], "    let ");
+        assert_eq!(window.cursor_x(), 8);
+
+        // cc はインデントを残して行の内容を置き換える
+        let changed = window.change_lines(2);
+        assert_eq!(changed, "    let \n    let y = 2;\n");
+        assert_eq!(window.buffer(), &vec!["    ".to_string(), "end".to_string()]);
+        assert_eq!(window.cursor_x(), 4);
+    }
+
+    #[test]
+    fn test_set_view_clamps_to_shared_buffer() {
+        let mut window = window_with(&["first", "second line", "third"]);
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 8;
+        let view = window.view();
+
+        // 別のペインで行が削除された後に表示位置を戻す
+        window.delete_lines(1, 2);
+        window.set_view(view);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (5, 0));
+        assert_eq!(window.scroll_y(), 0);
+    }
+
+    #[test]
+    fn test_replace_char_next_to_emoji() {
+        let mut window = window_with(&["a😀b", ""]);
+        *window.cursor_x_mut() = 1;
+        assert!(window.replace_char('x'));
+        assert_eq!(window.buffer()[0], "axb");
+
+        *window.cursor_x_mut() = 2;
+        assert!(window.replace_char('あ'));
+        assert_eq!(window.buffer()[0], "axあ");
+        assert_eq!(window.cursor_x(), 2);
+
+        assert!(window.undo());
+        assert_eq!(window.buffer()[0], "axb");
+
+        // 空行では何もしない
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 0;
+        assert!(!window.replace_char('x'));
+        assert_eq!(window.buffer()[1], "");
+    }
+
+    #[test]
+    fn test_replace_char_with_newline_splits_line() {
+        let mut window = window_with(&["foo bar"]);
+        *window.cursor_x_mut() = 3;
+        assert!(window.replace_char('\n'));
+        assert_eq!(window.buffer(), &vec!["foo".to_string(), "bar".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 1));
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &vec!["foo bar".to_string()]);
+    }
+
+    #[test]
+    fn test_open_new_line_above_and_below() {
+        let mut window = window_with(&["fn main() {", "    body();", "}"]);
+        *window.cursor_y_mut() = 1;
+        window.open_new_line(true, true);
+        assert_eq!(window.buffer()[1], "    ");
+        assert_eq!(window.buffer()[2], "    body();");
+        assert_eq!((window.cursor_x(), window.cursor_y()), (4, 1));
+
+        // auto_indent が無効なら行頭から始める
+        *window.cursor_y_mut() = 2;
+        window.open_new_line(false, false);
+        assert_eq!(window.buffer()[3], "");
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 3));
+        assert_eq!(window.buffer().len(), 5);
+    }
+
+    #[test]
+    fn test_detect_indent_style() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            IndentStyle::detect(&lines(&["fn main() {", "  if x {", "    y();", "  }", "}"])),
+            Some(IndentStyle::Spaces(2))
+        );
+        assert_eq!(
+            IndentStyle::detect(&lines(&["def f():", "    if x:", "        return 1", "    return 2"])),
+            Some(IndentStyle::Spaces(4))
+        );
+        assert_eq!(
+            IndentStyle::detect(&lines(&["func main() {", "\tif x {", "\t\ty()", "\t}", "}"])),
+            Some(IndentStyle::Tabs)
+        );
+        assert_eq!(IndentStyle::detect(&lines(&["no", "indentation", ""])), None);
+        assert_eq!(IndentStyle::detect(&lines(&["a", "            b", "c", "            d"])), None);
+    }
+
+    #[test]
+    fn test_insert_newline_after_open_brace() {
+        let mut window = window_with(&["    if x {}"]);
+        *window.cursor_x_mut() = 10;
+        window.insert_newline("    ", true);
+        assert_eq!(window.buffer(), &vec!["    if x {".to_string(), "        }".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (8, 1));
+
+        // 閉じ括弧の直前で改行すると一段浅くなる
+        window.insert_newline("    ", true);
+        assert_eq!(window.buffer()[2], "    }");
+        assert_eq!((window.cursor_x(), window.cursor_y()), (4, 2));
+    }
+
+    #[test]
+    fn test_insert_newline_without_auto_indent() {
+        let mut window = window_with(&["    if x {}"]);
+        *window.cursor_x_mut() = 10;
+        window.insert_newline("    ", false);
+        assert_eq!(window.buffer(), &vec!["    if x {".to_string(), "}".to_string()]);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 1));
+    }
+
+    #[test]
+    fn test_insert_tab_aligns_to_tab_stop() {
+        let mut window = window_with(&["ab", "\tx"]);
+        *window.cursor_x_mut() = 2;
+        window.insert_tab("    ");
+        assert_eq!(window.buffer()[0], "ab  ");
+        assert_eq!(window.cursor_x(), 4);
+        window.insert_tab("    ");
+        assert_eq!(window.buffer()[0], "ab      ");
+
+        // タブストップはインデント幅ではなく tab_size の倍数。既存のタブも tab_size 幅として数える
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 2;
+        window.set_tab_size(8);
+        window.insert_tab("    ");
+        assert_eq!(window.buffer()[1], "\tx       ");
+
+        // expand_tab が無効ならタブをそのまま挿入する
+        *window.cursor_x_mut() = 0;
+        window.insert_tab("\t");
+        assert_eq!(window.buffer()[1], "\t\tx       ");
+        assert_eq!(window.cursor_x(), 1);
+    }
+
+    #[test]
+    fn test_block_insert_and_append() {
+        let mut window = window_with(&["abcd", "a", "日本語", "abcd"]);
+        // I: 表示カラム 1 に挿入する（全角文字の途中ならその後ろ）
+        window.start_block_insert(0, 3, 1, false);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 0));
+        window.insert_text("--");
+        window.end_insert_mode();
+        assert_eq!(window.buffer(), &vec!["a--bcd".to_string(), "a--".to_string(), "日--本語".to_string(), "a--bcd".to_string()]);
+
+        // A: 短い行は空白で埋めてから挿入する
+        window.start_block_insert(1, 2, 4, true);
+        assert_eq!(window.buffer()[1], "a-- ");
+        window.insert_text("|");
+        window.end_insert_mode();
+        assert_eq!(window.buffer()[1], "a-- |");
+        assert_eq!(window.buffer()[2], "日--|本語");
+
+        // 1回の undo で矩形挿入全体を元に戻す
+        assert!(window.undo());
+        assert_eq!(window.buffer()[1], "a--");
+        assert_eq!(window.buffer()[2], "日--本語");
+    }
+
+    #[test]
+    fn test_block_insert_and_append_on_tab_indented_lines() {
+        // タブは次のタブストップまでの幅として数える（tab_size 4 なら "\tabc" の b は表示カラム 5）
+        let mut window = window_with(&["\tabc", "\tabc", "\ta"]);
+        window.start_block_insert(0, 2, 5, false);
+        assert_eq!(window.cursor_x(), 2);
+        window.insert_text("X");
+        window.end_insert_mode();
+        assert_eq!(window.buffer(), &vec!["\taXbc".to_string(), "\taXbc".to_string(), "\taX".to_string()]);
+
+        window.start_block_insert(0, 2, 8, true);
+        window.insert_text("|");
+        window.end_insert_mode();
+        assert_eq!(window.buffer(), &vec!["\taXbc|".to_string(), "\taXbc|".to_string(), "\taX  |".to_string()]);
+    }
+
+    #[test]
+    fn test_auto_pairs() {
+        let mut window = window_with(&[""]);
+        for c in "f(\"x".chars() {
+            window.insert_char(c, true);
+        }
+        assert_eq!(window.buffer()[0], "f(\"x\")");
+        // 次の文字と同じ閉じ文字は飛び越す
+        window.insert_char('"', true);
+        window.insert_char(')', true);
+        assert_eq!(window.buffer()[0], "f(\"x\")");
+        assert_eq!(window.cursor_x(), 6);
+
+        // 英数字の直後の引用符は対にしない
+        for c in "a's".chars() {
+            window.insert_char(c, true);
+        }
+        assert_eq!(window.buffer()[0], "f(\"x\")a's");
+
+        // 空の括弧の間での Backspace は両方を削除する
+        window.insert_char('[', true);
+        assert_eq!(window.buffer()[0], "f(\"x\")a's[]");
+        window.backspace(true);
+        assert_eq!(window.buffer()[0], "f(\"x\")a's");
+
+        // auto_pairs が無効なら入力した文字だけを挿入する
+        window.insert_char('{', false);
+        assert_eq!(window.buffer()[0], "f(\"x\")a's{");
+    }
+
+    #[test]
+    fn test_completion_from_buffer_words() {
+        let mut window = window_with(&["alpha alps", "fn al", "alphabet al_pha"]);
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 5;
+        assert_eq!(window.completion_prefix_start(), 3);
+        // カーソルに近い行の単語が先に並ぶ
+        assert_eq!(window.buffer_words_with_prefix("al"), vec!["alpha", "alps", "alphabet", "al_pha"]);
+
+        window.replace_word_with_completion("alphabet");
+        assert_eq!(window.buffer()[1], "fn alphabet");
+        assert_eq!(window.cursor_x(), 11);
+    }
+
+    #[test]
+    fn test_typing_prefix_offers_buffer_words() {
+        let mut window = window_with(&["    println!(\"{}\", x);", "    print_all();", ""]);
+        *window.cursor_y_mut() = 2;
+        for c in "    prin".chars() {
+            window.insert_char(c, false);
+        }
+        let (y, start, prefix) = window.completion_prefix();
+        assert_eq!((y, start, prefix.as_str()), (2, 4, "prin"));
+        assert_eq!(window.buffer_words_with_prefix(&prefix), vec!["print_all", "println"]);
+    }
+
+    #[test]
+    fn test_delete_line_selection() {
+        let mut window = window_with(&["zero", "one", "two", "three", "four"]);
+        assert_eq!(window.line_selection(), None);
+        // カラムに関係なく行全体を選択する（選択の向きも問わない）
+        *window.visual_start_mut() = Some((4, 3));
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 2;
+        assert_eq!(window.line_selection(), Some((1, 3)));
+
+        assert_eq!(window.delete_line_selection().as_deref(), Some("one\ntwo\nthree\n"));
+        assert_eq!(window.buffer(), &vec!["zero".to_string(), "four".to_string()]);
+        assert_eq!(window.visual_start(), None);
+        assert_eq!(window.cursor_y(), 1);
+        assert!(window.undo());
+        assert_eq!(window.buffer().len(), 5);
+    }
+
+    #[test]
+    fn test_shift_lines_keeps_cursor_on_same_character() {
+        let mut window = window_with(&["  ab", "", "cd", "      ef"]);
+        *window.cursor_x_mut() = 3;
+        window.shift_lines(0, 2, "    ", true);
+        // 空行にはインデントを足さない
+        assert_eq!(window.buffer(), &["      ab", "", "    cd", "      ef"]);
+        assert_eq!(window.cursor_x(), 7);
+
+        // インデント幅より浅い行は、あるだけの空白を取り除く
+        window.shift_lines(0, 3, "    ", false);
+        window.shift_lines(0, 3, "    ", false);
+        assert_eq!(window.buffer(), &["ab", "", "cd", "ef"]);
+        assert_eq!(window.cursor_x(), 1);
+
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &["  ab", "", "cd", "  ef"]);
+    }
+
+    #[test]
+    fn test_join_lines_with_and_without_space() {
+        let mut window = window_with(&["foo", "    bar", "", "baz", "qux"]);
+        window.set_mark('a');
+        *window.cursor_y_mut() = 3;
+        window.set_mark('b');
+        *window.cursor_y_mut() = 0;
+
+        assert!(window.join_lines(1, true));
+        assert_eq!(window.buffer(), &["foo bar", "", "baz", "qux"]);
+        assert_eq!(window.cursor_x(), 3);
+        // 空行は空白を足さずにつなぐ
+        assert!(window.join_lines(3, true));
+        assert_eq!(window.buffer(), &["foo bar baz", "qux"]);
+        assert!(window.jump_to_mark('b'));
+        assert_eq!((window.cursor_x(), window.cursor_y()), (8, 0));
+
+        // gJ は空白を足さず、先頭の空白も残す
+        let mut window = window_with(&["let x =", "  42;"]);
+        assert!(window.join_lines(1, false));
+        assert_eq!(window.buffer(), &["let x =  42;"]);
+        assert_eq!(window.cursor_x(), 7);
+        assert!(!window.join_lines(1, false));
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &["let x =", "  42;"]);
+    }
+
+    #[test]
+    fn test_find_char_and_repeat() {
+        let mut window = window_with(&["a,b,日x,c"]);
+        let fx = CharSearch { target: ',', forward: true, till: false };
+        assert!(window.find_char(fx, 1, false));
+        assert_eq!(window.cursor_x(), 1);
+        // ; で同じ向きに繰り返し、全角文字も1文字として数える
+        assert!(window.find_char(fx, 1, true));
+        assert_eq!(window.cursor_x(), 3);
+        assert!(window.find_char(fx, 1, true));
+        assert_eq!(window.cursor_x(), 6);
+        assert!(!window.find_char(fx, 1, true));
+        assert_eq!(window.cursor_x(), 6);
+        // , は逆向き、回数指定は n 個目
+        let reverse = CharSearch { forward: false, ..fx };
+        assert!(window.find_char(reverse, 2, true));
+        assert_eq!(window.cursor_x(), 1);
+
+        // t は手前で止まり、; で繰り返しても隣で止まり続けない
+        *window.cursor_x_mut() = 0;
+        let tc = CharSearch { target: ',', forward: true, till: true };
+        assert!(window.find_char(tc, 1, false));
+        assert_eq!(window.cursor_x(), 0);
+        assert!(window.find_char(tc, 1, true));
+        assert_eq!(window.cursor_x(), 2);
+        let big_t = CharSearch { target: 'a', forward: false, till: true };
+        assert!(window.find_char(big_t, 1, false));
+        assert_eq!(window.cursor_x(), 1);
+    }
+
+    #[test]
+    fn test_toggle_comment_uses_language_marker() {
+        let mut window = window_with(&["fn main() {", "    let x = 1;", "", "    // done", "}"]);
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 8;
+        // 1行でもコメントでなければ全行を最も浅いインデントでコメントにする（空行はそのまま）
+        assert!(window.toggle_comment(1, 3));
+        assert_eq!(window.buffer()[1..4], ["    // let x = 1;", "", "    // // done"]);
+        assert_eq!(window.cursor_x(), 11);
+        assert!(window.toggle_comment(1, 3));
+        assert_eq!(window.buffer()[1..4], ["    let x = 1;", "", "    // done"]);
+        assert_eq!(window.cursor_x(), 8);
+        assert!(window.toggle_comment(3, 3));
+        assert_eq!(window.buffer()[3], "    done");
+        assert!(!window.toggle_comment(2, 2));
+
+        // 1回の undo で元に戻せる
+        assert!(window.undo());
+        assert_eq!(window.buffer()[3], "    // done");
+
+        let mut window = Window::new(None);
+        window.filename = Some("script.py".to_string());
+        *window.buffer_mut() = vec!["x = 1".to_string(), "  y = 2".to_string()];
+        assert!(window.toggle_comment(0, 1));
+        assert_eq!(window.buffer(), &["# x = 1", "#   y = 2"]);
+    }
+
+    #[test]
+    fn test_tabs_expand_to_tab_stops() {
+        let mut window = window_with(&["\tab\tc", "    x"]);
+        window.set_tab_size(4);
+        assert_eq!(window.display_x(1, 0), 4);
+        assert_eq!(window.display_x(4, 0), 8);
+        window.set_tab_size(8);
+        assert_eq!(window.display_x(4, 0), 16);
+
+        // Shift+Tab はタブ1つか1段分の空白を取り除く
+        window.dedent_line("    ");
+        assert_eq!(window.buffer()[0], "ab\tc");
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 4;
+        window.dedent_line("  ");
+        assert_eq!(window.buffer()[1], "  x");
+        assert_eq!(window.cursor_x(), 2);
+    }
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut window = window_with(&["zero", "one", "two", "three"]);
+        *window.cursor_y_mut() = 2;
+        *window.cursor_x_mut() = 1;
+        window.set_mark('a');
+        *window.cursor_y_mut() = 3;
+        window.set_mark('b');
+
+        // 上に行を挿入すると一緒にずれる
+        *window.cursor_y_mut() = 0;
+        window.open_new_line(false, false);
+        window.end_insert_mode();
+        assert!(window.jump_to_mark('a'));
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 3));
+        assert_eq!(window.buffer()[3], "two");
+
+        // 上の行を削除すると詰まり、マークした行を削除するとマークは消える
+        window.delete_lines(0, 2);
+        assert!(window.jump_to_mark('a'));
+        assert_eq!(window.buffer()[window.cursor_y()], "two");
+        window.delete_lines(window.cursor_y(), 1);
+        assert!(!window.jump_to_mark('a'));
+
+        // 範囲外を指すマークは最終行に収める
+        assert!(window.jump_to_mark('b'));
+        assert_eq!(window.buffer()[window.cursor_y()], "three");
+        *window.buffer_mut() = vec!["x".to_string()];
+        assert!(window.jump_to_mark('b'));
+        assert_eq!((window.cursor_x(), window.cursor_y()), (0, 0));
+        assert!(!window.jump_to_mark('z'));
+    }
+
+    #[test]
+    fn test_marks_follow_multi_line_changes() {
+        let mut window = window_with(&["a", "b", "c", "d", "mark", "e", "f"]);
+        *window.cursor_y_mut() = 4;
+        window.set_mark('a');
+
+        // 3cc で上の2行が消えた分だけ詰まる
+        *window.cursor_y_mut() = 0;
+        window.change_lines(3);
+        assert!(window.jump_to_mark('a'));
+        assert_eq!((window.cursor_y(), window.buffer()[window.cursor_y()].as_str()), (2, "mark"));
+
+        // 2D でも同じ
+        *window.cursor_y_mut() = 0;
+        window.remove_to_line_end(2);
+        assert!(window.jump_to_mark('a'));
+        assert_eq!((window.cursor_y(), window.buffer()[window.cursor_y()].as_str()), (1, "mark"));
+    }
+
+    #[test]
+    fn test_delete_chars_with_count() {
+        let mut window = window_with(&["aあbc"]);
+        *window.cursor_x_mut() = 1;
+        assert_eq!(window.delete_chars(2), "あb");
+        assert_eq!(window.buffer()[0], "ac");
+        assert_eq!(window.cursor_x(), 1);
+
+        // 行末を越える回数は行末まで削除し、カーソルは最後の文字に戻る
+        assert_eq!(window.delete_chars(5), "c");
+        assert_eq!((window.buffer()[0].as_str(), window.cursor_x()), ("a", 0));
+        assert_eq!(window.delete_chars(1), "a");
+        assert_eq!(window.delete_chars(1), "");
+
+        window.undo();
+        assert_eq!(window.buffer()[0], "a");
+    }
+
+    #[test]
+    fn test_jump_targets_nearest_first() {
+        let mut window = window_with(&["a.b.", "..", "c.", "d."]);
+        *window.cursor_x_mut() = 1;
+        *window.cursor_y_mut() = 1;
+        // 表示中の3行だけを対象にし、カーソル位置は含めない
+        assert_eq!(window.jump_targets('.', 3), vec![(0, 1), (1, 0), (1, 2), (3, 0)]);
+        assert!(window.jump_targets('x', 3).is_empty());
+
+        // 折り返し表示では画面からはみ出した表示行の一致を含めない
+        let mut window = window_with(&["a.b.c.d.e.f.", "g."]);
+        window.set_word_wrap(true);
+        window.scroll_to_cursor(2, 4, 0);
+        assert_eq!(window.view_rows(), vec![(0, 0, 4), (0, 4, 8)]);
+        assert_eq!(window.jump_targets('.', 2), vec![(1, 0), (3, 0), (5, 0), (7, 0)]);
+    }
+
+    #[test]
+    fn test_word_wrap_rows_and_cursor_position() {
+        let mut window = window_with(&["hello world foo", "abcdefghijkl", "日本語の文", "end"]);
+        window.set_word_wrap(true);
+        window.scroll_to_cursor(4, 6, 0);
+        // 空白の直後で折り返し、空白のない行は幅で折り返す（全角文字は幅に収まる位置で折る）
+        assert_eq!(window.wrap_starts(0), vec![0, 6, 12]);
+        assert_eq!(window.wrap_starts(1), vec![0, 6]);
+        assert_eq!(window.wrap_starts(2), vec![0, 3]);
+        assert_eq!(window.view_rows(), vec![(0, 0, 6), (0, 6, 12), (0, 12, 15), (1, 0, 6)]);
+
+        *window.cursor_x_mut() = 13;
+        assert_eq!(window.view_position(window.cursor_x(), window.cursor_y()), Some((2, 1)));
+
+        // 表示行単位でスクロールし、カーソルは常に画面内に描かれる
+        *window.cursor_y_mut() = 2;
+        *window.cursor_x_mut() = 4;
+        window.scroll_to_cursor(4, 6, 0);
+        assert_eq!((window.scroll_y(), window.view_rows()[0]), (1, (1, 0, 6)));
+        assert_eq!(window.view_position(4, 2), Some((3, 2)));
+        assert_eq!(window.view_position(0, 0), None);
+
+        *window.cursor_y_mut() = 0;
+        *window.cursor_x_mut() = 0;
+        window.scroll_to_cursor(4, 6, 0);
+        assert_eq!(window.view_position(0, 0), Some((0, 0)));
+
+        // 幅いっぱいの行の行末にあるカーソルは右端に収める
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 12;
+        window.scroll_to_cursor(4, 6, 0);
+        assert_eq!(window.view_position(12, 1), Some((3, 5)));
+    }
+
+    #[test]
+    fn test_modified_flag_cleared_on_save_and_reload() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("notes.txt");
+        fs::write(&path, "one\n").unwrap();
+
+        let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+        assert!(!window.is_modified());
+        window.insert_char('x', false);
+        assert!(window.is_modified());
+        window.save_file(&EditorConfig::default()).unwrap();
+        assert!(!window.is_modified());
+
+        window.delete_chars(1);
+        assert!(window.is_modified());
+        window.reload_file().unwrap();
+        assert!(!window.is_modified());
+        assert_eq!(window.buffer()[0], "xone");
+    }
+
+    #[test]
+    fn test_modified_flag_follows_undo_to_saved_state() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("notes.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut window = Window::new(Some(path.to_str().unwrap().to_string()));
+        window.cursor_x = 0;
+        window.cursor_y = 0;
+        window.delete_chars(1);
+        assert!(window.is_modified());
+        window.undo();
+        assert!(!window.is_modified());
+        window.redo();
+        assert!(window.is_modified());
+
+        // 保存した後は、保存時の内容に戻ったときだけ未変更になる
+        window.save_file(&EditorConfig::default()).unwrap();
+        window.undo();
+        assert!(window.is_modified());
+        window.redo();
+        assert!(!window.is_modified());
+    }
+
+    #[test]
+    fn test_word_count_follows_buffer_version() {
+        let mut window = window_with(&["The quick  brown", "", "\tfox jumps"]);
+        assert_eq!(window.word_count(), 5);
+
+        // 変更するたびに数え直す
+        *window.cursor_x_mut() = 16;
+        window.insert_char(' ', false);
+        window.insert_char('a', false);
+        assert_eq!(window.word_count(), 6);
+        window.delete_lines(2, 1);
+        assert_eq!(window.word_count(), 4);
+    }
+
+    #[test]
+    fn test_misspellings_follow_buffer_version() {
+        let dictionary = Dictionary::from_words(["the", "cat"]);
+        let mut window = window_with(&["the cat"]);
+        assert!(window.misspellings(&dictionary).is_empty());
+
+        // 前回の結果は変更するまで使い、変更したら調べ直す
+        *window.cursor_x_mut() = 7;
+        window.insert_char(' ', false);
+        window.insert_char('x', false);
+        window.insert_char('y', false);
+        assert_eq!(window.misspellings(&dictionary), vec![(0, 8, 10)]);
+        assert_eq!(window.misspellings(&Dictionary::default()).len(), 1);
+        window.clear_misspellings_cache();
+        assert!(window.misspellings(&Dictionary::default()).is_empty());
+    }
+
+    #[test]
+    fn test_display_row_motion_across_wide_characters() {
+        // 幅5で折り返すと「本」は4〜5カラムに収まらないため次の表示行に送られる
+        let mut window = window_with(&["aa日本語x", "bb", "日本語"]);
+        window.set_word_wrap(true);
+        window.scroll_to_cursor(10, 5, 0);
+        assert_eq!(window.wrap_starts(0), vec![0, 3]);
+        assert_eq!(window.wrap_position(3, 0), (1, 0));
+        assert_eq!(window.position_in_wrap_row(0, 1, 1), 3);
+
+        *window.cursor_x_mut() = 1;
+        window.move_display_rows(true, 1);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (3, 0));
+        // 回数指定では移動前の表示カラムを保つ
+        *window.cursor_x_mut() = 1;
+        window.move_display_rows(true, 2);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 1));
+        window.move_display_rows(false, 2);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 0));
+
+        // 全角文字の途中のカラムからはその文字に移る
+        *window.cursor_x_mut() = 2;
+        window.move_display_rows(true, 1);
+        assert_eq!(window.cursor_x(), 4);
+        window.move_display_rows(true, 2);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (1, 2));
+        // 最後の行も「語」が折り返される。短い表示行では行末の文字に収め、最後の表示行で止まる
+        window.move_display_rows(true, 5);
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 2));
+        assert_eq!(window.view_position(2, 2), Some((4, 0)));
+    }
+
+    #[test]
+    fn test_undo_redo_line_diffs_restore_cursor() {
+        let mut window = window_with(&["one", "two", "three"]);
+        *window.cursor_y_mut() = 1;
+        window.save_state();
+        window.delete_lines(1, 1);
+        *window.cursor_y_mut() = 1;
+        *window.cursor_x_mut() = 2;
+        window.replace_char('X');
+        assert_eq!(window.buffer(), &vec!["one".to_string(), "thXee".to_string()]);
+
+        assert!(window.undo());
+        assert_eq!(window.buffer()[1], "three");
+        assert_eq!((window.cursor_x(), window.cursor_y()), (2, 1));
+        *window.cursor_x_mut() = 0;
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &vec!["one".to_string(), "two".to_string(), "three".to_string()]);
+        assert!(!window.undo());
+
+        // redo は undo する直前のカーソル位置に戻す
+        assert!(window.redo());
+        assert_eq!((window.buffer().len(), window.cursor_x(), window.cursor_y()), (2, 0, 1));
+        assert!(window.redo());
+        assert_eq!(window.buffer()[1], "thXee");
+        assert!(!window.redo());
+    }
+
+    #[test]
+    fn test_undo_entry_holds_only_touched_lines() {
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {}", i)).collect();
+        let mut window = Window::new(None);
+        *window.buffer_mut() = lines.clone();
+        *window.cursor_y_mut() = 500;
+        window.replace_char('X');
+        *window.cursor_x_mut() = 4;
+        window.start_insert_mode();
+        window.insert_newline("", false);
+        window.end_insert_mode();
+
+        // 変更のあった行だけを変更前後の内容で持つ
+        let entry = window.undo_stack.back().unwrap();
+        assert_eq!((entry.start, entry.before.len(), entry.after.len()), (500, 1, 2));
+        assert!(window.undo());
+        let entry = window.undo_stack.back().unwrap();
+        assert_eq!((entry.start, entry.before.clone(), entry.after.clone()), (500, vec!["line 500".to_string()], vec!["Xine 500".to_string()]));
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &lines);
+    }
+
+    #[test]
+    fn test_insert_session_is_one_undo_step_and_levels_are_capped() {
+        let mut window = window_with(&["a"]);
+        window.start_insert_mode();
+        window.open_new_line(false, false);
+        window.insert_char('b', false);
+        window.end_insert_mode();
+        assert_eq!(window.buffer(), &vec!["a".to_string(), "b".to_string()]);
+        assert!(window.undo());
+        assert_eq!(window.buffer(), &vec!["a".to_string()]);
+        assert!(!window.undo());
+
+        window.set_undo_levels(2);
+        for _ in 0..3 {
+            window.replace_char('z');
+        }
+        assert!(window.undo());
+        assert!(window.undo());
+        assert!(!window.undo());
+    }
+
+    #[test]
+    fn test_undo_file_round_trip_and_hash_check() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("notes.txt");
+        let undo_path = dir.join("undo").join("notes.txt");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let filename = path.to_str().unwrap().to_string();
+
+        let mut window = Window::new(Some(filename.clone()));
+        window.cursor_x = 0;
+        window.cursor_y = 1;
+        window.delete_chars(1);
+        window.save_file(&EditorConfig::default()).unwrap();
+        window.write_undo_file(&undo_path).unwrap();
+
+        // 開き直しても u で保存前の内容に戻せる
+        let mut reopened = Window::new(Some(filename.clone()));
+        assert!(reopened.read_undo_file(&undo_path));
+        assert!(reopened.undo());
+        assert_eq!(reopened.buffer(), &["one", "two"]);
+        assert_eq!((reopened.cursor_x(), reopened.cursor_y()), (0, 1));
+
+        // 書き出した後にファイルが変わっていれば履歴は使わない
+        fs::write(&path, "changed\n").unwrap();
+        let mut changed = Window::new(Some(filename));
+        assert!(!changed.read_undo_file(&undo_path));
+        assert!(!changed.undo());
+
+        fs::write(&undo_path, "{broken").unwrap();
+        assert!(!reopened.read_undo_file(&undo_path));
+    }
 }
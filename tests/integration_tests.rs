@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 use vim_editor::config::Theme;
 use vim_editor::syntax::{highlight_syntax_with_state, count_leading_spaces, create_indent_spans, BracketState};
+use vim_editor::utils::{complete_path, stable_hash};
 
 #[test]
 fn test_syntax_highlighting_integration() {
@@ -167,4 +168,33 @@ fn test_complex_rust_code() {
             _ => {}
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_complete_path() {
+    let temp = tempfile::tempdir().unwrap();
+    let dir = temp.path();
+    std::fs::create_dir_all(dir.join("src/event")).unwrap();
+    for file in ["src/main.rs", "src/mod.rs", "Cargo.toml", ".hidden"] {
+        std::fs::write(dir.join(file), "").unwrap();
+    }
+
+    assert_eq!(complete_path(dir, ""), vec!["Cargo.toml", "src/"]);
+    assert_eq!(complete_path(dir, "s"), vec!["src/"]);
+    // ディレクトリの中へ続けて補完できる
+    assert_eq!(complete_path(dir, "src/m"), vec!["src/main.rs", "src/mod.rs"]);
+    assert_eq!(complete_path(dir, "src/"), vec!["src/event/", "src/main.rs", "src/mod.rs"]);
+    assert_eq!(complete_path(dir, "."), vec![".hidden"]);
+    assert!(complete_path(dir, "missing/").is_empty());
+    // 絶対パスはそのまま補完する
+    let absolute = format!("{}/Ca", dir.display());
+    assert_eq!(complete_path(dir, &absolute), vec![format!("{}/Cargo.toml", dir.display())]);
+}
+
+#[test]
+fn test_stable_hash_is_fnv1a_of_lines() {
+    // undo 履歴ファイルに書き出す値なので、FNV-1a の既知の値と一致し続けること
+    assert_eq!(stable_hash([]), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(stable_hash(["a", "b"]), 0x78ed_6781_f136_a14e);
+    assert_ne!(stable_hash(["ab"]), stable_hash(["a", "b"]));
+}